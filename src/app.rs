@@ -1,5 +1,282 @@
-use crate::host::{Host, HostStatus};
+use crate::batch::{self, BatchResults};
+use crate::clipboard;
+use crate::config::Config;
+use crate::favorites;
+use crate::form::HostForm;
+use crate::groups::{self, GroupAction, GroupPanel};
+use crate::health;
+use crate::history::{self, HistoryEntry};
+use crate::host::{self, Host, HostSource, HostStatus};
+use crate::state::AppState;
+use crate::tunnel::{self, TunnelInfo};
+use crate::watch::Reload;
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fmt;
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize};
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const DOUBLE_CLICK: Duration = Duration::from_millis(400);
+/// How long after one `g` a second one still counts as `gg`.
+const KEY_SEQUENCE: Duration = Duration::from_millis(500);
+/// How long a routine toast stays in the header.
+pub const TOAST: Duration = Duration::from_secs(2);
+/// Long enough to read a path or an error.
+pub const TOAST_LONG: Duration = Duration::from_secs(6);
+/// Toasts kept waiting at once; older ones are dropped.
+const TOAST_LIMIT: usize = 5;
+const RECENT_LIMIT: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterKind {
+    Substring,
+    Fuzzy,
+    Regex,
+}
+
+/// How much of each host the table shows; toggled with `z`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Density {
+    Normal,
+    /// Status, alias, `hostname:port` and check result only.
+    Compact,
+    /// Adds jump host, key file, last connection and tags, sized to fit.
+    Wide,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToastLevel {
+    Info,
+    Success,
+    Warning,
+    Error,
+}
+
+/// A message shown in the header until `expires_at`.
+pub struct Toast {
+    pub message: String,
+    pub level: ToastLevel,
+    pub expires_at: Instant,
+}
+
+/// A column of the host table after the star and status icon; tables
+/// draw them in declaration order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnId {
+    Alias,
+    Host,
+    User,
+    Port,
+    Group,
+    Status,
+    Rtt,
+    Trend,
+    JumpHost,
+    KeyFile,
+    LastConn,
+    Tags,
+}
+
+impl ColumnId {
+    pub const ALL: [ColumnId; 12] = [
+        ColumnId::Alias,
+        ColumnId::Host,
+        ColumnId::User,
+        ColumnId::Port,
+        ColumnId::Group,
+        ColumnId::Status,
+        ColumnId::Rtt,
+        ColumnId::Trend,
+        ColumnId::JumpHost,
+        ColumnId::KeyFile,
+        ColumnId::LastConn,
+        ColumnId::Tags,
+    ];
+
+    /// Shown until the user picks their own.
+    pub const DEFAULT: [ColumnId; 7] = [
+        ColumnId::Alias,
+        ColumnId::Host,
+        ColumnId::User,
+        ColumnId::Port,
+        ColumnId::Group,
+        ColumnId::Status,
+        ColumnId::Rtt,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ColumnId::Alias => "Alias",
+            ColumnId::Host => "Host",
+            ColumnId::User => "User",
+            ColumnId::Port => "Port",
+            ColumnId::Group => "Group",
+            ColumnId::Status => "Status",
+            ColumnId::Rtt => "RTT",
+            ColumnId::Trend => "Trend",
+            ColumnId::JumpHost => "JumpHost",
+            ColumnId::KeyFile => "KeyFile",
+            ColumnId::LastConn => "LastConn",
+            ColumnId::Tags => "Tags",
+        }
+    }
+
+    /// The sort its header shows an arrow for, if any.
+    pub fn sort_field(self) -> Option<SortField> {
+        match self {
+            ColumnId::Alias => Some(SortField::Alias),
+            ColumnId::Host => Some(SortField::Hostname),
+            ColumnId::User => Some(SortField::User),
+            ColumnId::Port => Some(SortField::Port),
+            ColumnId::Group => Some(SortField::Group),
+            ColumnId::Status => Some(SortField::Status),
+            ColumnId::Rtt => Some(SortField::Rtt),
+            ColumnId::LastConn => Some(SortField::LastConnected),
+            ColumnId::Trend | ColumnId::JumpHost | ColumnId::KeyFile | ColumnId::Tags => None,
+        }
+    }
+}
+
+/// Add `column` to `columns` if missing, otherwise take it out.
+pub fn toggle_column(columns: &mut Vec<ColumnId>, column: ColumnId) {
+    if let Some(i) = columns.iter().position(|&c| c == column) {
+        columns.remove(i);
+    } else {
+        columns.push(column);
+        columns.sort();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    Alias,
+    Hostname,
+    User,
+    Port,
+    Group,
+    Status,
+    Rtt,
+    LastConnected,
+}
+
+impl SortField {
+    fn next(self) -> Self {
+        match self {
+            SortField::Alias => SortField::Hostname,
+            SortField::Hostname => SortField::User,
+            SortField::User => SortField::Port,
+            SortField::Port => SortField::Group,
+            SortField::Group => SortField::Status,
+            SortField::Status => SortField::Rtt,
+            SortField::Rtt => SortField::LastConnected,
+            SortField::LastConnected => SortField::Alias,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+pub enum Mode {
+    Normal,
+    Edit(HostForm),
+    Add(HostForm),
+    /// Awaiting y/N to delete the host at this real index.
+    Delete(usize),
+    /// Typing a command to run on every selected host.
+    BatchPrompt(String),
+    BatchOutput { results: BatchResults, scroll: usize },
+    /// Typing a port mapping to forward through the host at `index`.
+    TunnelPrompt { index: usize, input: String, error: Option<String> },
+    TunnelList { selected: usize },
+    /// Inside tmux with no `connect_target` configured: ask where to connect.
+    ConnectChoice(usize),
+    /// The pre-flight check for the host at `index` failed; y connects anyway.
+    PreflightFailed { index: usize, error: PreflightError },
+    /// Past connections, newest first; `selected` counts from the newest.
+    History { selected: usize },
+    /// Numbered popup of `recent_connections`.
+    Recent,
+    /// Check log of the host at `index`, newest first.
+    HealthHistory { index: usize, scroll: usize },
+    /// Key list, scrolled down `scroll` rows when it doesn't fit.
+    Help { scroll: usize },
+    /// Read-only view of every field of the host at this real index.
+    HostDetail(usize),
+    /// Fleet-wide stats in place of the host table.
+    Dashboard,
+    /// Side panel for adding, renaming, reordering and deleting groups.
+    GroupManage(GroupPanel),
+    /// `:` prompt jumping to the first alias starting with what's typed.
+    Command(CommandMode),
+    /// Quitting with tunnels open: y kills them first, anything else stays.
+    QuitConfirm,
+    /// Checklist of table columns; `columns` replaces `visible_columns` on Enter.
+    ColumnPicker { selected: usize, columns: Vec<ColumnId> },
+}
+
+/// State of the `:` jump prompt.
+pub struct CommandMode {
+    pub input: String,
+    /// `selected` and `scroll_offset` before the prompt opened, for Esc.
+    restore: (usize, usize),
+}
+
+/// Why `App::preflight_check` could not reach a host.
+#[derive(Debug)]
+pub enum PreflightError {
+    DnsResolutionFailed,
+    ConnectionRefused,
+    TimedOut,
+    OtherIo(io::Error),
+}
+
+impl fmt::Display for PreflightError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PreflightError::DnsResolutionFailed => write!(f, "hostname does not resolve"),
+            PreflightError::ConnectionRefused => {
+                write!(f, "connection refused; is sshd listening on that port?")
+            }
+            PreflightError::TimedOut => write!(
+                f,
+                "no answer within {}s; host down or port firewalled?",
+                PREFLIGHT_TIMEOUT.as_secs()
+            ),
+            PreflightError::OtherIo(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+const PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// One selectable row of the host table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListEntry {
+    /// Group header; `count` is how many of its hosts match the filter.
+    Group { name: String, count: usize, collapsed: bool },
+    /// Host at this real index.
+    Host(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectTarget {
+    CurrentTerminal,
+    TmuxWindow,
+}
 
 pub struct App {
     pub hosts: Arc<Mutex<Vec<Host>>>,
@@ -7,85 +284,1671 @@ pub struct App {
     pub scroll_offset: usize,
     pub filter: String,
     pub filter_mode: bool,
+    /// Only this group's hosts are shown (`--group`); Backspace on an
+    /// empty filter clears it.
+    pub group_filter: Option<String>,
+    pub mode: Mode,
+    pub filter_kind: FilterKind,
+    pub sort_field: SortField,
+    pub sort_order: SortOrder,
     pub should_quit: bool,
     pub connect_index: Option<usize>,
+    /// Whether the pending connection adds the host's `local_forwards`.
+    pub connect_forwards: bool,
+    /// Whether the pending connection is recorded with `script`.
+    pub connect_record: bool,
+    pub sftp_index: Option<usize>,
+    pub mosh_index: Option<usize>,
+    /// ssh config file to open in `$EDITOR` once back in the main loop.
+    pub edit_file: Option<PathBuf>,
     pub show_groups: bool,
-    pub message: Option<String>,
+    /// Groups folded down to their header row.
+    pub collapsed_groups: HashSet<String>,
+    /// Table columns in normal layout, in `ColumnId` order.
+    pub visible_columns: Vec<ColumnId>,
+    /// Normal-layout column widths for the table width, columns and
+    /// `host::status_generation` they were measured for; cleared when the
+    /// hosts change.
+    pub last_computed_widths: Option<(u16, Vec<ColumnId>, u64, Vec<u16>)>,
+    pub display_density: Density,
+    /// Header messages, oldest first; the newest is shown.
+    pub toasts: VecDeque<Toast>,
+    pub config: Config,
+    /// Auto-refresh period in seconds (0 = off), shared with the refresh task.
+    pub refresh_interval: Arc<AtomicU64>,
+    /// `Config::max_health_concurrency`, shared with the refresh task.
+    pub health_concurrency: Arc<AtomicUsize>,
+    /// Set while an SSH session owns the terminal so checks don't run meanwhile.
+    pub checks_paused: Arc<AtomicBool>,
+    /// When `check_all` last started, for the header.
+    pub last_check: Arc<Mutex<Option<chrono::DateTime<chrono::Local>>>>,
+    /// Real indices of hosts marked with Space for bulk actions.
+    pub selection: BTreeSet<usize>,
+    pub active_tunnels: Vec<TunnelInfo>,
+    /// Connection log, oldest first, as stored in history.json.
+    pub history: Vec<HistoryEntry>,
+    /// Distinct aliases from `history`, most recent first.
+    pub recent_connections: VecDeque<String>,
+    /// Aliases starred with `f`, persisted to favorites.json.
+    pub favorites: HashSet<String>,
+    pub show_favorites_only: bool,
+    /// Results from the config file watcher, if it could be started.
+    pub reloads: Option<Receiver<Reload>>,
+    /// The pre-flight check `connect_to` started, if it hasn't reported
+    /// back yet: the host's alias and the outcome.
+    pub preflight: Option<Receiver<(String, Result<(), PreflightError>)>>,
+    /// Screen row → display index for the host rows drawn last frame.
+    pub table_rows: Vec<(u16, usize)>,
+    /// How many rows the host table had room for last frame.
+    pub table_height: usize,
+    last_click: Option<(usize, Instant)>,
+    /// When `g` was pressed, while waiting to see if a second `g` follows.
+    pub pending_g: Option<Instant>,
 }
 
 impl App {
-    pub fn new(hosts: Vec<Host>) -> Self {
-        Self {
+    /// `filter` and `group` come from `--filter`/`--group` and open the
+    /// filter bar with the selection on the first matching host.
+    pub fn new(
+        hosts: Vec<Host>,
+        config: Config,
+        filter: Option<String>,
+        group: Option<String>,
+    ) -> Self {
+        let history = history::load();
+        let favorites = favorites::load_pruned(&hosts);
+        let mut recent_connections = VecDeque::new();
+        for entry in history.iter().rev() {
+            if recent_connections.len() == RECENT_LIMIT {
+                break;
+            }
+            if !recent_connections.contains(&entry.alias) {
+                recent_connections.push_back(entry.alias.clone());
+            }
+        }
+
+        let filter_mode = filter.is_some() || group.is_some();
+        let mut app = Self {
             hosts: Arc::new(Mutex::new(hosts)),
             selected: 0,
             scroll_offset: 0,
-            filter: String::new(),
-            filter_mode: false,
+            filter: filter.unwrap_or_default(),
+            filter_mode,
+            group_filter: group,
+            mode: Mode::Normal,
+            filter_kind: FilterKind::Substring,
+            sort_field: SortField::Group,
+            sort_order: SortOrder::Asc,
             should_quit: false,
             connect_index: None,
+            connect_forwards: false,
+            connect_record: false,
+            sftp_index: None,
+            mosh_index: None,
+            edit_file: None,
             show_groups: true,
-            message: None,
+            collapsed_groups: HashSet::new(),
+            visible_columns: ColumnId::DEFAULT.to_vec(),
+            last_computed_widths: None,
+            display_density: Density::Normal,
+            toasts: VecDeque::new(),
+            refresh_interval: Arc::new(AtomicU64::new(
+                config.health_check_interval_secs.unwrap_or(0),
+            )),
+            health_concurrency: Arc::new(AtomicUsize::new(config.max_health_concurrency)),
+            checks_paused: Arc::new(AtomicBool::new(false)),
+            last_check: Arc::new(Mutex::new(None)),
+            config,
+            selection: BTreeSet::new(),
+            active_tunnels: Vec::new(),
+            history,
+            recent_connections,
+            favorites,
+            show_favorites_only: false,
+            reloads: None,
+            preflight: None,
+            table_rows: Vec::new(),
+            table_height: 0,
+            last_click: None,
+            pending_g: None,
+        };
+        if filter_mode {
+            // Skip the group header so Enter connects straight away
+            app.selected = app
+                .list_entries()
+                .iter()
+                .position(|e| matches!(e, ListEntry::Host(_)))
+                .unwrap_or(0);
         }
+        app
     }
 
+    /// Hosts to show, in display order, leaving out collapsed groups.
     pub fn filtered_indices(&self) -> Vec<usize> {
+        let mut indices = self.unfolded_indices();
+        if self.grouped() && !self.collapsed_groups.is_empty() {
+            let hosts = self.hosts.lock().unwrap();
+            indices.retain(|&i| !self.collapsed_groups.contains(&hosts[i].group));
+        }
+        indices
+    }
+
+    fn unfolded_indices(&self) -> Vec<usize> {
+        let mut indices = if self.show_favorites_only {
+            self.filtered_by_favorites()
+        } else {
+            self.matching_indices()
+        };
+        if let Some(ref group) = self.group_filter {
+            let hosts = self.hosts.lock().unwrap();
+            indices.retain(|&i| hosts[i].group.eq_ignore_ascii_case(group));
+        }
+        if let Mode::Command(ref command) = self.mode {
+            let prefix = command.input.to_lowercase();
+            let hosts = self.hosts.lock().unwrap();
+            indices.retain(|&i| hosts[i].alias.to_lowercase().starts_with(&prefix));
+        }
+        indices
+    }
+
+    /// Open the `:` prompt.
+    pub fn open_command_mode(&mut self) {
+        self.mode = Mode::Command(CommandMode {
+            input: String::new(),
+            restore: (self.selected, self.scroll_offset),
+        });
+    }
+
+    /// Put the cursor on the first host matching the `:` prompt so far.
+    pub fn jump_to_prefix(&mut self) {
+        self.selected = self
+            .list_entries()
+            .iter()
+            .position(|e| matches!(e, ListEntry::Host(_)))
+            .unwrap_or(0);
+    }
+
+    /// Close the `:` prompt, keeping the cursor on the host it found.
+    pub fn accept_command(&mut self) {
+        let found = self.selected_host_index();
+        self.mode = Mode::Normal;
+        let row = found.and_then(|idx| {
+            self.list_entries().iter().position(|e| *e == ListEntry::Host(idx))
+        });
+        if let Some(row) = row {
+            self.selected = row;
+        } else {
+            self.clamp_selection();
+        }
+    }
+
+    /// Close the `:` prompt and put the cursor back where it was.
+    pub fn cancel_command(&mut self) {
+        if let Mode::Command(ref command) = self.mode {
+            (self.selected, self.scroll_offset) = command.restore;
+        }
+        self.mode = Mode::Normal;
+    }
+
+    /// Whether hosts are laid out group by group under headers. Fuzzy
+    /// results are ranked by score, so groups would interleave.
+    pub fn grouped(&self) -> bool {
+        self.show_groups
+            && self.sort_field == SortField::Group
+            && !(self.filter_kind == FilterKind::Fuzzy
+                && !self.filter.is_empty()
+                && !self.filter.starts_with('#'))
+    }
+
+    /// The rows of the host table; `selected` indexes into this.
+    pub fn list_entries(&self) -> Vec<ListEntry> {
+        if !self.grouped() {
+            return self.filtered_indices().into_iter().map(ListEntry::Host).collect();
+        }
+
+        let indices = self.unfolded_indices();
         let hosts = self.hosts.lock().unwrap();
-        if self.filter.is_empty() {
-            return (0..hosts.len()).collect();
+        let mut entries = Vec::new();
+        let mut start = 0;
+        while start < indices.len() {
+            let name = &hosts[indices[start]].group;
+            let count = indices[start..]
+                .iter()
+                .take_while(|&&i| hosts[i].group == *name)
+                .count();
+            let collapsed = self.collapsed_groups.contains(name);
+            entries.push(ListEntry::Group {
+                name: name.clone(),
+                count,
+                collapsed,
+            });
+            if !collapsed {
+                entries.extend(indices[start..start + count].iter().map(|&i| ListEntry::Host(i)));
+            }
+            start += count;
         }
+        entries
+    }
+
+    /// Hosts matching the filter that are also favorites, in display order.
+    pub fn filtered_by_favorites(&self) -> Vec<usize> {
+        let mut indices = self.matching_indices();
+        let hosts = self.hosts.lock().unwrap();
+        indices.retain(|&i| self.favorites.contains(&hosts[i].alias));
+        indices
+    }
+
+    fn matching_indices(&self) -> Vec<usize> {
+        let hosts = self.hosts.lock().unwrap();
         let query = self.filter.to_lowercase();
-        hosts
-            .iter()
-            .enumerate()
-            .filter(|(_, h)| {
-                h.alias.to_lowercase().contains(&query)
-                    || h.hostname.to_lowercase().contains(&query)
-                    || h.group.to_lowercase().contains(&query)
-                    || h.user.to_lowercase().contains(&query)
-            })
-            .map(|(i, _)| i)
-            .collect()
+        // `#db` matches hosts tagged `db`, whatever the filter kind
+        let kind = if query.starts_with('#') {
+            FilterKind::Substring
+        } else {
+            self.filter_kind
+        };
+        let mut indices: Vec<usize> = if self.filter.is_empty() {
+            (0..hosts.len()).collect()
+        } else {
+            match kind {
+                // Fuzzy results stay in best-match-first order
+                FilterKind::Fuzzy => return filtered_indices_fuzzy(&hosts, &query),
+                FilterKind::Regex => filtered_indices_regex(&hosts, &self.filter),
+                FilterKind::Substring => hosts
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, h)| h.matches_filter(&query))
+                    .map(|(i, _)| i)
+                    .collect(),
+            }
+        };
+
+        indices.sort_by(|&a, &b| {
+            let ord =
+                compare_hosts(&hosts[a], &hosts[b], self.sort_field, &self.config.group_order);
+            match self.sort_order {
+                SortOrder::Asc => ord,
+                SortOrder::Desc => ord.reverse(),
+            }
+        });
+        indices
+    }
+
+    /// Flip the order of the current sort column, or move on to the next
+    /// column once it has been shown both ways.
+    pub fn cycle_sort(&mut self) {
+        match self.sort_order {
+            SortOrder::Asc => self.sort_order = SortOrder::Desc,
+            SortOrder::Desc => {
+                self.sort_field = self.sort_field.next();
+                self.sort_order = SortOrder::Asc;
+            }
+        }
+        self.selected = 0;
+        self.scroll_offset = 0;
+    }
+
+    /// Switch to `kind`, or back to plain substring matching if already in it.
+    pub fn toggle_filter_kind(&mut self, kind: FilterKind) {
+        self.filter_kind = if self.filter_kind == kind {
+            FilterKind::Substring
+        } else {
+            kind
+        };
+        self.selected = 0;
+        self.scroll_offset = 0;
+    }
+
+    /// The compile error for the current filter, when in regex mode.
+    pub fn filter_error(&self) -> Option<String> {
+        if self.filter_kind != FilterKind::Regex
+            || self.filter.is_empty()
+            || self.filter.starts_with('#')
+        {
+            return None;
+        }
+        compile_filter_regex(&self.filter).err().map(|e| {
+            // regex errors repeat the pattern with a caret; the last line is the message
+            e.to_string().lines().last().unwrap_or_default().to_string()
+        })
     }
 
     pub fn select_up(&mut self) {
         if self.selected > 0 {
             self.selected -= 1;
+        } else if self.config.wrap_navigation {
+            self.select_last();
         }
     }
 
     pub fn select_down(&mut self) {
-        let max = self.filtered_indices().len().saturating_sub(1);
+        let max = self.list_entries().len().saturating_sub(1);
         if self.selected < max {
             self.selected += 1;
+        } else if self.config.wrap_navigation {
+            self.select_first();
         }
     }
 
+    pub fn select_first(&mut self) {
+        self.selected = 0;
+    }
+
+    pub fn select_last(&mut self) {
+        self.selected = self.list_entries().len().saturating_sub(1);
+    }
+
+    /// First `g` of `gg`: the second one within `KEY_SEQUENCE` goes to the top.
+    pub fn press_g(&mut self) {
+        match self.pending_g.take() {
+            Some(at) if at.elapsed() < KEY_SEQUENCE => self.select_first(),
+            _ => self.pending_g = Some(Instant::now()),
+        }
+    }
+
+    /// Move up `n` rows, stopping at the top; with `wrap_navigation`, a page
+    /// up from the top goes to the bottom.
     pub fn page_up(&mut self, n: usize) {
-        self.selected = self.selected.saturating_sub(n);
+        if self.selected == 0 && self.config.wrap_navigation {
+            self.select_last();
+        } else {
+            self.selected = self.selected.saturating_sub(n);
+        }
     }
 
     pub fn page_down(&mut self, n: usize) {
-        let max = self.filtered_indices().len().saturating_sub(1);
-        self.selected = (self.selected + n).min(max);
+        let max = self.list_entries().len().saturating_sub(1);
+        if self.selected == max && self.config.wrap_navigation {
+            self.select_first();
+        } else {
+            self.selected = (self.selected + n).min(max);
+        }
+    }
+
+    /// Connect to the selected host, with its port forwards if
+    /// `include_forwards` and recorded if `record`; on a group header, fold
+    /// or unfold it.
+    pub fn connect_selected(&mut self, include_forwards: bool, record: bool) {
+        match self.list_entries().get(self.selected) {
+            Some(&ListEntry::Host(real_idx)) => {
+                self.connect_to(real_idx, include_forwards, record)
+            }
+            Some(ListEntry::Group { .. }) => self.toggle_collapse(),
+            None => {}
+        }
     }
 
-    pub fn connect_selected(&mut self) {
-        let indices = self.filtered_indices();
-        if let Some(&real_idx) = indices.get(self.selected) {
+    /// Collapse or expand the group under the cursor. On a host row, its
+    /// group is collapsed and the cursor moves up to the header.
+    pub fn toggle_collapse(&mut self) {
+        if !self.grouped() {
+            return;
+        }
+        let entries = self.list_entries();
+        let name = match entries.get(self.selected) {
+            Some(ListEntry::Group { name, .. }) => name.clone(),
+            Some(&ListEntry::Host(idx)) => self.hosts.lock().unwrap()[idx].group.clone(),
+            None => return,
+        };
+        if !self.collapsed_groups.remove(&name) {
+            self.collapsed_groups.insert(name.clone());
+        }
+        let header = entries
+            .iter()
+            .position(|e| matches!(e, ListEntry::Group { name: n, .. } if *n == name));
+        if let Some(header) = header {
+            self.selected = header;
+        }
+    }
+
+    pub fn toggle_density(&mut self) {
+        self.display_density = match self.display_density {
+            Density::Normal => Density::Compact,
+            Density::Compact => Density::Wide,
+            Density::Wide => Density::Normal,
+        };
+    }
+
+    pub fn toggle_groups(&mut self) {
+        self.show_groups = !self.show_groups;
+        self.selected = 0;
+        self.scroll_offset = 0;
+    }
+
+    /// Connect to the host at `real_idx`, in tmux if configured or chosen,
+    /// after the pre-flight check if that is turned on. `record` records
+    /// the session even if the host or config doesn't ask for it.
+    pub fn connect_to(&mut self, real_idx: usize, include_forwards: bool, record: bool) {
+        self.connect_forwards = include_forwards;
+        self.connect_record = record || self.records_sessions(real_idx);
+        let host = self.hosts.lock().unwrap()[real_idx].clone();
+        if !self.config.preflight_check || goes_through_proxy(&host) {
+            self.connect_unchecked(real_idx);
+            return;
+        }
+        // Resolving and connecting can take seconds; the frame loop picks up the result
+        let (tx, rx) = mpsc::channel();
+        let alias = host.alias.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send((host.alias.clone(), Self::preflight_check(&host)));
+        });
+        self.preflight = Some(rx);
+        self.push_toast(format!("Checking {}…", alias), ToastLevel::Info, TOAST);
+    }
+
+    /// Connect, or report the failure, once the pre-flight check started by
+    /// `connect_to` is done.
+    pub fn apply_preflight(&mut self) {
+        let Some(rx) = &self.preflight else { return };
+        let (alias, result) = match rx.try_recv() {
+            Ok(done) => done,
+            Err(mpsc::TryRecvError::Empty) => return,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.preflight = None;
+                return;
+            }
+        };
+        self.preflight = None;
+        // The list may have been reloaded while the check ran
+        let idx = self.hosts.lock().unwrap().iter().position(|h| h.alias == alias);
+        match (idx, result) {
+            (Some(idx), Ok(())) => self.connect_unchecked(idx),
+            (Some(index), Err(error)) => self.mode = Mode::PreflightFailed { index, error },
+            (None, _) => self.push_toast(
+                format!("'{}' is no longer in the host list", alias),
+                ToastLevel::Warning, TOAST_LONG,
+            ),
+        }
+    }
+
+    /// `connect_to` without the pre-flight check.
+    pub fn connect_unchecked(&mut self, real_idx: usize) {
+        if std::env::var_os("TMUX").is_none() {
+            self.connect_here(real_idx);
+            return;
+        }
+        match self.config.connect_target {
+            Some(ConnectTarget::CurrentTerminal) => self.connect_here(real_idx),
+            Some(ConnectTarget::TmuxWindow) => self.connect_in_tmux(real_idx),
+            None => self.mode = Mode::ConnectChoice(real_idx),
+        }
+    }
+
+    /// Connect in this terminal, with mosh if the host or config asks for it.
+    pub fn connect_here(&mut self, real_idx: usize) {
+        if self.uses_mosh(real_idx) {
+            self.mosh_index = Some(real_idx);
+        } else {
             self.connect_index = Some(real_idx);
         }
     }
 
+    /// Whether Enter opens mosh for the host. mosh can't forward ports, so
+    /// Shift-Enter always uses ssh, as do recorded sessions.
+    fn uses_mosh(&self, idx: usize) -> bool {
+        let use_mosh = self.hosts.lock().unwrap()[idx].use_mosh;
+        (use_mosh || self.config.prefer_mosh) && !self.connect_forwards && !self.connect_record
+    }
+
+    /// Whether sessions with the host are recorded without asking, per the
+    /// host's `record_sessions` or else the config's.
+    fn records_sessions(&self, idx: usize) -> bool {
+        let record = self.hosts.lock().unwrap()[idx].record_sessions;
+        record.unwrap_or(self.config.record_sessions)
+    }
+
+    /// Resolve the host and open a TCP connection to its SSH port, so an
+    /// unreachable host is reported before ssh takes over the terminal.
+    /// Each resolved address is tried in turn; the last failure is reported.
+    /// Blocks for up to `PREFLIGHT_TIMEOUT` per address.
+    pub fn preflight_check(host: &Host) -> Result<(), PreflightError> {
+        let addrs: Vec<SocketAddr> = match (host.hostname.as_str(), host.port).to_socket_addrs() {
+            Ok(addrs) => addrs.collect(),
+            Err(_) => return Err(PreflightError::DnsResolutionFailed),
+        };
+        let mut error = PreflightError::DnsResolutionFailed;
+        for addr in &addrs {
+            match TcpStream::connect_timeout(addr, PREFLIGHT_TIMEOUT) {
+                Ok(_) => return Ok(()),
+                Err(e) => {
+                    error = match e.kind() {
+                        io::ErrorKind::ConnectionRefused => PreflightError::ConnectionRefused,
+                        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                            PreflightError::TimedOut
+                        }
+                        _ => PreflightError::OtherIo(e),
+                    }
+                }
+            }
+        }
+        Err(error)
+    }
+
+    /// The ssh invocation for the pending connection to the host at `idx`,
+    /// wrapped in `script` if it is being recorded, with the log's path.
+    pub fn session_command(&self, idx: usize) -> io::Result<(Vec<String>, Option<PathBuf>)> {
+        let hosts = self.hosts.lock().unwrap();
+        let cmd = self.config.ssh_command(&hosts[idx], self.connect_forwards);
+        if !self.connect_record {
+            return Ok((cmd, None));
+        }
+        let path = host::recording_path(&hosts[idx]);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        Ok((host::script_command(&cmd, &path), Some(path)))
+    }
+
+    /// Open the session in a new tmux window; the TUI stays on screen.
+    pub fn connect_in_tmux(&mut self, idx: usize) {
+        let alias = self.hosts.lock().unwrap()[idx].alias.clone();
+        let (cmd, recording) = if self.uses_mosh(idx) {
+            (self.config.mosh_command(&self.hosts.lock().unwrap()[idx]), None)
+        } else {
+            match self.session_command(idx) {
+                Ok(session) => session,
+                Err(e) => {
+                    let message = format!("Can't create the recordings directory: {}", e);
+                    self.push_toast(message, ToastLevel::Error, TOAST_LONG);
+                    return;
+                }
+            }
+        };
+        let result = Command::new("tmux")
+            .args(["new-window", "-n", &alias, "--"])
+            .args(&cmd)
+            .output();
+        match result {
+            Ok(o) if o.status.success() => {
+                // The window outlives this call, so there's no duration to record
+                self.record_connection(idx);
+                match recording {
+                    Some(path) => {
+                        let message = format!(
+                            "Opened {} in a tmux window, recording to {}",
+                            alias,
+                            path.display()
+                        );
+                        self.push_toast(message, ToastLevel::Success, TOAST_LONG)
+                    }
+                    None => self.push_toast(
+                        format!("Opened {} in a tmux window", alias),
+                        ToastLevel::Success,
+                        TOAST,
+                    ),
+                }
+            }
+            Ok(o) => {
+                let message = format!("tmux failed: {}", String::from_utf8_lossy(&o.stderr).trim());
+                self.push_toast(message, ToastLevel::Error, TOAST_LONG)
+            }
+            Err(e) => {
+                self.push_toast(format!("Failed to run tmux: {}", e), ToastLevel::Error, TOAST_LONG)
+            }
+        }
+    }
+
+    /// Pick up anything the config watcher reloaded since the last frame.
+    pub fn apply_reloads(&mut self) {
+        let reloads: Vec<Reload> = match self.reloads {
+            Some(ref rx) => rx.try_iter().collect(),
+            None => return,
+        };
+        for reload in reloads {
+            match reload {
+                Reload::Hosts(hosts) => {
+                    host::merge_hosts(&mut self.hosts.lock().unwrap(), hosts);
+                    // Indices may have shifted under the selection and open dialogs
+                    self.selection.clear();
+                    self.last_computed_widths = None;
+                    if matches!(
+                        self.mode,
+                        Mode::Edit(_)
+                            | Mode::Delete(_)
+                            | Mode::TunnelPrompt { .. }
+                            | Mode::ConnectChoice(_)
+                            | Mode::PreflightFailed { .. }
+                            | Mode::HealthHistory { .. }
+                            | Mode::HostDetail(_)
+                    ) {
+                        self.mode = Mode::Normal;
+                    }
+                    self.clamp_selection();
+                    self.sync_group_panel();
+                    self.push_toast("Config reloaded", ToastLevel::Info, TOAST);
+                }
+                Reload::Config(config) => {
+                    self.refresh_interval.store(
+                        config.health_check_interval_secs.unwrap_or(0),
+                        std::sync::atomic::Ordering::Relaxed,
+                    );
+                    self.health_concurrency.store(
+                        config.max_health_concurrency,
+                        std::sync::atomic::Ordering::Relaxed,
+                    );
+                    health::configure(&config);
+                    host::configure(&config);
+                    self.config = *config;
+                    self.sync_group_panel();
+                    self.push_toast("Config reloaded", ToastLevel::Info, TOAST);
+                }
+                Reload::Error(e) => self.push_toast(e, ToastLevel::Error, TOAST_LONG),
+            }
+        }
+    }
+
+    /// Check every host now and note the time for the header.
+    pub fn check_all(&mut self) {
+        *self.last_check.lock().unwrap() = Some(chrono::Local::now());
+        health::check_all_bounded(Arc::clone(&self.hosts), self.config.max_health_concurrency);
+    }
+
+    /// Log a session to the host at `idx` and persist the history.
+    pub fn record_connection(&mut self, idx: usize) {
+        let entry = {
+            let hosts = self.hosts.lock().unwrap();
+            HistoryEntry::new(&hosts[idx].alias, &hosts[idx].hostname)
+        };
+        self.recent_connections.retain(|a| *a != entry.alias);
+        self.recent_connections.push_front(entry.alias.clone());
+        self.recent_connections.truncate(RECENT_LIMIT);
+        history::push(&mut self.history, entry);
+        self.save_history();
+    }
+
+    /// Bump the host's connection stats after a session exits cleanly.
+    pub fn mark_connected(&mut self, idx: usize) {
+        let saved = {
+            let mut hosts = self.hosts.lock().unwrap();
+            let host = &mut hosts[idx];
+            host.last_connected = Some(chrono::Utc::now());
+            host.connection_count += 1;
+            if host.source.is_owned() { save_sshmap_hosts(&mut hosts) } else { Ok(()) }
+        };
+        if let Err(e) = saved {
+            self.push_toast(format!("Failed to save: {}", e), ToastLevel::Error, TOAST_LONG);
+        }
+        // state.json keeps the stats of every host, ~/.ssh/config ones included
+        if let Err(e) = AppState::capture(self).save() {
+            self.push_toast(format!("Failed to save state: {}", e), ToastLevel::Error, TOAST_LONG);
+        }
+    }
+
+    /// Fill in the duration of the session started by `record_connection`.
+    pub fn finish_connection(&mut self, elapsed: Duration) {
+        if let Some(entry) = self.history.last_mut() {
+            entry.duration_secs = Some(elapsed.as_secs());
+        }
+        self.save_history();
+    }
+
+    fn save_history(&mut self) {
+        if let Err(e) = history::save(&self.history) {
+            let message = format!("Failed to save history: {}", e);
+            self.push_toast(message, ToastLevel::Error, TOAST_LONG);
+        }
+    }
+
+    /// Reconnect to the `n`th most recent history entry, if its host still exists.
+    pub fn reconnect_from_history(&mut self, n: usize) {
+        let alias = match self.history.iter().rev().nth(n) {
+            Some(entry) => entry.alias.clone(),
+            None => return,
+        };
+        let idx = self.hosts.lock().unwrap().iter().position(|h| h.alias == alias);
+        match idx {
+            Some(idx) => {
+                // Back from the session on the host, if the list shows it
+                self.select_by_alias(&alias);
+                self.connect_to(idx, false, false)
+            }
+            None => self.push_toast(
+                format!("'{}' is no longer in the host list", alias),
+                ToastLevel::Warning, TOAST_LONG,
+            ),
+        }
+    }
+
+    pub fn toggle_favorite(&mut self) {
+        let alias = match self.selected_host_index() {
+            Some(idx) => self.hosts.lock().unwrap()[idx].alias.clone(),
+            None => return,
+        };
+        if !self.favorites.remove(&alias) {
+            self.favorites.insert(alias);
+        }
+        if let Err(e) = favorites::save(&self.favorites) {
+            let message = format!("Failed to save favorites: {}", e);
+            self.push_toast(message, ToastLevel::Error, TOAST_LONG);
+        }
+        self.clamp_selection();
+    }
+
+    pub fn toggle_favorites_only(&mut self) {
+        self.show_favorites_only = !self.show_favorites_only;
+        self.selected = 0;
+        self.scroll_offset = 0;
+    }
+
+    /// Keep the cursor on a row after the visible list shrinks.
+    fn clamp_selection(&mut self) {
+        let len = self.list_entries().len();
+        if self.selected >= len {
+            self.selected = len.saturating_sub(1);
+        }
+    }
+
+    /// Connect to the `n`th entry of the recent list; hosts that have since
+    /// been removed are skipped.
+    pub fn connect_recent(&mut self, n: usize) {
+        let alias = match self.recent_connections.get(n) {
+            Some(alias) => alias.clone(),
+            None => return,
+        };
+        let idx = self.hosts.lock().unwrap().iter().position(|h| h.alias == alias);
+        if let Some(idx) = idx {
+            self.mode = Mode::Normal;
+            self.select_by_alias(&alias);
+            self.connect_to(idx, false, false);
+        }
+    }
+
+    /// The full ssh invocation for `host`, including `ssh_extra_args` from the config.
+    pub fn ssh_command_for(&self, host: &Host) -> Vec<String> {
+        self.config.ssh_command(host, false)
+    }
+
+    pub fn toggle_selection(&mut self) {
+        if let Some(idx) = self.selected_host_index() {
+            if !self.selection.remove(&idx) {
+                self.selection.insert(idx);
+            }
+        }
+    }
+
+    /// Put the cursor on the first visible host called `alias`, ignoring
+    /// case, and scroll the table just far enough to show it; false if no
+    /// shown host has that alias.
+    pub fn select_by_alias(&mut self, alias: &str) -> bool {
+        let entries = self.list_entries();
+        let hosts = self.hosts.lock().unwrap();
+        let row = entries.iter().position(|e| {
+            matches!(e, ListEntry::Host(i) if hosts[*i].alias.eq_ignore_ascii_case(alias))
+        });
+        drop(hosts);
+        match row {
+            Some(row) => {
+                self.selected = row;
+                let height = self.table_height.max(1);
+                if row < self.scroll_offset {
+                    self.scroll_offset = row;
+                } else if row >= self.scroll_offset + height {
+                    self.scroll_offset = row + 1 - height;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Select the `n`th row (from 0) of those drawn last frame, so Enter
+    /// connects to it straight away.
+    pub fn select_visible_row(&mut self, n: usize) {
+        if let Some(&(_, display_idx)) = self.table_rows.get(n) {
+            self.selected = display_idx;
+        }
+    }
+
+    /// Select the row drawn at screen `row`; a second click on the same
+    /// row within `DOUBLE_CLICK` connects to it (or folds a group).
+    pub fn click_row(&mut self, row: u16) {
+        let display_idx = match self.table_rows.iter().find(|(y, _)| *y == row) {
+            Some(&(_, idx)) => idx,
+            None => return,
+        };
+
+        let now = Instant::now();
+        let is_double = matches!(
+            self.last_click,
+            Some((idx, at)) if idx == display_idx && now.duration_since(at) < DOUBLE_CLICK
+        );
+
+        self.selected = display_idx;
+        if is_double {
+            self.last_click = None;
+            self.connect_selected(false, false);
+        } else {
+            self.last_click = Some((display_idx, now));
+        }
+    }
+
+    /// Real index of the host under the cursor; `None` on a group header.
     pub fn selected_host_index(&self) -> Option<usize> {
-        let indices = self.filtered_indices();
-        indices.get(self.selected).copied()
+        match self.list_entries().get(self.selected) {
+            Some(&ListEntry::Host(idx)) => Some(idx),
+            _ => None,
+        }
+    }
+
+    pub fn open_edit_form(&mut self) {
+        let idx = match self.selected_host_index() {
+            Some(idx) => idx,
+            None => return,
+        };
+        let hosts = self.hosts.lock().unwrap();
+        let host = &hosts[idx];
+        if host.source == HostSource::SshConfig {
+            let file = host.ssh_config_source.as_deref().map(host::tilde_path);
+            let message = format!(
+                "'{}' is defined in {}; edit it there (i, then e)",
+                host.alias,
+                file.as_deref().unwrap_or("~/.ssh/config")
+            );
+            drop(hosts);
+            self.push_toast(message, ToastLevel::Warning, TOAST_LONG);
+            return;
+        }
+        let form = HostForm::edit(idx, host);
+        drop(hosts);
+        self.mode = Mode::Edit(form);
+    }
+
+    /// Edit the host at `idx` where it is defined: in the form if sshmap's
+    /// file has it, otherwise its ssh config file in `$EDITOR`.
+    pub fn edit_host_source(&mut self, idx: usize) {
+        let hosts = self.hosts.lock().unwrap();
+        let host = &hosts[idx];
+        if host.source.is_owned() {
+            let form = HostForm::edit(idx, host);
+            drop(hosts);
+            self.mode = Mode::Edit(form);
+            return;
+        }
+        match host.ssh_config_source.clone() {
+            Some(path) => {
+                drop(hosts);
+                self.mode = Mode::Normal;
+                self.edit_file = Some(path);
+            }
+            None => {
+                let message = format!("No config file recorded for '{}'", host.alias);
+                drop(hosts);
+                self.push_toast(message, ToastLevel::Warning, TOAST_LONG);
+            }
+        }
+    }
+
+    /// Re-read every host file after editing one outside the watched directory.
+    pub fn reload_hosts(&mut self) {
+        host::merge_hosts(&mut self.hosts.lock().unwrap(), host::load_hosts());
+        self.selection.clear();
+        self.last_computed_widths = None;
+        self.clamp_selection();
+    }
+
+    pub fn confirm_delete(&mut self) {
+        let idx = match self.selected_host_index() {
+            Some(idx) => idx,
+            None => return,
+        };
+        let hosts = self.hosts.lock().unwrap();
+        if hosts[idx].source == HostSource::SshConfig {
+            let message =
+                format!("'{}' is defined in ~/.ssh/config; remove it there", hosts[idx].alias);
+            drop(hosts);
+            self.push_toast(message, ToastLevel::Warning, TOAST_LONG);
+            return;
+        }
+        drop(hosts);
+        self.mode = Mode::Delete(idx);
+    }
+
+    pub fn delete_host(&mut self, idx: usize) {
+        let mut hosts = self.hosts.lock().unwrap();
+        if idx >= hosts.len() {
+            return;
+        }
+        let removed = hosts.remove(idx);
+        self.selection.clear();
+        self.last_computed_widths = None;
+        let result = save_sshmap_hosts(&mut hosts);
+        drop(hosts);
+
+        let max = self.list_entries().len().saturating_sub(1);
+        self.selected = self.selected.min(max);
+        match result {
+            Ok(()) => {
+                self.push_toast(format!("Deleted '{}'", removed.alias), ToastLevel::Success, TOAST)
+            }
+            Err(e) => {
+                let message = format!("Failed to save hosts: {}", e);
+                self.push_toast(message, ToastLevel::Error, TOAST_LONG)
+            }
+        }
     }
 
+    pub fn open_batch_prompt(&mut self) {
+        if self.selection.is_empty() {
+            self.push_toast("Select hosts with Space first", ToastLevel::Warning, TOAST);
+            return;
+        }
+        self.mode = Mode::BatchPrompt(String::new());
+    }
+
+    pub fn run_batch(&mut self, command: &str) {
+        let targets = {
+            let hosts = self.hosts.lock().unwrap();
+            self.selection
+                .iter()
+                .filter_map(|&i| hosts.get(i))
+                .map(|h| (h.alias.clone(), self.ssh_command_for(h)))
+                .collect()
+        };
+        self.mode = Mode::BatchOutput {
+            results: batch::run(targets, command),
+            scroll: 0,
+        };
+    }
+
+    /// Group names in display order, as listed in the group panel.
     pub fn groups(&self) -> Vec<String> {
+        groups::ordered(&self.hosts.lock().unwrap(), &self.config.group_order)
+    }
+
+    pub fn toggle_column(&mut self, column: ColumnId) {
+        toggle_column(&mut self.visible_columns, column);
+    }
+
+    pub fn open_column_picker(&mut self) {
+        self.mode = Mode::ColumnPicker {
+            selected: 0,
+            columns: self.visible_columns.clone(),
+        };
+    }
+
+    pub fn open_group_panel(&mut self) {
+        self.mode = Mode::GroupManage(GroupPanel::new(self.groups()));
+    }
+
+    /// Apply the name typed into the group panel, adding or renaming a group.
+    pub fn submit_group_input(&mut self) {
+        let panel = match &mut self.mode {
+            Mode::GroupManage(panel) => panel,
+            _ => return,
+        };
+        let (name, old) = match &panel.action {
+            GroupAction::Add(input) => (input.trim().to_string(), None),
+            GroupAction::Rename(input) => {
+                (input.trim().to_string(), panel.current().map(str::to_string))
+            }
+            _ => return,
+        };
+        if name.is_empty() {
+            panel.error = Some("Group name cannot be empty".into());
+            return;
+        }
+        if old.as_deref() != Some(name.as_str()) && panel.groups.contains(&name) {
+            panel.error = Some(format!("Group '{}' already exists", name));
+            return;
+        }
+
+        if let Some(old) = &old {
+            if let Err(e) = self.check_regroupable(old) {
+                if let Mode::GroupManage(panel) = &mut self.mode {
+                    panel.error = Some(e);
+                }
+                return;
+            }
+        }
+
+        let mut order = self.groups();
+        match old {
+            Some(old) => {
+                let mut hosts = self.hosts.lock().unwrap();
+                for host in hosts.iter_mut().filter(|h| h.group == old) {
+                    host.group = name.clone();
+                }
+                let result = save_sshmap_hosts(&mut hosts);
+                drop(hosts);
+                self.last_computed_widths = None;
+                if let Err(e) = result {
+                    let message = format!("Failed to save hosts: {}", e);
+                    self.push_toast(message, ToastLevel::Error, TOAST_LONG);
+                }
+                if self.collapsed_groups.remove(&old) {
+                    self.collapsed_groups.insert(name.clone());
+                }
+                for g in order.iter_mut().filter(|g| **g == old) {
+                    *g = name.clone();
+                }
+            }
+            None => order.push(name.clone()),
+        }
+        self.save_group_order(order);
+        self.refresh_group_panel(Some(&name));
+    }
+
+    /// Move the selected group one place up or down in the display order.
+    pub fn move_group(&mut self, up: bool) {
+        let selected = match &self.mode {
+            Mode::GroupManage(panel) => panel.selected,
+            _ => return,
+        };
+        let mut order = self.groups();
+        let other = if up {
+            selected.checked_sub(1)
+        } else {
+            Some(selected + 1).filter(|&i| i < order.len())
+        };
+        let other = match other {
+            Some(other) => other,
+            None => return,
+        };
+        order.swap(selected, other);
+        let name = order[other].clone();
+        self.save_group_order(order);
+        self.refresh_group_panel(Some(&name));
+    }
+
+    /// Delete the selected group. If hosts still use it, ask where they go first.
+    pub fn start_group_delete(&mut self) {
+        let name = match &self.mode {
+            Mode::GroupManage(panel) => match panel.current() {
+                Some(name) => name.to_string(),
+                None => return,
+            },
+            _ => return,
+        };
+        let in_use = self.hosts.lock().unwrap().iter().any(|h| h.group == name);
+        if !in_use {
+            self.delete_group(&name, None);
+            return;
+        }
+        let regroupable = self.check_regroupable(&name);
+        let targets = groups::delete_targets(&self.groups(), &name);
+        if let Mode::GroupManage(panel) = &mut self.mode {
+            if let Err(e) = regroupable {
+                panel.error = Some(e);
+            } else if targets.is_empty() {
+                panel.error = Some(format!("No other group to move the hosts of '{}' to", name));
+            } else {
+                panel.action = GroupAction::Delete { targets, selected: 0 };
+            }
+        }
+    }
+
+    /// Finish a delete started by `start_group_delete` with the chosen target.
+    pub fn confirm_group_delete(&mut self) {
+        let (name, target) = match &self.mode {
+            Mode::GroupManage(panel) => match (&panel.action, panel.current()) {
+                (GroupAction::Delete { targets, selected }, Some(name)) => {
+                    (name.to_string(), targets[*selected].clone())
+                }
+                _ => return,
+            },
+            _ => return,
+        };
+        self.delete_group(&name, Some(&target));
+    }
+
+    fn delete_group(&mut self, name: &str, move_to: Option<&str>) {
+        if let Some(target) = move_to {
+            let mut hosts = self.hosts.lock().unwrap();
+            for host in hosts.iter_mut().filter(|h| h.group == name) {
+                host.group = target.to_string();
+            }
+            let result = save_sshmap_hosts(&mut hosts);
+            drop(hosts);
+            self.last_computed_widths = None;
+            if let Err(e) = result {
+                let message = format!("Failed to save hosts: {}", e);
+                self.push_toast(message, ToastLevel::Error, TOAST_LONG);
+            }
+        }
+        self.collapsed_groups.remove(name);
+        let mut order = self.groups();
+        order.retain(|g| g != name);
+        self.save_group_order(order);
+        self.refresh_group_panel(move_to);
+        self.clamp_selection();
+    }
+
+    /// Hosts from ~/.ssh/config take their group from a `# group:` comment
+    /// there, which sshmap doesn't rewrite, so their group can't be changed here.
+    fn check_regroupable(&self, group: &str) -> Result<(), String> {
         let hosts = self.hosts.lock().unwrap();
-        let mut groups: Vec<String> = hosts.iter().map(|h| h.group.clone()).collect();
-        groups.sort();
-        groups.dedup();
-        groups
+        let from_ssh_config = hosts
+            .iter()
+            .filter(|h| h.group == group && h.source == HostSource::SshConfig)
+            .count();
+        if from_ssh_config == 0 {
+            return Ok(());
+        }
+        Err(format!(
+            "'{}' has {} host(s) from ~/.ssh/config; change their # group: comment there",
+            group, from_ssh_config
+        ))
+    }
+
+    /// Bring an open group panel up to date after a reload, keeping the
+    /// selected group and whatever is being typed.
+    fn sync_group_panel(&mut self) {
+        let groups = self.groups();
+        if let Mode::GroupManage(panel) = &mut self.mode {
+            let current = panel.current().map(str::to_string);
+            panel.selected = current
+                .and_then(|c| groups.iter().position(|g| *g == c))
+                .unwrap_or(panel.selected)
+                .min(groups.len().saturating_sub(1));
+            panel.groups = groups;
+        }
+    }
+
+    fn save_group_order(&mut self, order: Vec<String>) {
+        self.config.group_order = order;
+        if let Err(e) = self.config.save() {
+            self.push_toast(format!("Failed to save config: {}", e), ToastLevel::Error, TOAST_LONG);
+        }
+    }
+
+    /// Reload the panel's list after a change, keeping `focus` selected.
+    fn refresh_group_panel(&mut self, focus: Option<&str>) {
+        let groups = self.groups();
+        if let Mode::GroupManage(panel) = &mut self.mode {
+            if let Some(i) = focus.and_then(|f| groups.iter().position(|g| g == f)) {
+                panel.selected = i;
+            }
+            panel.selected = panel.selected.min(groups.len().saturating_sub(1));
+            panel.groups = groups;
+            panel.action = GroupAction::Browse;
+            panel.error = None;
+        }
+    }
+
+    /// Show `message` in the header for `duration`, on top of any toasts
+    /// still showing.
+    pub fn push_toast(
+        &mut self,
+        message: impl Into<String>,
+        level: ToastLevel,
+        duration: Duration,
+    ) {
+        self.toasts.push_back(Toast {
+            message: message.into(),
+            level,
+            expires_at: Instant::now() + duration,
+        });
+        while self.toasts.len() > TOAST_LIMIT {
+            self.toasts.pop_front();
+        }
+    }
+
+    /// Drop toasts that have run their time.
+    pub fn expire_toasts(&mut self) {
+        let now = Instant::now();
+        self.toasts.retain(|t| t.expires_at > now);
+    }
+
+    pub fn copy_command(&mut self) {
+        let idx = match self.selected_host_index() {
+            Some(idx) => idx,
+            None => return,
+        };
+        let cmd = self.hosts.lock().unwrap()[idx].ssh_command(false).join(" ");
+        match clipboard::copy(&cmd) {
+            Ok(()) => self.push_toast("Copied to clipboard", ToastLevel::Success, TOAST),
+            // The detail pane already shows the full command to copy by hand
+            Err(e) => {
+                let message = format!("Clipboard unavailable ({}); command shown below", e);
+                self.push_toast(message, ToastLevel::Warning, TOAST_LONG)
+            }
+        }
+    }
+
+    pub fn open_tunnel_prompt(&mut self) {
+        if let Some(index) = self.selected_host_index() {
+            self.mode = Mode::TunnelPrompt {
+                index,
+                input: String::new(),
+                error: None,
+            };
+        }
+    }
+
+    pub fn start_tunnel(&mut self) {
+        let (index, mapping) = match &self.mode {
+            Mode::TunnelPrompt { index, input, .. } => (*index, input.trim().to_string()),
+            _ => return,
+        };
+        let (alias, cmd) = {
+            let hosts = self.hosts.lock().unwrap();
+            (hosts[index].alias.clone(), self.ssh_command_for(&hosts[index]))
+        };
+
+        match tunnel::open(&alias, &cmd, &mapping) {
+            Ok(t) => {
+                self.active_tunnels.push(t);
+                self.mode = Mode::Normal;
+                let message = format!("Tunnel {} via {} started", mapping, alias);
+                self.push_toast(message, ToastLevel::Success, TOAST);
+            }
+            Err(e) => {
+                if let Mode::TunnelPrompt { error, .. } = &mut self.mode {
+                    *error = Some(e);
+                }
+            }
+        }
+    }
+
+    pub fn kill_tunnel(&mut self, i: usize) {
+        if i < self.active_tunnels.len() {
+            let mut t = self.active_tunnels.remove(i);
+            t.kill();
+            let message = format!("Tunnel {} via {} closed", t.mapping, t.alias);
+            self.push_toast(message, ToastLevel::Info, TOAST);
+        }
+    }
+
+    /// Close every tunnel before quitting, so no ssh -N is left behind.
+    pub fn kill_all_tunnels(&mut self) {
+        for mut t in self.active_tunnels.drain(..) {
+            t.kill();
+        }
+    }
+
+    /// Drop tunnels whose ssh process has exited (auth failure, port in use, ...).
+    pub fn reap_tunnels(&mut self) {
+        let mut exited = Vec::new();
+        self.active_tunnels.retain_mut(|t| match t.child.try_wait() {
+            Ok(None) => true,
+            _ => {
+                exited.push(format!("{} via {}", t.mapping, t.alias));
+                false
+            }
+        });
+        if let Some(last) = exited.pop() {
+            self.push_toast(format!("Tunnel {} exited", last), ToastLevel::Warning, TOAST_LONG);
+        }
+    }
+
+    pub fn open_add_form(&mut self) {
+        self.mode = Mode::Add(HostForm::add(&self.config.default_user));
+    }
+
+    /// Validate and apply the open form, then persist. On a validation
+    /// error the form stays open with the error in its status line.
+    pub fn submit_form(&mut self) {
+        let form = match &mut self.mode {
+            Mode::Edit(form) | Mode::Add(form) => form,
+            _ => return,
+        };
+
+        let mut hosts = self.hosts.lock().unwrap();
+        let base = match form.target {
+            Some(idx) => hosts[idx].clone(),
+            None => Host::default(),
+        };
+        let updated = match form.to_host(&base) {
+            Ok(h) => h,
+            Err(e) => {
+                form.error = Some(e);
+                return;
+            }
+        };
+        if hosts
+            .iter()
+            .enumerate()
+            .any(|(i, h)| Some(i) != form.target && h.alias == updated.alias)
+        {
+            form.error = Some(format!("Alias '{}' is already in use", updated.alias));
+            return;
+        }
+
+        match form.target {
+            Some(idx) => hosts[idx] = updated,
+            None => {
+                hosts.push(updated);
+                host::sort_hosts(&mut hosts);
+                self.selection.clear();
+            }
+        }
+        self.last_computed_widths = None;
+        let result = save_sshmap_hosts(&mut hosts);
+        drop(hosts);
+
+        self.mode = Mode::Normal;
+        match result {
+            Ok(()) => self.push_toast("Host saved", ToastLevel::Success, TOAST),
+            Err(e) => {
+                let message = format!("Failed to save hosts: {}", e);
+                self.push_toast(message, ToastLevel::Error, TOAST_LONG)
+            }
+        }
+    }
+}
+
+/// Whether ssh reaches the host through a jump host or proxy command, which
+/// a direct connection from here says nothing about.
+fn goes_through_proxy(host: &Host) -> bool {
+    host.proxy_jump.is_some()
+        || host.extra_ssh_options.iter().any(|option| {
+            let option = option.to_lowercase();
+            option.contains("proxycommand") || option.contains("proxyjump") || option == "-j"
+        })
+}
+
+/// Write back the hosts sshmap owns; ~/.ssh/config entries stay where they
+/// are, and of a merged host only sshmap's entry is written.
+fn save_sshmap_hosts(hosts: &mut [Host]) -> anyhow::Result<()> {
+    let owned: Vec<Host> = hosts
+        .iter()
+        .filter(|h| h.source.is_owned())
+        .map(host::sshmap_entry)
+        .collect();
+    host::save_sshmap_config(&owned)?;
+    // The next save compares against what is in the file now
+    for (host, entry) in hosts.iter_mut().filter(|h| h.source.is_owned()).zip(owned) {
+        if let Some(from) = host.merged_from.as_mut() {
+            from.sshmap = entry;
+        }
+    }
+    Ok(())
+}
+
+fn compare_hosts(a: &Host, b: &Host, field: SortField, group_order: &[String]) -> Ordering {
+    let ord = match field {
+        SortField::Alias => Ordering::Equal,
+        SortField::Hostname => a.hostname.cmp(&b.hostname),
+        SortField::User => a.user.cmp(&b.user),
+        SortField::Port => a.port.cmp(&b.port),
+        SortField::Group => groups::rank(group_order, &a.group)
+            .cmp(&groups::rank(group_order, &b.group))
+            .then_with(|| a.group.cmp(&b.group)),
+        SortField::Status => status_rank(&a.status).cmp(&status_rank(&b.status)),
+        SortField::Rtt => rtt_key(&a.status).total_cmp(&rtt_key(&b.status)),
+        // Newest first; never-connected hosts (None) sort last
+        SortField::LastConnected => b.last_connected.cmp(&a.last_connected),
+    };
+    ord.then_with(|| a.alias.cmp(&b.alias))
+}
+
+fn status_rank(status: &HostStatus) -> u8 {
+    match status {
+        HostStatus::Up { .. } | HostStatus::TlsOk { .. } => 0,
+        HostStatus::TcpOpen(_) | HostStatus::TlsExpiringSoon { .. } => 1,
+        HostStatus::Timeout => 2,
+        HostStatus::Down | HostStatus::TlsExpired => 3,
+        HostStatus::DnsError => 4,
+        HostStatus::Checking => 5,
+        HostStatus::Unknown => 6,
+    }
+}
+
+// Hosts without an RTT sort after every host that has one
+fn rtt_key(status: &HostStatus) -> f64 {
+    status.rtt().unwrap_or(f64::INFINITY)
+}
+
+fn compile_filter_regex(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+/// Hosts with any field matching `pattern`. An invalid pattern matches nothing;
+/// the UI reports the error via `App::filter_error`.
+fn filtered_indices_regex(hosts: &[Host], pattern: &str) -> Vec<usize> {
+    let re = match compile_filter_regex(pattern) {
+        Ok(re) => re,
+        Err(_) => return Vec::new(),
+    };
+    hosts
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| {
+            re.is_match(&h.alias)
+                || re.is_match(&h.hostname)
+                || re.is_match(&h.user)
+                || re.is_match(&h.group)
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Hosts matching `query` fuzzily, best match first.
+fn filtered_indices_fuzzy(hosts: &[Host], query: &str) -> Vec<usize> {
+    let mut scored: Vec<(usize, i64)> = hosts
+        .iter()
+        .enumerate()
+        .filter_map(|(i, h)| {
+            [&h.alias, &h.hostname, &h.group, &h.user]
+                .iter()
+                .filter_map(|field| fuzzy_score(query, &field.to_lowercase()))
+                .max()
+                .map(|score| (i, score))
+        })
+        .collect();
+    // Stable sort keeps the group/alias order among equal scores
+    scored.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+    scored.into_iter().map(|(i, _)| i).collect()
+}
+
+/// Score `target` against `query` if every query char appears in order.
+/// Matches at the start, after a separator, or right after the previous
+/// match score higher, so `wprd` ranks `web-prod-1` above `www-backup-prd`.
+pub fn fuzzy_score(query: &str, target: &str) -> Option<i64> {
+    let target: Vec<char> = target.chars().collect();
+    let mut score = 0;
+    let mut pos = 0;
+    let mut last_match: Option<usize> = None;
+
+    for qc in query.chars() {
+        let offset = target[pos..].iter().position(|&tc| tc == qc)?;
+        let idx = pos + offset;
+
+        score += 1;
+        if idx == 0 {
+            score += 10;
+        } else if matches!(target[idx - 1], '-' | '_' | '.' | ' ') {
+            score += 5;
+        }
+        if last_match.is_some_and(|last| last + 1 == idx) {
+            score += 3;
+        }
+        // Penalise gaps so tighter matches win
+        score -= offset as i64;
+
+        last_match = Some(idx);
+        pos = idx + 1;
+    }
+
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{app_with, host};
+    use std::sync::mpsc;
+
+    fn ssh_config_host(alias: &str, group: &str) -> Host {
+        Host { source: HostSource::SshConfig, ..host(alias, group) }
+    }
+
+    #[test]
+    fn reloading_hosts_keeps_the_group_panel_open() {
+        let mut app = app_with(vec![host("a", "web"), host("b", "db")]);
+        let (tx, rx) = mpsc::channel();
+        app.reloads = Some(rx);
+        app.open_group_panel();
+        if let Mode::GroupManage(panel) = &mut app.mode {
+            panel.select_down();
+        }
+
+        tx.send(Reload::Hosts(vec![host("a", "web"), host("b", "db"), host("c", "cache")]))
+            .unwrap();
+        app.apply_reloads();
+
+        match &app.mode {
+            Mode::GroupManage(panel) => {
+                assert_eq!(panel.groups, ["cache", "db", "web"]);
+                assert_eq!(panel.current(), Some("web"));
+            }
+            _ => panic!("group panel was closed"),
+        }
+    }
+
+    #[test]
+    fn reloading_the_config_changes_the_refresh_concurrency() {
+        use std::sync::atomic::Ordering;
+
+        let mut app = app_with(vec![host("a", "web")]);
+        let (tx, rx) = mpsc::channel();
+        app.reloads = Some(rx);
+        let refresh = Arc::clone(&app.health_concurrency);
+        let config = Config { max_health_concurrency: 4, ..Config::default() };
+
+        tx.send(Reload::Config(Box::new(config))).unwrap();
+        app.apply_reloads();
+
+        assert_eq!(refresh.load(Ordering::Relaxed), 4);
+        assert_eq!(app.config.max_health_concurrency, 4);
+    }
+
+    #[test]
+    fn groups_of_ssh_config_hosts_are_not_renamed() {
+        let mut app = app_with(vec![ssh_config_host("a", "web"), host("b", "web")]);
+        app.open_group_panel();
+        if let Mode::GroupManage(panel) = &mut app.mode {
+            panel.action = GroupAction::Rename("frontend".into());
+        }
+
+        app.submit_group_input();
+
+        match &app.mode {
+            Mode::GroupManage(panel) => assert!(panel.error.is_some()),
+            _ => panic!("group panel was closed"),
+        }
+        let hosts = app.hosts.lock().unwrap();
+        assert!(hosts.iter().all(|h| h.group == "web"));
+    }
+
+    #[test]
+    fn preflight_skips_hosts_behind_a_proxy() {
+        let jumped = Host { proxy_jump: Some("bastion".into()), ..host("jumped", "a") };
+        let proxied = Host {
+            extra_ssh_options: vec!["-o".into(), "ProxyCommand=nc %h %p".into()],
+            ..host("proxied", "a")
+        };
+        let mut app = app_with(vec![jumped, proxied]);
+        app.config.preflight_check = true;
+
+        for idx in 0..2 {
+            // Neither resolves from here, but ssh would get there through the proxy
+            app.mode = Mode::Normal;
+            app.connect_to(idx, false, false);
+            assert!(app.preflight.is_none());
+            assert!(!matches!(app.mode, Mode::PreflightFailed { .. }));
+        }
+    }
+
+    #[test]
+    fn preflight_failures_arrive_without_blocking() {
+        // Nothing listens once the listener is dropped
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        drop(listener);
+        let closed = Host { hostname: "127.0.0.1".into(), port, ..host("closed", "a") };
+        let mut app = app_with(vec![closed]);
+        app.config.preflight_check = true;
+
+        app.connect_to(0, false, false);
+        assert!(app.preflight.is_some());
+        let start = Instant::now();
+        while app.preflight.is_some() && start.elapsed() < PREFLIGHT_TIMEOUT * 2 {
+            app.apply_preflight();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        match &app.mode {
+            Mode::PreflightFailed { index: 0, error } => {
+                assert!(matches!(error, PreflightError::ConnectionRefused), "{:?}", error)
+            }
+            _ => panic!("no pre-flight failure reported"),
+        }
+    }
+
+    #[test]
+    fn renaming_a_group_re_measures_the_columns() {
+        let mut app = app_with(vec![host("a", "web"), host("b", "db")]);
+        app.last_computed_widths = Some((80, ColumnId::DEFAULT.to_vec(), 0, vec![8; 7]));
+        app.open_group_panel();
+        if let Mode::GroupManage(panel) = &mut app.mode {
+            panel.select_down();
+            panel.action = GroupAction::Rename("frontend-servers".into());
+        }
+
+        app.submit_group_input();
+
+        assert!(app.hosts.lock().unwrap().iter().any(|h| h.group == "frontend-servers"));
+        assert!(app.last_computed_widths.is_none());
+    }
+
+    fn selected_alias(app: &App) -> Option<String> {
+        let idx = app.selected_host_index()?;
+        Some(app.hosts.lock().unwrap()[idx].alias.clone())
+    }
+
+    #[test]
+    fn select_by_alias_ignores_case_and_scrolls_back() {
+        let mut app = app_with(vec![host("web", "a"), host("db", "b"), host("cache", "b")]);
+        app.selected = app.list_entries().len() - 1;
+        app.scroll_offset = app.selected;
+
+        assert!(app.select_by_alias("WEB"));
+        assert_eq!(selected_alias(&app).as_deref(), Some("web"));
+        assert_eq!(app.scroll_offset, app.selected);
+
+        assert!(app.select_by_alias("cache"));
+        assert_eq!(selected_alias(&app).as_deref(), Some("cache"));
+    }
+
+    #[test]
+    fn select_by_alias_scrolls_just_far_enough() {
+        use ratatui::backend::TestBackend;
+        use ratatui::Terminal;
+
+        let hosts = (0..30).map(|i| host(&format!("h{:02}", i), "a")).collect();
+        let mut app = app_with(hosts);
+        let mut terminal = Terminal::new(TestBackend::new(120, 16)).unwrap();
+        let mut draw = |app: &mut App| {
+            terminal.draw(|f| crate::ui::render(f, app)).unwrap();
+        };
+        draw(&mut app);
+        assert_eq!(app.table_height, 6);
+
+        // Row 0 is the group header, so h20 is row 21, the last of rows 16-21
+        assert!(app.select_by_alias("h20"));
+        assert_eq!(app.selected, 21);
+        assert_eq!(app.scroll_offset, 16);
+        draw(&mut app);
+        assert_eq!(app.scroll_offset, 16);
+        assert_eq!(app.table_rows.last().map(|&(_, row)| row), Some(21));
+
+        assert!(app.select_by_alias("h02"));
+        assert_eq!(app.scroll_offset, 3);
+    }
+
+    #[test]
+    fn select_by_alias_only_finds_shown_hosts() {
+        let mut app = app_with(vec![host("web", "a"), host("db", "b")]);
+        assert!(!app.select_by_alias("nope"));
+
+        app.collapsed_groups.insert("b".into());
+        let before = app.selected;
+        assert!(!app.select_by_alias("db"));
+        assert_eq!(app.selected, before);
+
+        app.collapsed_groups.clear();
+        app.filter = "web".into();
+        assert!(!app.select_by_alias("db"));
+        assert!(app.select_by_alias("web"));
     }
 }
@@ -0,0 +1,57 @@
+use std::process::Command;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub struct BatchResult {
+    pub alias: String,
+    /// `None` while the command is still running on this host.
+    pub output: Option<String>,
+    pub success: bool,
+}
+
+pub type BatchResults = Arc<Mutex<Vec<BatchResult>>>;
+
+/// Run `command` on each `(alias, ssh_command)` pair in parallel. Results
+/// fill in as each host finishes so the overlay can show progress.
+pub fn run(targets: Vec<(String, Vec<String>)>, command: &str) -> BatchResults {
+    let results: BatchResults = Arc::new(Mutex::new(
+        targets
+            .iter()
+            .map(|(alias, _)| BatchResult {
+                alias: alias.clone(),
+                output: None,
+                success: false,
+            })
+            .collect(),
+    ));
+
+    for (i, (_, ssh)) in targets.into_iter().enumerate() {
+        let results = Arc::clone(&results);
+        let command = command.to_string();
+        thread::spawn(move || {
+            // BatchMode stops ssh prompting for passwords over the TUI
+            let output = Command::new(&ssh[0])
+                .arg("-o")
+                .arg("BatchMode=yes")
+                .args(&ssh[1..])
+                .arg("--")
+                .arg(&command)
+                .output();
+
+            let (text, success) = match output {
+                Ok(o) => {
+                    let mut text = String::from_utf8_lossy(&o.stdout).into_owned();
+                    text.push_str(&String::from_utf8_lossy(&o.stderr));
+                    (text, o.status.success())
+                }
+                Err(e) => (format!("failed to launch ssh: {}", e), false),
+            };
+
+            let mut r = results.lock().unwrap();
+            r[i].output = Some(text);
+            r[i].success = success;
+        });
+    }
+
+    results
+}
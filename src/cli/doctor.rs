@@ -0,0 +1,229 @@
+use crate::config::Config;
+use crate::host::{self, Host, HostSource};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// What one check found: a note on success, or the problem and how to fix it.
+type Outcome = Result<String, (String, String)>;
+
+/// Run every self-check, print a ✓/✗ line for each and exit 1 if any failed.
+pub fn run() -> anyhow::Result<()> {
+    let hosts = host::load_hosts();
+    let checks: Vec<(&str, Outcome)> = vec![
+        ("SSH config", ssh_config()),
+        ("config.toml", config_toml()),
+        ("Host file", host_file()),
+        ("Config directory", config_dir_writable()),
+        ("ping", ping_binary()),
+        ("Hosts loaded", hosts_loaded(&hosts)),
+        ("Aliases", duplicate_aliases()),
+        ("Host entries", host_entries(&hosts)),
+    ];
+
+    let mut failed = 0;
+    for (name, outcome) in checks {
+        match outcome {
+            Ok(note) => println!("✓ {}: {}", name, note),
+            Err((problem, fix)) => {
+                failed += 1;
+                println!("✗ {}: {}", name, problem);
+                println!("    fix: {}", fix);
+            }
+        }
+    }
+
+    if failed > 0 {
+        println!("\n{} of the checks failed", failed);
+        std::process::exit(1);
+    }
+    println!("\nAll checks passed");
+    Ok(())
+}
+
+fn ssh_config() -> Outcome {
+    let path = host::ssh_config_path();
+    match fs::read_to_string(&path) {
+        Ok(content) => {
+            let entries = content
+                .lines()
+                .filter(|l| l.trim_start().to_lowercase().starts_with("host "))
+                .count();
+            Ok(format!("{} ({} Host entries)", path.display(), entries))
+        }
+        Err(e) => Err((
+            format!("cannot read {}: {}", path.display(), e),
+            "create it, or add hosts with `sshmap add` instead".to_string(),
+        )),
+    }
+}
+
+/// Parsed here rather than through `Config::load`, so what is checked is
+/// exactly the file on disk.
+fn config_toml() -> Outcome {
+    let path = Config::path();
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(format!("{} not created yet; using the defaults", path.display()));
+        }
+        Err(e) => {
+            return Err((
+                format!("cannot read {}: {}", path.display(), e),
+                format!("check the ownership and permissions of {}", path.display()),
+            ));
+        }
+    };
+    match toml::from_str::<Config>(&content) {
+        Ok(_) => Ok(format!("{} parses", path.display())),
+        Err(e) => Err((
+            format!("{}: {}", path.display(), e),
+            format!("fix the syntax error, or delete {} to get the defaults", path.display()),
+        )),
+    }
+}
+
+fn host_file() -> Outcome {
+    let path = host::active_hosts_path();
+    match host::check_sshmap_config() {
+        Ok(()) if path.exists() => Ok(format!("{} parses", path.display())),
+        Ok(()) => Ok(format!("{} not created yet", path.display())),
+        Err(e) => Err((
+            format!("{:#}", e),
+            format!("fix the syntax error in {}", path.display()),
+        )),
+    }
+}
+
+fn config_dir_writable() -> Outcome {
+    let dir = host::sshmap_config_dir();
+    // The directory is created on first save, so its nearest existing
+    // ancestor is what has to be writable
+    let existing = dir.ancestors().find(|d| d.exists()).unwrap_or(Path::new("/"));
+    let probe = existing.join(".sshmap-doctor");
+    match fs::write(&probe, b"") {
+        Ok(()) => {
+            let _ = fs::remove_file(&probe);
+            Ok(format!("{} is writable", dir.display()))
+        }
+        Err(e) => Err((
+            format!("cannot write to {}: {}", existing.display(), e),
+            format!("check the ownership and permissions of {}", existing.display()),
+        )),
+    }
+}
+
+/// The config.toml line that swaps ICMP checks for one ping doesn't run.
+const NO_PING_METHOD: &str = "health_check_method = \"ssh_banner\"";
+
+fn ping_binary() -> Outcome {
+    let paths = std::env::var_os("PATH").unwrap_or_default();
+    let found = std::env::split_paths(&paths)
+        .map(|dir| dir.join("ping"))
+        .find(|path| is_executable(path));
+    match found {
+        Some(path) => Ok(format!("found at {}", path.display())),
+        None => Err((
+            "no executable `ping` on PATH; ICMP checks will report hosts down".to_string(),
+            format!("install ping (iputils-ping) or set {}", NO_PING_METHOD),
+        )),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+fn hosts_loaded(hosts: &[Host]) -> Outcome {
+    if hosts.is_empty() {
+        Err((
+            "no hosts found".to_string(),
+            "add Host entries to ~/.ssh/config or run `sshmap add`".to_string(),
+        ))
+    } else {
+        Ok(format!("{} hosts", hosts.len()))
+    }
+}
+
+/// `load_hosts` quietly keeps the first of two hosts with the same alias,
+/// so look at ~/.ssh/config and sshmap's own file directly.
+fn duplicate_aliases() -> Outcome {
+    let in_ssh_config = host::duplicate_ssh_config_aliases();
+    let owned = host::load_owned_hosts().unwrap_or_default();
+    let ssh_aliases: HashSet<String> = host::load_hosts()
+        .into_iter()
+        .filter(|h| h.source == HostSource::SshConfig)
+        .map(|h| h.alias)
+        .collect();
+
+    let mut seen = HashSet::new();
+    let in_host_file: Vec<&str> = owned
+        .iter()
+        .filter(|h| !seen.insert(h.alias.as_str()) || ssh_aliases.contains(&h.alias))
+        .map(|h| h.alias.as_str())
+        .collect();
+
+    let mut files = Vec::new();
+    if !in_ssh_config.is_empty() {
+        files.push("~/.ssh/config".to_string());
+    }
+    if !in_host_file.is_empty() {
+        files.push(host::active_hosts_path().display().to_string());
+    }
+    let mut duplicates: Vec<&str> = in_ssh_config.iter().map(String::as_str).collect();
+    duplicates.extend(in_host_file);
+    duplicates.sort();
+    duplicates.dedup();
+
+    if duplicates.is_empty() {
+        Ok("no duplicates".to_string())
+    } else {
+        Err((
+            format!("defined more than once: {}", duplicates.join(", ")),
+            format!(
+                "rename or remove the extra entries in {}; only the first is used",
+                files.join(" and ")
+            ),
+        ))
+    }
+}
+
+/// `validate_host` on every host: hostnames, ports, identity files and so on.
+fn host_entries(hosts: &[Host]) -> Outcome {
+    let problems: Vec<String> = hosts
+        .iter()
+        .flat_map(|h| {
+            host::validate_host(h)
+                .into_iter()
+                .map(move |e| format!("{}: {}", h.alias, e))
+        })
+        .collect();
+    if problems.is_empty() {
+        Ok("all valid".to_string())
+    } else {
+        Err((
+            problems.join("; "),
+            "fix these in ~/.ssh/config or with `e` in the TUI".to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health::CheckMethod;
+
+    #[test]
+    fn the_suggested_check_method_parses() {
+        let config: Config = toml::from_str(NO_PING_METHOD).unwrap();
+        assert_eq!(config.health_check_method, CheckMethod::SshBanner);
+    }
+}
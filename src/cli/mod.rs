@@ -0,0 +1,763 @@
+mod doctor;
+
+use crate::app::fuzzy_score;
+use crate::config::{self, Config};
+use crate::health::{self, CheckMethod};
+use crate::host::{self, Host, HostSource, HostStatus};
+use crate::state::AppState;
+use anyhow::{bail, Context};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::engine::{ArgValueCandidates, CompletionCandidate};
+use clap_complete::env::EnvCompleter;
+use clap_complete::{CompleteEnv, Shell};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// SSH connection manager: browse, group, health-check and connect.
+/// Run without a subcommand to open the TUI.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// Settings file to use instead of ~/.config/sshmap/config.toml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Host file to use instead of ~/.config/sshmap/hosts.{toml,json}
+    #[arg(long, global = true, value_name = "PATH")]
+    pub hosts: Option<PathBuf>,
+    /// Where to write the log when SSHMAP_LOG is set
+    /// (default ~/.config/sshmap/sshmap.log)
+    #[arg(long, global = true, value_name = "PATH")]
+    pub log_file: Option<PathBuf>,
+    /// Never touch the terminal: print the host list, or run the subcommand
+    #[arg(long, global = true)]
+    pub no_tui: bool,
+    /// SSH to this host straight away instead of opening the TUI
+    #[arg(long, value_name = "ALIAS", add = ArgValueCandidates::new(alias_candidates))]
+    pub connect: Option<String>,
+    /// Open the TUI with this filter already typed in
+    #[arg(long, value_name = "QUERY")]
+    pub filter: Option<String>,
+    /// Open the TUI showing only this group
+    #[arg(long)]
+    pub group: Option<String>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Print the host list
+    List(ListArgs),
+    /// SSH to a host by alias
+    Connect {
+        #[arg(add = ArgValueCandidates::new(alias_candidates))]
+        alias: String,
+        /// Print the ssh command instead of running it
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check one host; exits 0 if up, 1 if down, 2 if unknown
+    Ping(PingArgs),
+    /// Check every host and summarise; exits 1 unless all are up
+    PingAll(PingAllArgs),
+    /// Add a host to sshmap's host file
+    Add(AddArgs),
+    /// Remove a host from sshmap's host file
+    Remove { alias: String },
+    /// Merge hosts from another inventory into sshmap's host file
+    Import(ImportArgs),
+    /// Write the host list in another format
+    Export {
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::SshConfig)]
+        format: ExportFormat,
+        /// Write to this file instead of stdout
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Same as `export --format ssh-config`
+    ExportSshConfig {
+        #[arg(short, long, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Check the configuration for problems
+    Doctor,
+    /// Print the shell completion script (install instructions go to stderr)
+    Completions { shell: Shell },
+}
+
+#[derive(Args, Default)]
+pub struct ListArgs {
+    #[arg(short, long, value_enum, default_value_t = ListFormat::Table)]
+    format: ListFormat,
+    /// Only hosts matching this, as the TUI filter does (`#tag` for tags)
+    #[arg(long)]
+    filter: Option<String>,
+    /// Only hosts with this health status
+    #[arg(long, value_enum, requires = "ping")]
+    status: Option<StatusFilter>,
+    /// Check every listed host first and show its status
+    #[arg(long)]
+    ping: bool,
+}
+
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum ListFormat {
+    /// Aligned columns
+    #[default]
+    Table,
+    /// Array of host objects
+    Json,
+    Csv,
+    /// One alias per line
+    Aliases,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum StatusFilter {
+    Up,
+    Down,
+    Unknown,
+}
+
+impl StatusFilter {
+    fn of(status: &HostStatus) -> Self {
+        match status {
+            s if s.is_up() => StatusFilter::Up,
+            HostStatus::Unknown | HostStatus::Checking => StatusFilter::Unknown,
+            _ => StatusFilter::Down,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct PingArgs {
+    #[arg(add = ArgValueCandidates::new(alias_candidates))]
+    alias: String,
+    /// Check method, instead of the host's or the configured one
+    #[arg(short, long, value_enum)]
+    method: Option<PingMethod>,
+    /// Repeat the check this many times and print statistics
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    count: u32,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum PingMethod {
+    Icmp,
+    /// Connect to the host's SSH port
+    Tcp,
+    /// Read the SSH banner
+    Ssh,
+    /// GET http://<hostname>/ and expect 200
+    Http,
+}
+
+impl PingMethod {
+    fn for_host(self, host: &Host) -> CheckMethod {
+        match self {
+            PingMethod::Icmp => CheckMethod::Icmp,
+            PingMethod::Tcp => CheckMethod::TcpPort(host.port),
+            PingMethod::Ssh => CheckMethod::SshBanner,
+            PingMethod::Http => CheckMethod::Http {
+                url: format!("http://{}/", host.hostname),
+                expected_status: 200,
+            },
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct PingAllArgs {
+    #[arg(short, long, value_enum, default_value_t = SummaryFormat::Table)]
+    format: SummaryFormat,
+    /// Checks in flight at once (default: max_health_concurrency)
+    #[arg(long)]
+    concurrency: Option<usize>,
+    /// Stop waiting after this many seconds; unfinished hosts count as unknown
+    #[arg(long, value_name = "SECS")]
+    timeout: Option<u64>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SummaryFormat {
+    Table,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ExportFormat {
+    SshConfig,
+    Csv,
+    Json,
+}
+
+#[derive(Args)]
+pub struct AddArgs {
+    alias: String,
+    hostname: String,
+    #[arg(short, long)]
+    user: Option<String>,
+    #[arg(short, long, default_value_t = 22)]
+    port: u16,
+    #[arg(short, long)]
+    group: Option<String>,
+    /// Repeat to give several keys, tried in order
+    #[arg(short, long, value_name = "PATH")]
+    identity_file: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ImportArgs {
+    #[arg(short, long, value_enum)]
+    format: ImportFormat,
+    /// Inventory or state file (ansible, terraform)
+    path: Option<PathBuf>,
+    /// AWS region (aws)
+    #[arg(long)]
+    region: Option<String>,
+    /// AWS CLI profile (aws)
+    #[arg(long)]
+    profile: Option<String>,
+    /// kubectl context (kubectl)
+    #[arg(long)]
+    context: Option<String>,
+    /// GCP project (gcloud)
+    #[arg(long)]
+    project: Option<String>,
+    /// Compute Engine zone (gcloud)
+    #[arg(long)]
+    zone: Option<String>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ImportFormat {
+    /// INI or YAML inventory, told apart by the file extension
+    Ansible,
+    /// terraform.tfstate
+    Terraform,
+    /// Running EC2 instances, via the aws CLI
+    Aws,
+    /// Cluster nodes, via kubectl
+    Kubectl,
+    /// Active droplets, via doctl
+    #[value(name = "digitalocean")]
+    DigitalOcean,
+    /// Compute Engine VMs, via gcloud
+    Gcloud,
+}
+
+/// The known aliases, with their hostnames, for completing an alias
+/// argument. Read on every completion, so new hosts show up straight away.
+fn alias_candidates() -> Vec<CompletionCandidate> {
+    host::load_hosts()
+        .into_iter()
+        .map(|h| CompletionCandidate::new(h.alias).help(Some(h.hostname.into())))
+        .collect()
+}
+
+/// Answer a completion request from the script `sshmap completions` prints,
+/// and exit; a normal run carries on.
+pub fn complete_from_env() {
+    CompleteEnv::with_factory(Cli::command).complete();
+}
+
+impl Cli {
+    /// Point config and host loading at the files given on the command line.
+    pub fn apply_paths(&self) {
+        if let Some(ref path) = self.config {
+            config::set_config_file(path.clone());
+        }
+        if let Some(ref path) = self.hosts {
+            host::set_hosts_file(path.clone());
+        }
+    }
+}
+
+/// Run a non-interactive subcommand.
+pub fn run(command: Command) -> anyhow::Result<()> {
+    match command {
+        Command::List(args) => run_list(&args),
+        Command::Connect { alias, dry_run } => connect(&alias, dry_run),
+        Command::Ping(args) => ping(&args),
+        Command::PingAll(args) => ping_all(&args),
+        Command::Add(args) => add(args),
+        Command::Remove { alias } => remove(&alias),
+        Command::Import(args) => {
+            let hosts = import(args)?;
+            let (added, skipped) = host::import_hosts(hosts)?;
+            println!("Imported {} hosts ({} already known, skipped)", added, skipped);
+            Ok(())
+        }
+        Command::Export { format, output } => {
+            let hosts = host::load_hosts();
+            let text = match format {
+                ExportFormat::SshConfig => host::export_ssh_config(&hosts),
+                ExportFormat::Csv => host::export_csv(&hosts),
+                ExportFormat::Json => serde_json::to_string_pretty(&hosts)? + "\n",
+            };
+            write_output(output, &text)
+        }
+        Command::ExportSshConfig { output } => {
+            write_output(output, &host::export_ssh_config(&host::load_hosts()))
+        }
+        Command::Doctor => doctor::run(),
+        Command::Completions { shell } => {
+            completion_completer(shell)?.write_registration(
+                "COMPLETE",
+                "sshmap",
+                "sshmap",
+                "sshmap",
+                &mut std::io::stdout(),
+            )?;
+            eprintln!("{}", completion_instructions(shell));
+            Ok(())
+        }
+    }
+}
+
+pub fn run_list(args: &ListArgs) -> anyhow::Result<()> {
+    let mut hosts = host::load_hosts();
+    if let Some(ref query) = args.filter {
+        let query = query.to_lowercase();
+        hosts.retain(|h| h.matches_filter(&query));
+    }
+    if args.ping {
+        hosts = check_hosts(hosts)?;
+    }
+    if let Some(wanted) = args.status {
+        hosts.retain(|h| StatusFilter::of(&h.status) == wanted);
+    }
+
+    match args.format {
+        ListFormat::Table => print_table(&hosts, args.ping),
+        ListFormat::Json => println!("{}", serde_json::to_string_pretty(&hosts)?),
+        ListFormat::Csv => print!("{}", host::export_csv(&hosts)),
+        ListFormat::Aliases => {
+            for h in &hosts {
+                println!("{}", h.alias);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Replace this process with ssh to `alias`, or print what would run.
+pub fn connect(alias: &str, dry_run: bool) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    let hosts = host::load_hosts();
+    let Some(host) = hosts.iter().find(|h| h.alias == alias) else {
+        eprintln!("sshmap: no host named '{}'", alias);
+        let similar = similar_aliases(&hosts, alias);
+        if !similar.is_empty() {
+            eprintln!("Did you mean: {}", similar.join(", "));
+        }
+        std::process::exit(1);
+    };
+    let cmd = config.ssh_command(host, false);
+    if dry_run {
+        println!("{}", cmd.join(" "));
+        return Ok(());
+    }
+    if let Err(e) = AppState::select_next_time(&host.alias) {
+        tracing::warn!("can't save the selected host: {:#}", e);
+    }
+
+    let mut command = std::process::Command::new(&cmd[0]);
+    command.args(&cmd[1..]).envs(&host.environment);
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        // exec only returns on failure
+        let err = command.exec();
+        Err(err).with_context(|| format!("running {}", cmd[0]))
+    }
+    #[cfg(not(unix))]
+    {
+        let status = command.status().with_context(|| format!("running {}", cmd[0]))?;
+        std::process::exit(status.code().unwrap_or(1));
+    }
+}
+
+/// Up to five aliases close to `alias`, best first.
+fn similar_aliases(hosts: &[Host], alias: &str) -> Vec<String> {
+    let query = alias.to_lowercase();
+    let mut scored: Vec<(i64, &str)> = hosts
+        .iter()
+        .filter_map(|h| {
+            let target = h.alias.to_lowercase();
+            let score = fuzzy_score(&query, &target)
+                .or_else(|| fuzzy_score(&target, &query))
+                .or_else(|| target.contains(&query).then_some(0))?;
+            Some((score, h.alias.as_str()))
+        })
+        .collect();
+    scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+    scored.into_iter().take(5).map(|(_, a)| a.to_string()).collect()
+}
+
+/// Check one host `count` times and exit 0 if it was ever up, 1 if it was
+/// only ever down, 2 if every check came back unknown.
+fn ping(args: &PingArgs) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    health::configure(&config);
+    let mut host = match find_host(&host::load_hosts(), &args.alias) {
+        Ok(host) => host,
+        Err(e) => {
+            eprintln!("sshmap: {}", e);
+            std::process::exit(2);
+        }
+    };
+    if let Some(method) = args.method {
+        host.health_check_method = Some(method.for_host(&host));
+        host.health_check_port = None;
+    }
+
+    let hosts = Arc::new(Mutex::new(vec![host]));
+    let mut statuses = Vec::new();
+    for _ in 0..args.count {
+        health::wait(health::check_one(Arc::clone(&hosts), 0));
+        let host = hosts.lock().unwrap()[0].clone();
+        println!("{} {}", host.alias, status_text(&host));
+        statuses.push(host.status);
+    }
+
+    if args.count > 1 {
+        let up = statuses.iter().filter(|s| s.is_up()).count();
+        let rtts: Vec<f64> = statuses.iter().filter_map(HostStatus::rtt).collect();
+        println!(
+            "--- {} checks, {} up, {:.0}% failed",
+            statuses.len(),
+            up,
+            100.0 * (statuses.len() - up) as f64 / statuses.len() as f64
+        );
+        if !rtts.is_empty() {
+            let min = rtts.iter().cloned().fold(f64::INFINITY, f64::min);
+            let max = rtts.iter().cloned().fold(0.0, f64::max);
+            let avg = rtts.iter().sum::<f64>() / rtts.len() as f64;
+            println!("rtt min/avg/max = {:.1}/{:.1}/{:.1} ms", min, avg, max);
+        }
+    }
+
+    let code = if statuses.iter().any(HostStatus::is_up) {
+        0
+    } else if statuses.iter().all(|s| StatusFilter::of(s) == StatusFilter::Unknown) {
+        2
+    } else {
+        1
+    };
+    std::process::exit(code);
+}
+
+/// Check every host, print the counts and the hosts that are not up, and
+/// exit 1 unless every host is up.
+fn ping_all(args: &PingAllArgs) -> anyhow::Result<()> {
+    let config = Config::load()?;
+    health::configure(&config);
+    let hosts = Arc::new(Mutex::new(host::load_hosts()));
+    let concurrency = args.concurrency.unwrap_or(config.max_health_concurrency).max(1);
+    let handle = health::check_all_bounded(Arc::clone(&hosts), concurrency);
+    let finished = match args.timeout {
+        Some(secs) => health::wait_timeout(handle, Duration::from_secs(secs)),
+        None => {
+            health::wait(handle);
+            true
+        }
+    };
+    let hosts = hosts.lock().unwrap().clone();
+
+    let count = |wanted| hosts.iter().filter(|h| StatusFilter::of(&h.status) == wanted).count();
+    let (up, down, unknown) = (
+        count(StatusFilter::Up),
+        count(StatusFilter::Down),
+        count(StatusFilter::Unknown),
+    );
+    let not_up: Vec<&Host> = hosts.iter().filter(|h| !h.status.is_up()).collect();
+
+    match args.format {
+        SummaryFormat::Table => {
+            println!(
+                "{} hosts: {} up, {} down, {} unknown",
+                hosts.len(),
+                up,
+                down,
+                unknown
+            );
+            if !finished {
+                println!("Timed out before every check finished");
+            }
+            if !not_up.is_empty() {
+                let alias_w = not_up.iter().map(|h| h.alias.len()).max().unwrap_or(0).max(5);
+                let host_w = not_up.iter().map(|h| h.hostname.len()).max().unwrap_or(0).max(8);
+                println!();
+                println!("{:<alias_w$}  {:<host_w$}  STATUS", "ALIAS", "HOSTNAME");
+                for h in &not_up {
+                    println!("{:<alias_w$}  {:<host_w$}  {}", h.alias, h.hostname, status_text(h));
+                }
+            }
+        }
+        SummaryFormat::Json => {
+            let not_up: Vec<_> = not_up
+                .iter()
+                .map(|h| {
+                    serde_json::json!({
+                        "alias": h.alias,
+                        "hostname": h.hostname,
+                        "status": status_text(h),
+                    })
+                })
+                .collect();
+            let summary = serde_json::json!({
+                "total": hosts.len(),
+                "up": up,
+                "down": down,
+                "unknown": unknown,
+                "timed_out": !finished,
+                "not_up": not_up,
+            });
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        }
+    }
+
+    if up < hosts.len() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+/// The script for `shell` calls back into `sshmap` with COMPLETE set, so
+/// completions always reflect the current host list.
+fn completion_completer(shell: Shell) -> anyhow::Result<&'static dyn EnvCompleter> {
+    use clap_complete::env;
+    Ok(match shell {
+        Shell::Bash => &env::Bash,
+        Shell::Zsh => &env::Zsh,
+        Shell::Fish => &env::Fish,
+        Shell::Elvish => &env::Elvish,
+        Shell::PowerShell => &env::Powershell,
+        _ => bail!("no completion support for {}", shell),
+    })
+}
+
+/// Loading the script at shell startup keeps it in step with upgrades.
+fn completion_instructions(shell: Shell) -> String {
+    let install = match shell {
+        Shell::Bash => "echo 'source <(COMPLETE=bash sshmap)' >> ~/.bashrc",
+        Shell::Zsh => "echo 'source <(COMPLETE=zsh sshmap)' >> ~/.zshrc",
+        Shell::Fish => {
+            "echo 'COMPLETE=fish sshmap | source' > ~/.config/fish/completions/sshmap.fish"
+        }
+        Shell::Elvish => {
+            "echo 'eval (E:COMPLETE=elvish sshmap | slurp)' >> ~/.config/elvish/rc.elv"
+        }
+        Shell::PowerShell => {
+            "echo '$env:COMPLETE = \"powershell\"; sshmap | Out-String | Invoke-Expression; \
+             Remove-Item Env:\\COMPLETE' >> $PROFILE"
+        }
+        _ => return "# Save this script where your shell loads completions from".to_string(),
+    };
+    format!("# Install with: {}", install)
+}
+
+fn add(args: AddArgs) -> anyhow::Result<()> {
+    if host::load_hosts().iter().any(|h| h.alias == args.alias) {
+        bail!("alias '{}' is already in use", args.alias);
+    }
+    let user = match args.user {
+        Some(user) => user,
+        None => Config::load()?.default_user,
+    };
+    let new = Host {
+        alias: args.alias.clone(),
+        hostname: args.hostname,
+        user,
+        port: args.port,
+        identity_files: args.identity_file,
+        group: args.group.unwrap_or_else(|| host::DEFAULT_GROUP.to_string()),
+        ..Default::default()
+    };
+    let errors = host::validate_host(&new);
+    if !errors.is_empty() {
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        bail!("{}", messages.join("; "));
+    }
+    let mut hosts = host::load_owned_hosts()?;
+    hosts.push(new);
+    host::sort_hosts(&mut hosts);
+    host::save_sshmap_config(&hosts)?;
+    println!("Added {}", args.alias);
+    Ok(())
+}
+
+fn remove(alias: &str) -> anyhow::Result<()> {
+    let mut hosts = host::load_owned_hosts()?;
+    let before = hosts.len();
+    hosts.retain(|h| h.alias != alias);
+    if hosts.len() == before {
+        let in_ssh_config = host::load_hosts()
+            .iter()
+            .any(|h| h.alias == alias && h.source == HostSource::SshConfig);
+        if in_ssh_config {
+            bail!("'{}' is defined in ~/.ssh/config; remove it there", alias);
+        }
+        bail!("no host named '{}'", alias);
+    }
+    host::save_sshmap_config(&hosts)?;
+    println!("Removed {}", alias);
+    Ok(())
+}
+
+fn import(args: ImportArgs) -> anyhow::Result<Vec<Host>> {
+    let path = || args.path.clone().context("this format needs a path");
+    match args.format {
+        ImportFormat::Ansible => {
+            let path = path()?;
+            let ext = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+            if matches!(ext, "yml" | "yaml") {
+                host::import_from_ansible_yaml(&path)
+            } else {
+                host::import_from_ansible_ini(&path)
+            }
+        }
+        ImportFormat::Terraform => host::import_from_terraform_state(&path()?),
+        ImportFormat::Aws => {
+            let region = args.region.as_deref().context("--format aws needs --region")?;
+            host::import_from_aws(region, args.profile.as_deref())
+        }
+        ImportFormat::Kubectl => {
+            let user = Config::load()?.kubectl_default_user;
+            host::import_from_kubectl(args.context.as_deref(), &user)
+        }
+        ImportFormat::DigitalOcean => {
+            let config = Config::load()?;
+            host::import_from_do(&config.digitalocean_default_user, config.doctl_profile.as_deref())
+        }
+        ImportFormat::Gcloud => {
+            host::import_from_gcloud(args.project.as_deref(), args.zone.as_deref())
+        }
+    }
+}
+
+fn find_host(hosts: &[Host], alias: &str) -> anyhow::Result<Host> {
+    hosts
+        .iter()
+        .find(|h| h.alias == alias)
+        .cloned()
+        .with_context(|| format!("no host named '{}'", alias))
+}
+
+/// Run a health check on each of `hosts` and wait for them all.
+fn check_hosts(hosts: Vec<Host>) -> anyhow::Result<Vec<Host>> {
+    let config = Config::load()?;
+    health::configure(&config);
+    let hosts = Arc::new(Mutex::new(hosts));
+    health::wait(health::check_all(Arc::clone(&hosts)));
+    let hosts = hosts.lock().unwrap().clone();
+    Ok(hosts)
+}
+
+fn status_text(host: &Host) -> String {
+    let label = match host.status {
+        ref s if s.is_up() => "UP",
+        HostStatus::Timeout => "TIMEOUT",
+        HostStatus::DnsError => "DNS ERROR",
+        HostStatus::TlsExpired => "CERT EXPIRED",
+        HostStatus::Unknown | HostStatus::Checking => "UNKNOWN",
+        _ => "DOWN",
+    };
+    if host.status.is_up() {
+        format!("{} {}", label, host.rtt_label())
+    } else {
+        label.to_string()
+    }
+}
+
+fn write_output(output: Option<PathBuf>, text: &str) -> anyhow::Result<()> {
+    match output {
+        Some(path) => fs::write(&path, text).with_context(|| format!("writing {}", path.display())),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn print_table(hosts: &[Host], with_status: bool) {
+    let width = |f: fn(&Host) -> &str, title: &str| {
+        hosts.iter().map(|h| f(h).chars().count()).chain([title.len()]).max().unwrap_or(0)
+    };
+    let alias_w = width(|h| &h.alias, "ALIAS");
+    let host_w = width(|h| &h.hostname, "HOSTNAME");
+    let user_w = width(|h| &h.user, "USER");
+    let group_w = width(|h| &h.group, "GROUP");
+    let status_col = |text: &str| if with_status { format!("  {}", text) } else { String::new() };
+    println!(
+        "{:<alias_w$}  {:<host_w$}  {:<user_w$}  {:>5}  {:<group_w$}{}",
+        "ALIAS",
+        "HOSTNAME",
+        "USER",
+        "PORT",
+        "GROUP",
+        status_col("STATUS")
+    );
+    for h in hosts {
+        let line = format!(
+            "{:<alias_w$}  {:<host_w$}  {:<user_w$}  {:>5}  {:<group_w$}{}",
+            h.alias,
+            h.hostname,
+            h.user,
+            h.port,
+            h.group,
+            status_col(&status_text(h))
+        );
+        println!("{}", line.trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::isolated_home;
+    use clap_complete::engine;
+    use std::ffi::OsString;
+
+    /// What the completer offers for the last of `args`.
+    fn complete(args: &[&str]) -> Vec<String> {
+        let args: Vec<OsString> = args.iter().map(OsString::from).collect();
+        let index = args.len() - 1;
+        engine::complete(&mut Cli::command(), args, index, None)
+            .unwrap()
+            .into_iter()
+            .map(|c| c.get_value().to_string_lossy().into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn aliases_are_completed_from_the_current_host_list() {
+        let ssh_dir = isolated_home().join(".ssh");
+        fs::create_dir_all(&ssh_dir).unwrap();
+        fs::write(ssh_dir.join("config"), "Host web\nHost db\nHost web-2\n").unwrap();
+
+        let offered = complete(&["sshmap", "connect", ""]);
+        for alias in ["web", "db", "web-2"] {
+            assert!(offered.iter().any(|o| o == alias), "{} not in {:?}", alias, offered);
+        }
+        assert_eq!(complete(&["sshmap", "--connect", "we"]), ["web", "web-2"]);
+        assert_eq!(complete(&["sshmap", "--connect=d"]), ["--connect=db"]);
+    }
+
+    #[test]
+    fn the_completion_script_calls_back_into_sshmap() {
+        for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::Elvish, Shell::PowerShell] {
+            let mut script = Vec::new();
+            completion_completer(shell)
+                .unwrap()
+                .write_registration("COMPLETE", "sshmap", "sshmap", "sshmap", &mut script)
+                .unwrap();
+            let script = String::from_utf8(script).unwrap();
+            assert!(!script.trim().is_empty(), "{} script is empty", shell);
+            assert!(script.contains("COMPLETE"), "{} script: {}", shell, script);
+        }
+    }
+}
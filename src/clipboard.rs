@@ -0,0 +1,25 @@
+//! System clipboard access, compiled in with the `clipboard` feature.
+
+#[cfg(feature = "clipboard")]
+pub fn copy(text: &str) -> Result<(), String> {
+    use std::sync::Mutex;
+
+    // On X11 the copied text is served by the process that owns the
+    // clipboard handle, so keep it alive for the life of the app.
+    static CLIPBOARD: Mutex<Option<arboard::Clipboard>> = Mutex::new(None);
+
+    let mut guard = CLIPBOARD.lock().unwrap();
+    if guard.is_none() {
+        *guard = Some(arboard::Clipboard::new().map_err(|e| e.to_string())?);
+    }
+    guard
+        .as_mut()
+        .unwrap()
+        .set_text(text)
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "clipboard"))]
+pub fn copy(_text: &str) -> Result<(), String> {
+    Err("built without clipboard support".into())
+}
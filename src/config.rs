@@ -0,0 +1,227 @@
+use crate::app::ConnectTarget;
+use crate::health::{self, CheckMethod};
+use crate::host::{self, Host, MergeStrategy};
+use crate::keys::Keybindings;
+use crate::theme::Theme;
+use crate::watch;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Re-run `check_all` this often; unset means only on demand.
+    pub health_check_interval_secs: Option<u64>,
+    pub health_check_method: CheckMethod,
+    /// Echo requests per ICMP check; RTT is averaged over the replies.
+    pub ping_count: u8,
+    /// When ICMP gets no answer, try the SSH port before calling a host down.
+    pub fallback_tcp_check: bool,
+    /// Stop waiting for a host's check after this long and mark it timed out.
+    /// A hung check can't be cancelled; it finishes in the background and
+    /// its result is dropped.
+    pub health_timeout_secs: u64,
+    /// Pop up a desktop notification when a host goes down or comes back.
+    pub desktop_notifications: bool,
+    /// Shell commands run when a host goes down or comes back up, with
+    /// `%alias`, `%hostname` and `%group` filled in already quoted.
+    pub on_host_down: Option<String>,
+    pub on_host_up: Option<String>,
+    /// Append every up/down transition to `~/.config/sshmap/health.log`.
+    pub health_log: bool,
+    /// Size at which `health.log` is moved aside to `health.log.1`.
+    pub health_log_max_mb: u32,
+    /// Serve Prometheus metrics on this port (0 disables; needs the
+    /// `metrics` feature).
+    pub metrics_port: u16,
+    /// Address the metrics endpoint listens on; loopback unless set to
+    /// e.g. `0.0.0.0` for scraping from other machines, since the metrics
+    /// name every host and group.
+    pub metrics_bind: String,
+    /// Upper bound on health checks in flight at once.
+    #[serde(alias = "max_ping_concurrency")]
+    pub max_health_concurrency: usize,
+    pub default_user: String,
+    pub ssh_extra_args: Vec<String>,
+    /// User given to nodes imported with `sshmap import --format kubectl`.
+    pub kubectl_default_user: String,
+    /// User given to droplets imported with `sshmap import --format digitalocean`.
+    pub digitalocean_default_user: String,
+    /// doctl credentials for that import: a doctl config file (`--config`,
+    /// `~` allowed) if this names one, otherwise an API token, handed over
+    /// as `DIGITALOCEAN_ACCESS_TOKEN`.
+    pub doctl_profile: Option<String>,
+    /// Try a TCP connect to the host's SSH port before launching ssh, and
+    /// report the failure instead of starting a session that can't connect.
+    /// Hosts reached through a jump host or ProxyCommand go straight to ssh.
+    pub preflight_check: bool,
+    /// Connect with mosh rather than ssh on Enter, as if every host set `use_mosh`.
+    pub prefer_mosh: bool,
+    /// Record every ssh session with `script`, as if connecting with R.
+    pub record_sessions: bool,
+    /// Where Enter opens sessions when running inside tmux; unset asks each time.
+    pub connect_target: Option<ConnectTarget>,
+    /// How a host defined in both ~/.ssh/config and sshmap's file is loaded.
+    pub merge_strategy: MergeStrategy,
+    /// Up on the first host goes to the last and Down on the last to the
+    /// first; paging from either end does the same.
+    pub wrap_navigation: bool,
+    /// Display order of groups; groups not listed follow alphabetically.
+    pub group_order: Vec<String>,
+    pub theme: Theme,
+    pub keybindings: Keybindings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            health_check_interval_secs: None,
+            health_check_method: CheckMethod::Icmp,
+            ping_count: health::DEFAULT_PING_COUNT,
+            fallback_tcp_check: true,
+            health_timeout_secs: health::DEFAULT_HEALTH_TIMEOUT_SECS,
+            desktop_notifications: false,
+            on_host_down: None,
+            on_host_up: None,
+            health_log: false,
+            health_log_max_mb: 10,
+            metrics_port: 0,
+            metrics_bind: "127.0.0.1".into(),
+            max_health_concurrency: health::DEFAULT_MAX_CONCURRENCY,
+            default_user: String::new(),
+            ssh_extra_args: Vec::new(),
+            kubectl_default_user: String::new(),
+            digitalocean_default_user: "root".to_string(),
+            doctl_profile: None,
+            preflight_check: false,
+            prefer_mosh: false,
+            record_sessions: false,
+            connect_target: None,
+            merge_strategy: MergeStrategy::default(),
+            wrap_navigation: false,
+            group_order: Vec::new(),
+            theme: Theme::default(),
+            keybindings: Keybindings::default(),
+        }
+    }
+}
+
+/// Set by `--config`.
+static CONFIG_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Read and write settings at `path` instead of the config directory.
+pub fn set_config_file(path: PathBuf) {
+    let _ = CONFIG_FILE.set(path);
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        match CONFIG_FILE.get() {
+            Some(path) => path.clone(),
+            None => host::sshmap_config_dir().join("config.toml"),
+        }
+    }
+
+    /// The full ssh invocation for `host`, including `ssh_extra_args`.
+    pub fn ssh_command(&self, host: &Host, include_forwards: bool) -> Vec<String> {
+        let mut cmd = host.ssh_command(include_forwards);
+        cmd.splice(1..1, self.ssh_extra_args.iter().cloned());
+        cmd
+    }
+
+    /// The mosh invocation for `host`, whose ssh gets `ssh_extra_args` too.
+    pub fn mosh_command(&self, host: &Host) -> Vec<String> {
+        host.mosh_command(self.ssh_command(host, false))
+    }
+
+    /// Load `~/.config/sshmap/config.toml`, or the defaults if there is no
+    /// such file. Nothing is written until a setting is changed.
+    pub fn load() -> anyhow::Result<Config> {
+        Self::load_from(&Self::path())
+    }
+
+    /// `load` from `path` rather than the configured location.
+    pub fn load_from(path: &Path) -> anyhow::Result<Config> {
+        let content = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        toml::from_str(&content).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let toml = toml::to_string_pretty(self)?;
+        fs::write(&path, &toml)?;
+        watch::note_own_write(&path, &toml);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_survives_a_toml_round_trip() {
+        let config = Config {
+            health_check_interval_secs: Some(60),
+            health_check_method: CheckMethod::Http {
+                url: "https://example.com/health".into(),
+                expected_status: 204,
+            },
+            ping_count: 5,
+            on_host_down: Some("notify %alias".into()),
+            ssh_extra_args: vec!["-A".into()],
+            group_order: vec!["production".into(), "staging".into()],
+            wrap_navigation: true,
+            ..Config::default()
+        };
+
+        let written = toml::to_string_pretty(&config).unwrap();
+        let read: Config = toml::from_str(&written).unwrap();
+        assert_eq!(toml::to_string_pretty(&read).unwrap(), written);
+        assert_eq!(read.health_check_method, config.health_check_method);
+        assert_eq!(read.group_order, config.group_order);
+    }
+
+    #[test]
+    fn partial_config_takes_defaults_for_the_rest() {
+        let config: Config = toml::from_str("ping_count = 5\nmax_ping_concurrency = 8\n").unwrap();
+        let defaults = Config::default();
+
+        assert_eq!(config.ping_count, 5);
+        assert_eq!(config.max_health_concurrency, 8);
+        assert_eq!(config.fallback_tcp_check, defaults.fallback_tcp_check);
+        assert_eq!(config.health_check_method, defaults.health_check_method);
+        assert_eq!(config.digitalocean_default_user, defaults.digitalocean_default_user);
+        assert_eq!(
+            toml::to_string(&config.keybindings).unwrap(),
+            toml::to_string(&defaults.keybindings).unwrap()
+        );
+    }
+
+    #[test]
+    fn mosh_sessions_get_the_extra_ssh_args_too() {
+        let config = Config { ssh_extra_args: vec!["-A".into()], ..Config::default() };
+        let web = crate::test_support::host("web", "prod");
+        assert_eq!(config.mosh_command(&web), ["mosh", "--ssh", "ssh -A", "web.example"]);
+    }
+
+    #[test]
+    fn loading_a_missing_file_does_not_create_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("typo.toml");
+
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.ping_count, Config::default().ping_count);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,12 @@
+//! Desktop notifications through notify-rust: the notification daemon over
+//! D-Bus on Linux and the BSDs, Notification Center on macOS, toasts on Windows.
+
+use notify_rust::Notification;
+
+/// Pop up `body` under the title "sshmap". Without a notification daemon
+/// nothing is shown, and that is only logged.
+pub fn notify(body: &str) {
+    if let Err(e) = Notification::new().summary("sshmap").body(body).show() {
+        tracing::debug!("can't send desktop notification: {}", e);
+    }
+}
@@ -0,0 +1,41 @@
+use crate::host::{sshmap_config_dir, Host};
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+fn favorites_path() -> PathBuf {
+    sshmap_config_dir().join("favorites.json")
+}
+
+/// Favorited aliases. A missing or unreadable file means no favorites.
+pub fn load() -> HashSet<String> {
+    fs::read_to_string(favorites_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// `load`, dropping aliases that are no longer in `hosts` and saving the
+/// file again if any were.
+pub fn load_pruned(hosts: &[Host]) -> HashSet<String> {
+    let mut favorites = load();
+    let before = favorites.len();
+    favorites.retain(|alias| hosts.iter().any(|h| &h.alias == alias));
+    if favorites.len() != before {
+        let _ = save(&favorites);
+    }
+    favorites
+}
+
+pub fn save(favorites: &HashSet<String>) -> anyhow::Result<()> {
+    let path = favorites_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Sorted so the file doesn't churn between saves
+    let mut aliases: Vec<&String> = favorites.iter().collect();
+    aliases.sort();
+    let json = serde_json::to_string_pretty(&aliases)?;
+    fs::write(&path, json)?;
+    Ok(())
+}
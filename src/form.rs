@@ -0,0 +1,192 @@
+use crate::health::CheckMethod;
+use crate::host::{self, Host, PortForward};
+
+pub const ALIAS: usize = 0;
+pub const HOSTNAME: usize = 1;
+pub const USER: usize = 2;
+pub const PORT: usize = 3;
+pub const GROUP: usize = 4;
+pub const IDENTITY_FILES: usize = 5;
+pub const PROXY_JUMP: usize = 6;
+pub const FORWARDS: usize = 7;
+pub const ENVIRONMENT: usize = 8;
+pub const SSH_OPTIONS: usize = 9;
+pub const DESCRIPTION: usize = 10;
+pub const TAGS: usize = 11;
+pub const HEALTH_CHECK: usize = 12;
+
+/// Presets offered by ←/→ on the health check field; empty means the global default.
+const HEALTH_CHECK_CHOICES: &[&str] = &["", "icmp", "tcp:22", "ssh", "dns"];
+
+pub struct FormField {
+    pub label: &'static str,
+    pub value: String,
+    /// Values ←/→ cycle through; empty for plain text fields.
+    pub choices: &'static [&'static str],
+}
+
+/// Editable copy of a host's fields, shown by `ui::render_edit_form`.
+pub struct HostForm {
+    pub title: &'static str,
+    /// Real index of the host being edited; `None` when adding a new one.
+    pub target: Option<usize>,
+    pub fields: Vec<FormField>,
+    pub focus: usize,
+    pub error: Option<String>,
+}
+
+impl HostForm {
+    pub fn edit(index: usize, host: &Host) -> Self {
+        Self::new(" Edit host ", Some(index), host)
+    }
+
+    pub fn add(default_user: &str) -> Self {
+        let host = Host {
+            user: default_user.to_string(),
+            ..Default::default()
+        };
+        Self::new(" Add host ", None, &host)
+    }
+
+    fn new(title: &'static str, target: Option<usize>, host: &Host) -> Self {
+        let values = [
+            ("Alias", host.alias.clone()),
+            ("Hostname", host.hostname.clone()),
+            ("User", host.user.clone()),
+            ("Port", host.port.to_string()),
+            ("Group", host.group.clone()),
+            ("Identity files", host.identity_files.join(", ")),
+            ("Proxy jump", host.proxy_jump.clone().unwrap_or_default()),
+            (
+                "Forwards",
+                host.local_forwards.iter().map(|f| f.to_string()).collect::<Vec<_>>().join(", "),
+            ),
+            ("Environment", host::format_environment(&host.environment)),
+            ("SSH options", host::format_ssh_options(&host.extra_ssh_options)),
+            ("Description", host.description.clone().unwrap_or_default()),
+            ("Tags", host::join_tags(&host.tags)),
+            (
+                "Health check",
+                host.health_check_method
+                    .as_ref()
+                    .map(|m| m.spec(host.health_check_port))
+                    .unwrap_or_default(),
+            ),
+        ];
+        Self {
+            title,
+            target,
+            fields: values
+                .into_iter()
+                .enumerate()
+                .map(|(i, (label, value))| FormField {
+                    label,
+                    value,
+                    choices: if i == HEALTH_CHECK { HEALTH_CHECK_CHOICES } else { &[] },
+                })
+                .collect(),
+            focus: 0,
+            error: None,
+        }
+    }
+
+    pub fn next_field(&mut self) {
+        self.focus = (self.focus + 1) % self.fields.len();
+    }
+
+    pub fn prev_field(&mut self) {
+        self.focus = (self.focus + self.fields.len() - 1) % self.fields.len();
+    }
+
+    /// Step the focused field through its choices; no-op for text fields.
+    pub fn cycle(&mut self, forward: bool) {
+        let field = &mut self.fields[self.focus];
+        if field.choices.is_empty() {
+            return;
+        }
+        let len = field.choices.len();
+        let current = field.choices.iter().position(|c| *c == field.value);
+        let next = match (current, forward) {
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+            (None, _) => 0,
+        };
+        field.value = field.choices[next].to_string();
+        self.error = None;
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.fields[self.focus].value.push(c);
+        self.error = None;
+    }
+
+    pub fn pop(&mut self) {
+        self.fields[self.focus].value.pop();
+        self.error = None;
+    }
+
+    fn value(&self, field: usize) -> &str {
+        self.fields[field].value.trim()
+    }
+
+    /// Apply the form to a copy of `base`, or explain why it can't be saved.
+    pub fn to_host(&self, base: &Host) -> Result<Host, String> {
+        let port: u16 = match self.value(PORT).parse() {
+            Ok(p) => p,
+            Err(_) => return Err("Port must be a number between 1 and 65535".into()),
+        };
+        let proxy = self.value(PROXY_JUMP);
+        let forwards = self
+            .value(FORWARDS)
+            .split(',')
+            .map(str::trim)
+            .filter(|f| !f.is_empty())
+            .map(str::parse)
+            .collect::<Result<Vec<PortForward>, String>>()?;
+        let environment = host::parse_environment(self.value(ENVIRONMENT))?;
+        let description = self.value(DESCRIPTION);
+
+        let health = match self.value(HEALTH_CHECK) {
+            "" => None,
+            spec => match CheckMethod::parse_spec(spec) {
+                Some(parsed) => Some(parsed),
+                None => {
+                    let hint = "icmp, tcp:PORT, ssh[:PORT], dns or a URL, joined by | or &";
+                    return Err(format!("Health check must be {}", hint));
+                }
+            },
+        };
+
+        let mut host = base.clone();
+        host.alias = self.value(ALIAS).to_string();
+        host.hostname = self.value(HOSTNAME).to_string();
+        host.user = self.value(USER).to_string();
+        host.port = port;
+        host.group = match self.value(GROUP) {
+            "" => host::DEFAULT_GROUP.to_string(),
+            g => g.to_string(),
+        };
+        host.identity_files = self
+            .value(IDENTITY_FILES)
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .map(str::to_string)
+            .collect();
+        host.proxy_jump = (!proxy.is_empty()).then(|| proxy.to_string());
+        host.local_forwards = forwards;
+        host.environment = environment;
+        host.extra_ssh_options = host::parse_ssh_options(self.value(SSH_OPTIONS));
+        host.description = (!description.is_empty()).then(|| description.to_string());
+        host.tags = host::parse_tags(self.value(TAGS));
+        host.health_check_port = health.as_ref().and_then(|(_, p)| *p);
+        host.health_check_method = health.map(|(m, _)| m);
+
+        let errors = host::validate_host(&host);
+        if !errors.is_empty() {
+            let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            return Err(messages.join("; "));
+        }
+        Ok(host)
+    }
+}
@@ -0,0 +1,75 @@
+use crate::host::{Host, DEFAULT_GROUP};
+
+/// State of the `M` group panel.
+pub struct GroupPanel {
+    /// Group names in display order.
+    pub groups: Vec<String>,
+    pub selected: usize,
+    pub action: GroupAction,
+    pub error: Option<String>,
+}
+
+pub enum GroupAction {
+    Browse,
+    /// Typing the name of a new group.
+    Add(String),
+    /// Typing a new name for the selected group.
+    Rename(String),
+    /// Picking which group receives the deleted group's hosts.
+    Delete { targets: Vec<String>, selected: usize },
+}
+
+impl GroupPanel {
+    pub fn new(groups: Vec<String>) -> Self {
+        Self {
+            groups,
+            selected: 0,
+            action: GroupAction::Browse,
+            error: None,
+        }
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.groups.get(self.selected).map(String::as_str)
+    }
+
+    pub fn select_up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn select_down(&mut self) {
+        if self.selected + 1 < self.groups.len() {
+            self.selected += 1;
+        }
+    }
+}
+
+/// Every group: those in `order` first, as listed, then the rest
+/// alphabetically. Groups in `order` are kept even when no host uses them.
+pub fn ordered(hosts: &[Host], order: &[String]) -> Vec<String> {
+    let mut groups: Vec<String> = order.to_vec();
+    let mut rest: Vec<String> = hosts
+        .iter()
+        .map(|h| h.group.clone())
+        .filter(|g| !order.contains(g))
+        .collect();
+    rest.sort();
+    rest.dedup();
+    groups.extend(rest);
+    groups
+}
+
+/// Sort key for `group` under `order`; unlisted groups come after listed ones.
+pub fn rank(order: &[String], group: &str) -> usize {
+    order.iter().position(|g| g == group).unwrap_or(order.len())
+}
+
+/// Where the hosts of `deleted` may go: the other groups, plus the default
+/// group standing in for "ungrouped".
+pub fn delete_targets(groups: &[String], deleted: &str) -> Vec<String> {
+    let mut targets: Vec<String> = groups.iter().filter(|g| *g != deleted).cloned().collect();
+    if deleted != DEFAULT_GROUP && !targets.iter().any(|g| g == DEFAULT_GROUP) {
+        targets.push(DEFAULT_GROUP.to_string());
+    }
+    targets
+}
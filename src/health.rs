@@ -1,91 +1,1244 @@
-use crate::host::{Host, HostStatus};
-use std::process::Command;
-use std::sync::{Arc, Mutex};
-use std::thread;
-use std::time::Instant;
-
-pub fn check_all(hosts: Arc<Mutex<Vec<Host>>>) {
-    let count = {
-        let h = hosts.lock().unwrap();
-        // Mark all as checking
-        h.len()
-    };
+use crate::config::Config;
+use crate::desktop;
+use crate::metrics;
+use crate::host::{self, Host, HostStatus};
+use crate::tls;
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{IpAddr, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::sync::Semaphore;
+use tokio::task::{JoinHandle, JoinSet};
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+/// Covers the ssh login to each jump host as well as the banner.
+const PROXY_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(3);
+/// Certificates expiring within this many days are flagged.
+const TLS_WARN_DAYS: i64 = 14;
+
+pub const DEFAULT_MAX_CONCURRENCY: usize = 32;
+pub const DEFAULT_PING_COUNT: u8 = 3;
+/// Long enough for a failed ping followed by a check through a jump host.
+pub const DEFAULT_HEALTH_TIMEOUT_SECS: u64 = 15;
+
+static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+static MAX_CONCURRENCY: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_CONCURRENCY);
+static PING_COUNT: AtomicU8 = AtomicU8::new(DEFAULT_PING_COUNT);
+static FALLBACK_TCP: AtomicBool = AtomicBool::new(true);
+static HEALTH_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(DEFAULT_HEALTH_TIMEOUT_SECS);
+static DESKTOP_NOTIFICATIONS: AtomicBool = AtomicBool::new(false);
+static HEALTH_LOG: AtomicBool = AtomicBool::new(false);
+static HEALTH_LOG_MAX_MB: AtomicU32 = AtomicU32::new(10);
+static ON_HOST_DOWN: RwLock<Option<String>> = RwLock::new(None);
+static ON_HOST_UP: RwLock<Option<String>> = RwLock::new(None);
+/// `Config::health_check_method`, for hosts without their own.
+static DEFAULT_METHOD: RwLock<CheckMethod> = RwLock::new(CheckMethod::Icmp);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckMethod {
+    #[default]
+    Icmp,
+    TcpPort(u16),
+    SshBanner,
+    /// Report days until the certificate served at `url` expires.
+    HttpsTls { url: String },
+    /// Time a lookup of the hostname; the answer lands in `Host::resolved_ip`.
+    Dns,
+    /// GET `url` and expect this status code back.
+    Http { url: String, expected_status: u16 },
+    /// Up if any of these checks is.
+    AnyOf(Vec<CheckMethod>),
+    /// Up only if all of these checks are.
+    AllOf(Vec<CheckMethod>),
+}
+
+/// The parts of a combined spec, which can't themselves be combined.
+fn parse_specs(spec: &str, separator: &str) -> Option<(Vec<CheckMethod>, Option<u16>)> {
+    let mut methods = Vec::new();
+    let mut port = None;
+    for part in spec.split(separator) {
+        let (method, part_port) = CheckMethod::parse_spec(part)?;
+        if matches!(method, CheckMethod::AnyOf(_) | CheckMethod::AllOf(_)) {
+            return None;
+        }
+        methods.push(method);
+        port = port.or(part_port);
+    }
+    Some((methods, port))
+}
+
+fn join_specs(methods: &[CheckMethod], port: Option<u16>, separator: &str) -> String {
+    let specs: Vec<String> = methods.iter().map(|m| m.spec(port)).collect();
+    specs.join(separator)
+}
+
+/// Apply the check settings that aren't passed per call.
+pub fn configure(config: &Config) {
+    MAX_CONCURRENCY.store(config.max_health_concurrency.max(1), Ordering::Relaxed);
+    PING_COUNT.store(config.ping_count.max(1), Ordering::Relaxed);
+    FALLBACK_TCP.store(config.fallback_tcp_check, Ordering::Relaxed);
+    HEALTH_TIMEOUT_SECS.store(config.health_timeout_secs.max(1), Ordering::Relaxed);
+    DESKTOP_NOTIFICATIONS.store(config.desktop_notifications, Ordering::Relaxed);
+    HEALTH_LOG.store(config.health_log, Ordering::Relaxed);
+    HEALTH_LOG_MAX_MB.store(config.health_log_max_mb.max(1), Ordering::Relaxed);
+    *ON_HOST_DOWN.write().unwrap() = config.on_host_down.clone();
+    *ON_HOST_UP.write().unwrap() = config.on_host_up.clone();
+    *DEFAULT_METHOD.write().unwrap() = config.health_check_method.clone();
+}
+
+impl CheckMethod {
+    /// Parse the short form used in `# health:` comments and the edit form:
+    /// `icmp`, `tcp:8080`, `ssh` or `ssh:2222`, `dns`, `https://host/` for the
+    /// certificate check, or `http(s)://host/path [status]` for an HTTP GET.
+    /// Join several with ` | ` (any of them) or ` & ` (all of them).
+    /// The second value is the port the spec names, if any, which becomes the
+    /// host's `health_check_port`.
+    pub fn parse_spec(spec: &str) -> Option<(CheckMethod, Option<u16>)> {
+        let spec = spec.trim();
+        if spec.contains(" | ") {
+            let (methods, port) = parse_specs(spec, " | ")?;
+            return Some((CheckMethod::AnyOf(methods), port));
+        }
+        if spec.contains(" & ") {
+            let (methods, port) = parse_specs(spec, " & ")?;
+            return Some((CheckMethod::AllOf(methods), port));
+        }
+        if spec.starts_with("http://") || spec.starts_with("https://") {
+            let mut parts = spec.split_whitespace();
+            let url = parts.next()?.to_string();
+            let method = match parts.next() {
+                Some(status) => CheckMethod::Http { url, expected_status: status.parse().ok()? },
+                None if url.starts_with("https://") => CheckMethod::HttpsTls { url },
+                None => CheckMethod::Http { url, expected_status: 200 },
+            };
+            return Some((method, None));
+        }
+        let (kind, port) = match spec.split_once(':') {
+            Some((kind, port)) => (kind, Some(port.trim().parse::<u16>().ok()?)),
+            None => (spec, None),
+        };
+        match (kind.to_lowercase().as_str(), port) {
+            ("icmp", None) => Some((CheckMethod::Icmp, None)),
+            ("tcp", Some(p)) => Some((CheckMethod::TcpPort(p), Some(p))),
+            ("ssh", port) => Some((CheckMethod::SshBanner, port)),
+            ("dns", None) => Some((CheckMethod::Dns, None)),
+            _ => None,
+        }
+    }
+
+    /// Inverse of `parse_spec`.
+    pub fn spec(&self, port: Option<u16>) -> String {
+        match (self, port) {
+            (CheckMethod::Icmp, _) => "icmp".to_string(),
+            (CheckMethod::TcpPort(p), _) => format!("tcp:{}", p),
+            (CheckMethod::SshBanner, Some(p)) => format!("ssh:{}", p),
+            (CheckMethod::SshBanner, None) => "ssh".to_string(),
+            (CheckMethod::HttpsTls { url }, _) => url.clone(),
+            (CheckMethod::Dns, _) => "dns".to_string(),
+            (CheckMethod::Http { url, expected_status }, _) => {
+                format!("{} {}", url, expected_status)
+            }
+            (CheckMethod::AnyOf(methods), port) => join_specs(methods, port, " | "),
+            (CheckMethod::AllOf(methods), port) => join_specs(methods, port, " & "),
+        }
+    }
+}
+
+fn runtime() -> &'static Runtime {
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start health check runtime")
+    })
+}
+
+/// Block until a check started by this module finishes, for the CLI.
+pub fn wait(handle: JoinHandle<()>) {
+    let _ = runtime().block_on(handle);
+}
+
+/// Like `wait`, but give up after `timeout`; returns whether the check finished.
+pub fn wait_timeout(handle: JoinHandle<()>, timeout: Duration) -> bool {
+    runtime().block_on(async { tokio::time::timeout(timeout, handle).await.is_ok() })
+}
+
+/// Check every host, with at most `Config::max_health_concurrency` checks
+/// running at once.
+pub fn check_all(hosts: Arc<Mutex<Vec<Host>>>) -> JoinHandle<()> {
+    check_all_bounded(hosts, MAX_CONCURRENCY.load(Ordering::Relaxed))
+}
+
+/// Like `check_all`, with `max_concurrency` in place of the configured limit.
+pub fn check_all_bounded(hosts: Arc<Mutex<Vec<Host>>>, max_concurrency: usize) -> JoinHandle<()> {
+    let count = hosts.lock().unwrap().len();
+    check_indices(hosts, (0..count).collect(), max_concurrency)
+}
 
+/// Check only the hosts at the given real indices (the multi-select set).
+pub fn check_selected(
+    hosts: Arc<Mutex<Vec<Host>>>,
+    selection: &BTreeSet<usize>,
+    max_concurrency: usize,
+) -> JoinHandle<()> {
+    check_indices(hosts, selection.iter().copied().collect(), max_concurrency)
+}
+
+fn check_indices(
+    hosts: Arc<Mutex<Vec<Host>>>,
+    indices: Vec<usize>,
+    max_concurrency: usize,
+) -> JoinHandle<()> {
+    // Mark all as checking. Indices shift when hosts are reloaded, added or
+    // deleted mid-check, so the checks find their host again by alias.
+    let mut aliases = Vec::new();
+    let mut scheduler = Scheduler::default();
     {
         let mut h = hosts.lock().unwrap();
-        for host in h.iter_mut() {
-            host.status = HostStatus::Checking;
+        for &i in &indices {
+            if let Some(host) = h.get_mut(i) {
+                host.status = HostStatus::Checking;
+                aliases.push(host.alias.clone());
+                scheduler.add(method_for(host));
+            }
         }
     }
 
-    for i in 0..count {
-        let hosts = Arc::clone(&hosts);
-        thread::spawn(move || {
-            let hostname = {
-                let h = hosts.lock().unwrap();
-                h[i].hostname.clone()
-            };
+    let timeout = health_timeout();
+    runtime().spawn(async move {
+        let checked = Arc::clone(&hosts);
+        let scheduler = Arc::new(scheduler);
+        let transitions = run_bounded(aliases, max_concurrency, move |alias| {
+            run_check(Arc::clone(&checked), Arc::clone(&scheduler), alias, timeout)
+        })
+        .await;
+        metrics::update(&hosts.lock().unwrap());
+        // One notification for the whole round rather than one per host
+        run_alert_commands(&transitions);
+        notify_transitions(transitions).await;
+    })
+}
 
-            let status = ping_host(&hostname);
+/// Run `check` for each item with at most `max_concurrency` in flight,
+/// collecting the results that are `Some`.
+async fn run_bounded<I, F, Fut, T>(items: Vec<I>, max_concurrency: usize, check: F) -> Vec<T>
+where
+    F: Fn(I) -> Fut,
+    Fut: Future<Output = Option<T>> + Send + 'static,
+    T: Send + 'static,
+{
+    let permits = Arc::new(Semaphore::new(max_concurrency.max(1)));
+    let mut set = JoinSet::new();
+    for item in items {
+        let permits = Arc::clone(&permits);
+        let check = check(item);
+        set.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            check.await
+        });
+    }
 
-            let mut h = hosts.lock().unwrap();
-            h[i].status = status;
+    let mut results = Vec::new();
+    while let Some(result) = set.join_next().await {
+        if let Ok(Some(value)) = result {
+            results.push(value);
+        }
+    }
+    results
+}
+
+/// Re-check every host each `interval_secs` (0 disables) unless `paused`,
+/// `max_concurrency` at a time. All three are read on every tick so a config
+/// reload takes effect without a restart.
+pub fn spawn_auto_refresh(
+    hosts: Arc<Mutex<Vec<Host>>>,
+    interval_secs: Arc<AtomicU64>,
+    paused: Arc<AtomicBool>,
+    last_check: Arc<Mutex<Option<DateTime<Local>>>>,
+    max_concurrency: Arc<AtomicUsize>,
+) -> JoinHandle<()> {
+    runtime().spawn(async move {
+        let mut last_run = Instant::now();
+        loop {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            let secs = interval_secs.load(Ordering::Relaxed);
+            if secs == 0
+                || paused.load(Ordering::Relaxed)
+                || last_run.elapsed() < Duration::from_secs(secs)
+            {
+                continue;
+            }
+            last_run = Instant::now();
+            *last_check.lock().unwrap() = Some(Local::now());
+            let max_concurrency = max_concurrency.load(Ordering::Relaxed);
+            let _ = check_all_bounded(Arc::clone(&hosts), max_concurrency).await;
+        }
+    })
+}
+
+pub fn check_one(hosts: Arc<Mutex<Vec<Host>>>, index: usize) -> JoinHandle<()> {
+    let mut scheduler = Scheduler::default();
+    let alias = hosts.lock().unwrap().get_mut(index).map(|host| {
+        host.status = HostStatus::Checking;
+        scheduler.add(method_for(host));
+        host.alias.clone()
+    });
+
+    let timeout = health_timeout();
+    runtime().spawn(async move {
+        let Some(alias) = alias else { return };
+        let transition =
+            run_check(Arc::clone(&hosts), Arc::new(scheduler), alias, timeout).await;
+        metrics::update(&hosts.lock().unwrap());
+        if let Some(transition) = transition {
+            run_alert_commands(std::slice::from_ref(&transition));
+            notify_transitions(vec![transition]).await;
+        }
+    })
+}
+
+/// A host that went from up to down, or back, in one check.
+struct Transition {
+    alias: String,
+    hostname: String,
+    group: String,
+    alert_command: Option<String>,
+    up: bool,
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} went {}", self.alias, if self.up { "UP" } else { "DOWN" })
+    }
+}
+
+async fn notify_transitions(transitions: Vec<Transition>) {
+    if !DESKTOP_NOTIFICATIONS.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Some(body) = notification_body(&transitions) {
+        let _ = tokio::task::spawn_blocking(move || desktop::notify(&body)).await;
+    }
+}
+
+/// One line for a single transition, else a count followed by a line each.
+fn notification_body(transitions: &[Transition]) -> Option<String> {
+    match transitions {
+        [] => None,
+        [one] => Some(one.to_string()),
+        many => {
+            let lines: Vec<String> = many.iter().map(|t| t.to_string()).collect();
+            Some(format!("{} hosts changed state:\n{}", many.len(), lines.join("\n")))
+        }
+    }
+}
+
+/// Start the host's alert command, or `on_host_down`/`on_host_up`, for each
+/// transition without waiting for it; the exit code ends up in health.log.
+fn run_alert_commands(transitions: &[Transition]) {
+    for transition in transitions {
+        let template = transition.alert_command.clone().or_else(|| {
+            let configured = if transition.up { &ON_HOST_UP } else { &ON_HOST_DOWN };
+            configured.read().unwrap().clone()
         });
+        let Some(template) = template else { continue };
+        let command = expand_alert_command(&template, transition);
+        let alias = transition.alias.clone();
+        runtime().spawn_blocking(move || run_alert_command(&alias, &command));
     }
 }
 
-pub fn check_one(hosts: Arc<Mutex<Vec<Host>>>, index: usize) {
-    let hosts = Arc::clone(&hosts);
-    thread::spawn(move || {
-        let hostname = {
-            let mut h = hosts.lock().unwrap();
-            if index >= h.len() {
-                return;
+/// Fill in `%alias`, `%hostname` and `%group`, each quoted for `sh` since
+/// they come from ssh_config and importers rather than the user.
+fn expand_alert_command(template: &str, transition: &Transition) -> String {
+    let placeholders = [
+        ("%alias", &transition.alias),
+        ("%hostname", &transition.hostname),
+        ("%group", &transition.group),
+    ];
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('%') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match placeholders.iter().find(|(name, _)| rest.starts_with(name)) {
+            Some((name, value)) => {
+                out.push_str(&host::shell_quote(value));
+                rest = &rest[name.len()..];
             }
-            h[index].status = HostStatus::Checking;
-            h[index].hostname.clone()
-        };
+            None => {
+                out.push('%');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn run_alert_command(alias: &str, command: &str) {
+    tracing::info!(host = alias, command, "running alert command");
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+    let exit_code = match status {
+        Ok(status) => status.code(),
+        Err(e) => {
+            tracing::warn!("can't run alert command '{}': {}", command, e);
+            None
+        }
+    };
+    if HEALTH_LOG.load(Ordering::Relaxed) {
+        #[derive(Serialize)]
+        struct Event<'a> {
+            ts: String,
+            alias: &'a str,
+            command: &'a str,
+            exit_code: Option<i32>,
+        }
+        write_health_log(&Event { ts: log_timestamp(), alias, command, exit_code });
+    }
+}
 
-        let status = ping_host(&hostname);
+/// `Config::health_timeout_secs`, the longest a host may stay in `Checking`.
+fn health_timeout() -> Duration {
+    Duration::from_secs(HEALTH_TIMEOUT_SECS.load(Ordering::Relaxed))
+}
 
-        let mut h = hosts.lock().unwrap();
-        if index < h.len() {
-            h[index].status = status;
+/// Check the host called `alias` and record the result, `Timeout` if the
+/// check takes longer than `timeout`; returns the change if it went from up
+/// to down or back. The result is dropped if the host was deleted while the
+/// check ran.
+async fn run_check(
+    hosts: Arc<Mutex<Vec<Host>>>,
+    scheduler: Arc<Scheduler>,
+    alias: String,
+    timeout: Duration,
+) -> Option<Transition> {
+    let host = hosts.lock().unwrap().iter().find(|h| h.alias == alias)?.clone();
+
+    // The checks themselves block (ping subprocess, std sockets)
+    let check = tokio::task::spawn_blocking(move || {
+        let (status, ip) = scheduler.check(&host);
+        // Hosts behind a bastion are often unreachable (or unresolvable) directly
+        match &host.proxy_jump {
+            Some(proxy) if !status.is_up() => {
+                match check_via_proxy(proxy, &host.hostname, check_port(&host)) {
+                    via @ HostStatus::Up { .. } => (via, ip),
+                    _ => (status, ip),
+                }
+            }
+            _ => (status, ip),
         }
     });
+    // A hung check can't be cancelled, but the host needn't sit in Checking.
+    // The blocking thread runs on until the check returns and its result is dropped.
+    let (status, resolved_ip) = match tokio::time::timeout(timeout, check).await {
+        Ok(result) => result.unwrap_or((HostStatus::Down, None)),
+        Err(_) => (HostStatus::Timeout, None),
+    };
+
+    let mut h = hosts.lock().unwrap();
+    let host = h.iter_mut().find(|h| h.alias == alias)?;
+    tracing::debug!(host = %host.alias, status = ?status, "health check finished");
+    if resolved_ip.is_some() {
+        host.resolved_ip = resolved_ip;
+    }
+    // `status` is Checking by now; the last result is the one to compare with
+    let previous = host.health_history.back().map(|r| r.status.clone());
+    let was_up = previous.as_ref().map(HostStatus::is_up);
+    let up = status.is_up();
+    let transition = match was_up {
+        Some(was_up) if was_up != up => Some(Transition {
+            alias: alias.clone(),
+            hostname: host.hostname.clone(),
+            group: host.group.clone(),
+            alert_command: host.alert_command_override.clone(),
+            up,
+        }),
+        _ => None,
+    };
+    host.record_status(status.clone());
+    drop(h);
+
+    if HEALTH_LOG.load(Ordering::Relaxed) && was_up.unwrap_or(false) != up {
+        log_health_event(&alias, previous.as_ref().unwrap_or(&HostStatus::Unknown), &status);
+    }
+    transition
 }
 
+fn health_log_path() -> PathBuf {
+    host::sshmap_config_dir().join("health.log")
+}
+
+/// Append `{"ts","alias","from","to"}` to `health.log` as one JSON line.
+fn log_health_event(alias: &str, from: &HostStatus, to: &HostStatus) {
+    #[derive(Serialize)]
+    struct Event<'a> {
+        ts: String,
+        alias: &'a str,
+        from: &'static str,
+        to: &'static str,
+    }
+    let event = Event { ts: log_timestamp(), alias, from: status_name(from), to: status_name(to) };
+    write_health_log(&event);
+}
+
+fn log_timestamp() -> String {
+    chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+fn write_health_log(event: &impl Serialize) {
+    let line = serde_json::to_string(event).expect("event serializes");
+    let path = health_log_path();
+    if let Err(e) = append_health_log(&path, &line) {
+        tracing::warn!(path = %path.display(), error = %e, "can't write health log");
+    }
+}
+
+/// Move the log to `health.log.1` first once it outgrows `health_log_max_mb`.
+fn append_health_log(path: &Path, line: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let max_bytes = u64::from(HEALTH_LOG_MAX_MB.load(Ordering::Relaxed)) * 1024 * 1024;
+    if fs::metadata(path).is_ok_and(|m| m.len() >= max_bytes) {
+        fs::rename(path, path.with_extension("log.1"))?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+fn status_name(status: &HostStatus) -> &'static str {
+    match status {
+        HostStatus::Unknown => "Unknown",
+        HostStatus::Checking => "Checking",
+        HostStatus::Up { .. } => "Up",
+        HostStatus::TcpOpen(_) => "TcpOpen",
+        HostStatus::Down => "Down",
+        HostStatus::Timeout => "Timeout",
+        HostStatus::DnsError => "DnsError",
+        HostStatus::TlsOk { .. } => "TlsOk",
+        HostStatus::TlsExpiringSoon { .. } => "TlsExpiringSoon",
+        HostStatus::TlsExpired => "TlsExpired",
+    }
+}
+
+/// One way of deciding whether a host is reachable. Each `CheckMethod` maps
+/// to one of these; new kinds of check only need another implementation.
+pub trait HealthCheck: Send + Sync {
+    fn check(&self, host: &Host) -> HostStatus;
+
+    /// Like `check`, also giving the address the check resolved the host to,
+    /// for checks that learn one.
+    fn check_resolving(&self, host: &Host) -> (HostStatus, Option<IpAddr>) {
+        (self.check(host), None)
+    }
+}
+
+/// The check for each method in use, built once per round and shared by
+/// every host checked with that method.
+#[derive(Default)]
+pub struct Scheduler {
+    checks: HashMap<CheckMethod, Box<dyn HealthCheck>>,
+}
+
+impl Scheduler {
+    /// Make sure there is a check for `method`.
+    pub fn add(&mut self, method: CheckMethod) {
+        self.checks.entry(method).or_insert_with_key(CheckMethod::health_check);
+    }
+
+    /// Run the check for `host`'s method, building it if `add` never saw it.
+    pub fn check(&self, host: &Host) -> (HostStatus, Option<IpAddr>) {
+        let method = method_for(host);
+        match self.checks.get(&method) {
+            Some(check) => check.check_resolving(host),
+            None => method.health_check().check_resolving(host),
+        }
+    }
+}
+
+/// The host's own check method, else the configured default.
+fn method_for(host: &Host) -> CheckMethod {
+    host.health_check_method.clone().unwrap_or_else(|| DEFAULT_METHOD.read().unwrap().clone())
+}
+
+impl CheckMethod {
+    /// The check that carries out this method.
+    pub fn health_check(&self) -> Box<dyn HealthCheck> {
+        match self {
+            // Plenty of cloud hosts drop ICMP but still take SSH connections
+            CheckMethod::Icmp if FALLBACK_TCP.load(Ordering::Relaxed) => {
+                Box::new(CompositeCheck {
+                    checks: vec![Box::new(IcmpPing), Box::new(SshPortOpen)],
+                    strategy: Strategy::AnyOf,
+                })
+            }
+            CheckMethod::Icmp => Box::new(IcmpPing),
+            CheckMethod::TcpPort(port) => Box::new(TcpConnect { port: *port }),
+            CheckMethod::SshBanner => Box::new(SshBanner),
+            CheckMethod::HttpsTls { url } => Box::new(TlsCertificate { url: url.clone() }),
+            CheckMethod::Dns => Box::new(DnsLookup),
+            CheckMethod::Http { url, expected_status } => {
+                Box::new(HttpGet { url: url.clone(), expected_status: *expected_status })
+            }
+            CheckMethod::AnyOf(methods) => Box::new(CompositeCheck {
+                checks: methods.iter().map(CheckMethod::health_check).collect(),
+                strategy: Strategy::AnyOf,
+            }),
+            CheckMethod::AllOf(methods) => Box::new(CompositeCheck {
+                checks: methods.iter().map(CheckMethod::health_check).collect(),
+                strategy: Strategy::AllOf,
+            }),
+        }
+    }
+}
+
+/// The port checked on the host: its health check port, else the SSH port.
+fn check_port(host: &Host) -> u16 {
+    host.health_check_port.unwrap_or(host.port)
+}
+
+pub struct IcmpPing;
+
+impl HealthCheck for IcmpPing {
+    fn check(&self, host: &Host) -> HostStatus {
+        ping_host(&host.hostname)
+    }
+}
+
+/// Reports `TcpOpen` rather than `Up`, marking a host that only answered
+/// on its SSH port.
+pub struct SshPortOpen;
+
+impl HealthCheck for SshPortOpen {
+    fn check(&self, host: &Host) -> HostStatus {
+        match check_tcp(&host.hostname, check_port(host), CHECK_TIMEOUT) {
+            status if status.is_up() => status.rtt().map_or(status, HostStatus::TcpOpen),
+            status => status,
+        }
+    }
+}
+
+pub struct TcpConnect {
+    pub port: u16,
+}
+
+impl HealthCheck for TcpConnect {
+    fn check(&self, host: &Host) -> HostStatus {
+        check_tcp(&host.hostname, self.port, CHECK_TIMEOUT)
+    }
+}
+
+pub struct SshBanner;
+
+impl HealthCheck for SshBanner {
+    fn check(&self, host: &Host) -> HostStatus {
+        check_ssh_banner(&host.hostname, check_port(host), CHECK_TIMEOUT)
+    }
+}
+
+pub struct HttpGet {
+    pub url: String,
+    pub expected_status: u16,
+}
+
+impl HealthCheck for HttpGet {
+    fn check(&self, _host: &Host) -> HostStatus {
+        check_http(&self.url, self.expected_status)
+    }
+}
+
+pub struct TlsCertificate {
+    pub url: String,
+}
+
+impl HealthCheck for TlsCertificate {
+    fn check(&self, _host: &Host) -> HostStatus {
+        check_tls(&self.url, CHECK_TIMEOUT)
+    }
+}
+
+pub struct DnsLookup;
+
+impl HealthCheck for DnsLookup {
+    fn check(&self, host: &Host) -> HostStatus {
+        check_dns(&host.hostname).0
+    }
+
+    fn check_resolving(&self, host: &Host) -> (HostStatus, Option<IpAddr>) {
+        check_dns(&host.hostname)
+    }
+}
+
+pub enum Strategy {
+    /// Up as soon as one check is; otherwise the first check's result.
+    AnyOf,
+    /// Up only if every check is; otherwise the first failure.
+    AllOf,
+}
+
+/// Several checks run in order and combined by `strategy`.
+pub struct CompositeCheck {
+    pub checks: Vec<Box<dyn HealthCheck>>,
+    pub strategy: Strategy,
+}
+
+impl HealthCheck for CompositeCheck {
+    fn check(&self, host: &Host) -> HostStatus {
+        self.check_resolving(host).0
+    }
+
+    fn check_resolving(&self, host: &Host) -> (HostStatus, Option<IpAddr>) {
+        let mut first = None;
+        let mut resolved = None;
+        for check in &self.checks {
+            let (status, ip) = check.check_resolving(host);
+            resolved = resolved.or(ip);
+            let done = match self.strategy {
+                Strategy::AnyOf => status.is_up(),
+                Strategy::AllOf => !status.is_up(),
+            };
+            // The remaining checks would look up the same name
+            if done || matches!(status, HostStatus::DnsError) {
+                return (status, resolved);
+            }
+            first.get_or_insert(status);
+        }
+        (first.unwrap_or(HostStatus::Unknown), resolved)
+    }
+}
+
+/// The exit status ping uses for "sent, but nothing came back"; the others
+/// are errors such as a bad address or no route.
+#[cfg(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd"))]
+const PING_NO_REPLY: i32 = 2;
+#[cfg(not(any(target_os = "macos", target_os = "freebsd", target_os = "openbsd")))]
+const PING_NO_REPLY: i32 = 1;
+
 fn ping_host(hostname: &str) -> HostStatus {
+    // Tell "no such host" apart from "no answer" before spending the ping window
+    if (hostname, 0).to_socket_addrs().is_err() {
+        return HostStatus::DnsError;
+    }
+
+    let count = PING_COUNT.load(Ordering::Relaxed).to_string();
     let start = Instant::now();
     let output = Command::new("ping")
-        .args(["-c", "1", "-W", "2", hostname])
+        .args(["-c", &count, "-i", "0.2", "-W", "2", hostname])
         .output();
 
     match output {
+        // ping exits non-zero when no reply came back at all
         Ok(o) if o.status.success() => {
-            let rtt = start.elapsed().as_secs_f64() * 1000.0;
-            // Try to parse actual RTT from ping output
+            let elapsed = start.elapsed().as_secs_f64() * 1000.0;
             let stdout = String::from_utf8_lossy(&o.stdout);
-            let parsed_rtt = parse_ping_rtt(&stdout).unwrap_or(rtt);
-            HostStatus::Up(parsed_rtt)
+            let samples = parse_ping_times(&stdout);
+            let loss = parse_packet_loss(&stdout).unwrap_or(0.0);
+            if samples.is_empty() {
+                return HostStatus::Up { mean: elapsed, stddev: 0.0, loss };
+            }
+            let (mean, stddev) = mean_stddev(&samples);
+            HostStatus::Up { mean, stddev, loss }
         }
+        Ok(o) if o.status.code() == Some(PING_NO_REPLY) => HostStatus::Timeout,
+        Ok(_) => HostStatus::Down,
+        Err(e) if e.kind() == io::ErrorKind::TimedOut => HostStatus::Timeout,
+        Err(_) => HostStatus::Down,
+    }
+}
+
+/// Map a failed connect or read to `Timeout` or `Down`.
+fn io_failure(e: &io::Error) -> HostStatus {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => HostStatus::Timeout,
         _ => HostStatus::Down,
     }
 }
 
-fn parse_ping_rtt(output: &str) -> Option<f64> {
-    // macOS: round-trip min/avg/max/stddev = 1.234/2.345/3.456/0.123 ms
-    // Linux: rtt min/avg/max/mdev = 1.234/2.345/3.456/0.123 ms
-    for line in output.lines() {
-        if line.contains("avg") && line.contains('/') {
-            let parts: Vec<&str> = line.split('=').collect();
-            if let Some(vals) = parts.last() {
-                let nums: Vec<&str> = vals.trim().split('/').collect();
-                if nums.len() >= 2 {
-                    return nums[1].trim().parse().ok();
+fn check_tcp(hostname: &str, port: u16, timeout: Duration) -> HostStatus {
+    let addr = match (hostname, port).to_socket_addrs().ok().and_then(|mut a| a.next()) {
+        Some(a) => a,
+        None => return HostStatus::DnsError,
+    };
+
+    let start = Instant::now();
+    match TcpStream::connect_timeout(&addr, timeout) {
+        Ok(_) => HostStatus::up(start.elapsed().as_secs_f64() * 1000.0),
+        Err(e) => io_failure(&e),
+    }
+}
+
+/// Confirm an SSH daemon is answering by reading its identification line
+/// (e.g. `SSH-2.0-OpenSSH_9.6`). RTT covers connect plus banner.
+fn check_ssh_banner(hostname: &str, port: u16, timeout: Duration) -> HostStatus {
+    let addr = match (hostname, port).to_socket_addrs().ok().and_then(|mut a| a.next()) {
+        Some(a) => a,
+        None => return HostStatus::DnsError,
+    };
+
+    let start = Instant::now();
+    let stream = match TcpStream::connect_timeout(&addr, timeout) {
+        Ok(s) => s,
+        Err(e) => return io_failure(&e),
+    };
+    if stream.set_read_timeout(Some(timeout)).is_err() {
+        return HostStatus::Down;
+    }
+
+    let mut banner = String::new();
+    match BufReader::new(stream).read_line(&mut banner) {
+        Ok(_) if banner.starts_with("SSH-") => {
+            HostStatus::up(start.elapsed().as_secs_f64() * 1000.0)
+        }
+        Ok(_) => HostStatus::Down,
+        Err(e) => io_failure(&e),
+    }
+}
+
+/// Read the SSH banner of `hostname:port` through the jump host(s) with
+/// `ssh -W`. Needs non-interactive (key or agent) auth to the jump hosts;
+/// anything else counts as down.
+fn check_via_proxy(proxy: &str, hostname: &str, port: u16) -> HostStatus {
+    let mut jumps: Vec<&str> = proxy.split(',').map(str::trim).collect();
+    let last = match jumps.pop() {
+        Some(last) if !last.is_empty() => last.to_string(),
+        _ => return HostStatus::Down,
+    };
+    let mut cmd = Command::new("ssh");
+    cmd.args(["-o", "BatchMode=yes", "-o", "ConnectTimeout=5"]);
+    if !jumps.is_empty() {
+        cmd.arg("-J").arg(jumps.join(","));
+    }
+    cmd.arg("-W")
+        .arg(format!("{}:{}", hostname, port))
+        .arg(last)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+
+    let start = Instant::now();
+    let mut child = match cmd.spawn() {
+        Ok(child) => child,
+        Err(_) => return HostStatus::Down,
+    };
+    let stdout = child.stdout.take().expect("stdout is piped");
+    // Reading blocks until the banner arrives, so do it where it can be abandoned
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut line = String::new();
+        let _ = BufReader::new(stdout).read_line(&mut line);
+        let _ = tx.send(line);
+    });
+    let banner = rx.recv_timeout(PROXY_CHECK_TIMEOUT);
+    let _ = child.kill();
+    let _ = child.wait();
+
+    match banner {
+        Ok(line) if line.starts_with("SSH-") => {
+            HostStatus::up(start.elapsed().as_secs_f64() * 1000.0)
+        }
+        Ok(_) => HostStatus::Down,
+        Err(_) => HostStatus::Timeout,
+    }
+}
+
+/// GET `url`; up only if the response carries `expected_status`.
+fn check_http(url: &str, expected_status: u16) -> HostStatus {
+    let agent = ureq::AgentBuilder::new().timeout(HTTP_TIMEOUT).build();
+    let start = Instant::now();
+    let status = match agent.get(url).call() {
+        Ok(resp) => resp.status(),
+        // ureq reports 4xx/5xx as errors, but those may be what's expected
+        Err(ureq::Error::Status(code, _)) => code,
+        Err(ureq::Error::Transport(_)) => return HostStatus::Down,
+    };
+    if status == expected_status {
+        HostStatus::up(start.elapsed().as_secs_f64() * 1000.0)
+    } else {
+        HostStatus::Down
+    }
+}
+
+/// Resolve `hostname`, using the lookup time as the RTT.
+fn check_dns(hostname: &str) -> (HostStatus, Option<IpAddr>) {
+    let start = Instant::now();
+    match (hostname, 0).to_socket_addrs().ok().and_then(|mut a| a.next()) {
+        Some(addr) => {
+            let rtt = start.elapsed().as_secs_f64() * 1000.0;
+            (HostStatus::up(rtt), Some(addr.ip()))
+        }
+        None => (HostStatus::DnsError, None),
+    }
+}
+
+fn check_tls(url: &str, timeout: Duration) -> HostStatus {
+    let (host, port) = match tls::parse_url(url) {
+        Some(hp) => hp,
+        None => return HostStatus::Down,
+    };
+    let addr = match (host.as_str(), port).to_socket_addrs().ok().and_then(|mut a| a.next()) {
+        Some(a) => a,
+        None => return HostStatus::DnsError,
+    };
+
+    match tls::days_remaining(&host, addr, timeout) {
+        Ok(days) if days < 0 => HostStatus::TlsExpired,
+        Ok(days) if days <= TLS_WARN_DAYS => HostStatus::TlsExpiringSoon { days_remaining: days },
+        Ok(days) => HostStatus::TlsOk { days_remaining: days },
+        Err(e) => io_failure(&e),
+    }
+}
+
+/// Per-reply RTTs in ms. Both Linux and macOS print one line per reply:
+/// `64 bytes from 10.0.0.1: icmp_seq=0 ttl=64 time=1.234 ms`
+/// Some pings print `time<1 ms` for very fast replies; that counts as the bound.
+fn parse_ping_times(output: &str) -> Vec<f64> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let start = line.find("time=").or_else(|| line.find("time<"))?;
+            let rest = &line[start + "time=".len()..];
+            let value = rest.split(|c: char| c.is_whitespace() || c == 'm').next()?;
+            value.parse().ok()
+        })
+        .collect()
+}
+
+/// Loss percentage from the summary line.
+/// macOS: 3 packets transmitted, 2 packets received, 33.3% packet loss
+/// Linux: 3 packets transmitted, 2 received, 33.3333% packet loss, time 2003ms
+fn parse_packet_loss(output: &str) -> Option<f64> {
+    let line = output.lines().find(|l| l.contains("packet loss"))?;
+    line.split(',')
+        .find(|part| part.contains("packet loss"))?
+        .trim()
+        .split('%')
+        .next()?
+        .parse()
+        .ok()
+}
+
+fn mean_stddev(samples: &[f64]) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn connect_timeouts_are_reported_as_timeout() {
+        let timed_out = io::Error::from(io::ErrorKind::TimedOut);
+        assert!(matches!(io_failure(&timed_out), HostStatus::Timeout));
+        let refused = io::Error::from(io::ErrorKind::ConnectionRefused);
+        assert!(matches!(io_failure(&refused), HostStatus::Down));
+    }
+
+    #[test]
+    fn tcp_check_of_a_listener_with_a_full_backlog_times_out() {
+        use std::net::TcpListener;
+
+        // Never accepting, the listener's queue fills up and further SYNs go
+        // unanswered, as with a firewall that drops them
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut queued = Vec::new();
+        while let Ok(stream) = TcpStream::connect_timeout(&addr, Duration::from_millis(200)) {
+            queued.push(stream);
+            assert!(queued.len() < 10_000, "the listener never stopped accepting");
+        }
+
+        let host = Host { hostname: "127.0.0.1".into(), ..Host::default() };
+        let start = Instant::now();
+        let status = CheckMethod::TcpPort(addr.port()).health_check().check(&host);
+        assert!(matches!(status, HostStatus::Timeout), "got {:?}", status);
+        assert!(start.elapsed() < CHECK_TIMEOUT + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn bounded_checks_never_exceed_the_limit() {
+        let in_flight = Arc::new(AtomicU32::new(0));
+        let peak = Arc::new(AtomicU32::new(0));
+        let check = |i: usize| {
+            let in_flight = Arc::clone(&in_flight);
+            let peak = Arc::clone(&peak);
+            async move {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                Some(i)
+            }
+        };
+
+        let mut done = runtime().block_on(run_bounded((0..40).collect(), 4, check));
+        done.sort();
+        assert_eq!(done, (0..40).collect::<Vec<_>>());
+        assert_eq!(peak.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn bounded_checks_of_100_hosts_finish_before_unbounded_ones() {
+        // Checks past the 16th open socket fail and retry a second later, as
+        // when the process runs out of file descriptors; each takes 20ms
+        async fn fake_check(open: Arc<AtomicU32>) -> Option<()> {
+            loop {
+                if open.fetch_add(1, Ordering::SeqCst) < 16 {
+                    tokio::time::sleep(Duration::from_millis(20)).await;
+                    open.fetch_sub(1, Ordering::SeqCst);
+                    return Some(());
                 }
+                open.fetch_sub(1, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_secs(1)).await;
             }
         }
+        // The paused clock jumps straight to the next timer, so this is exact
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .start_paused(true)
+            .build()
+            .unwrap();
+        let time = |limit: usize| {
+            let open = Arc::new(AtomicU32::new(0));
+            rt.block_on(async {
+                let start = tokio::time::Instant::now();
+                let done =
+                    run_bounded((0..100).collect(), limit, |_: usize| fake_check(Arc::clone(&open)))
+                        .await;
+                assert_eq!(done.len(), 100);
+                start.elapsed()
+            })
+        };
+
+        let bounded = time(4);
+        let unbounded = time(100);
+        assert_eq!(bounded, Duration::from_millis(25 * 20));
+        assert!(bounded < unbounded, "bounded {:?}, unbounded {:?}", bounded, unbounded);
+    }
+
+    const LINUX_PING: &str = "\
+PING 10.0.0.1 (10.0.0.1) 56(84) bytes of data.
+64 bytes from 10.0.0.1: icmp_seq=1 ttl=64 time=0.512 ms
+64 bytes from 10.0.0.1: icmp_seq=2 ttl=64 time=1.03 ms
+64 bytes from 10.0.0.1: icmp_seq=3 ttl=64 time=12.4 ms
+
+--- 10.0.0.1 ping statistics ---
+3 packets transmitted, 3 received, 0% packet loss, time 402ms
+rtt min/avg/max/mdev = 0.512/4.647/12.400/5.487 ms
+";
+
+    const LINUX_PING_LOST: &str = "\
+PING 10.0.0.9 (10.0.0.9) 56(84) bytes of data.
+From 10.0.0.2 icmp_seq=1 Destination Host Unreachable
+From 10.0.0.2 icmp_seq=2 Destination Host Unreachable
+
+--- 10.0.0.9 ping statistics ---
+3 packets transmitted, 0 received, +2 errors, 100% packet loss, time 2035ms
+";
+
+    const MACOS_PING: &str = "\
+PING 10.0.0.1 (10.0.0.1): 56 data bytes
+64 bytes from 10.0.0.1: icmp_seq=0 ttl=64 time=3.207 ms
+64 bytes from 10.0.0.1: icmp_seq=2 ttl=64 time=2.993 ms
+
+--- 10.0.0.1 ping statistics ---
+3 packets transmitted, 2 packets received, 33.3% packet loss
+round-trip min/avg/max/stddev = 2.993/3.100/3.207/0.107 ms
+";
+
+    const MACOS_PING_LOST: &str = "\
+PING 10.0.0.9 (10.0.0.9): 56 data bytes
+Request timeout for icmp_seq 0
+Request timeout for icmp_seq 1
+
+--- 10.0.0.9 ping statistics ---
+3 packets transmitted, 0 packets received, 100.0% packet loss
+";
+
+    #[test]
+    fn ping_times_come_from_each_reply() {
+        assert_eq!(parse_ping_times(LINUX_PING), vec![0.512, 1.03, 12.4]);
+        assert_eq!(parse_ping_times(MACOS_PING), vec![3.207, 2.993]);
+        assert!(parse_ping_times(LINUX_PING_LOST).is_empty());
+        assert!(parse_ping_times(MACOS_PING_LOST).is_empty());
+    }
+
+    #[test]
+    fn sub_millisecond_replies_count_as_one_millisecond() {
+        let output = "64 bytes from 10.0.0.1: icmp_seq=1 ttl=64 time<1 ms\n\
+                      64 bytes from 10.0.0.1: icmp_seq=2 ttl=64 time<1ms\n";
+        assert_eq!(parse_ping_times(output), vec![1.0, 1.0]);
+    }
+
+    #[test]
+    fn packet_loss_comes_from_the_summary() {
+        assert_eq!(parse_packet_loss(LINUX_PING), Some(0.0));
+        assert_eq!(parse_packet_loss(LINUX_PING_LOST), Some(100.0));
+        assert_eq!(parse_packet_loss(MACOS_PING), Some(33.3));
+        assert_eq!(parse_packet_loss(MACOS_PING_LOST), Some(100.0));
+        assert_eq!(parse_packet_loss("ping: unknown host nowhere"), None);
+    }
+
+    #[test]
+    fn specs_with_a_port_set_the_check_port() {
+        let parse = CheckMethod::parse_spec;
+        assert_eq!(parse("tcp:8080"), Some((CheckMethod::TcpPort(8080), Some(8080))));
+        assert_eq!(parse("ssh:2222"), Some((CheckMethod::SshBanner, Some(2222))));
+        assert_eq!(parse("ssh"), Some((CheckMethod::SshBanner, None)));
+        assert_eq!(parse("icmp"), Some((CheckMethod::Icmp, None)));
+        let either = CheckMethod::AnyOf(vec![CheckMethod::Icmp, CheckMethod::TcpPort(443)]);
+        assert_eq!(parse("icmp | tcp:443"), Some((either, Some(443))));
+    }
+
+    #[test]
+    fn alert_placeholders_cannot_inject_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let pwned = dir.path().join("pwned");
+        let transition = Transition {
+            alias: format!("x;touch {}", pwned.display()),
+            hostname: "10.0.0.1 %group".into(),
+            group: "it's".into(),
+            alert_command: None,
+            up: false,
+        };
+        let command = expand_alert_command("echo %alias %hostname %group 100%", &transition);
+
+        let output = Command::new("sh").arg("-c").arg(&command).output().unwrap();
+        assert!(!pwned.exists(), "{}", command);
+        let expected = format!("{} 10.0.0.1 %group it's 100%\n", transition.alias);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+    }
+
+    #[test]
+    fn a_result_goes_to_its_host_after_the_list_shifts() {
+        use crate::test_support::host;
+        use std::io::Write;
+        use std::net::TcpListener;
+
+        // An SSH daemon that only answers once the host list has changed
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (accepted_tx, accepted_rx) = std::sync::mpsc::channel();
+        let (answer_tx, answer_rx) = std::sync::mpsc::channel::<()>();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            accepted_tx.send(()).unwrap();
+            answer_rx.recv().unwrap();
+            stream.write_all(b"SSH-2.0-test\r\n").unwrap();
+        });
+
+        let checked = Host {
+            hostname: "127.0.0.1".into(),
+            port,
+            health_check_method: Some(CheckMethod::SshBanner),
+            ..host("checked", "")
+        };
+        let hosts = Arc::new(Mutex::new(vec![checked, host("other", "")]));
+        let handle = check_one(Arc::clone(&hosts), 0);
+
+        accepted_rx.recv().unwrap();
+        hosts.lock().unwrap().insert(0, host("inserted", ""));
+        answer_tx.send(()).unwrap();
+        wait(handle);
+
+        let hosts = hosts.lock().unwrap();
+        assert_eq!(hosts[0].alias, "inserted");
+        assert!(hosts[0].health_history.is_empty());
+        assert_eq!(hosts[1].alias, "checked");
+        assert!(hosts[1].status.is_up(), "{:?}", hosts[1].status);
+    }
+
+    #[test]
+    fn a_hung_check_ends_as_a_timeout() {
+        use crate::test_support::host;
+
+        // Blocks until the test is done with it
+        struct Hung(Mutex<mpsc::Receiver<()>>);
+        impl HealthCheck for Hung {
+            fn check(&self, _host: &Host) -> HostStatus {
+                let _ = self.0.lock().unwrap().recv();
+                HostStatus::up(1.0)
+            }
+        }
+        let (release, hung) = mpsc::channel();
+        let mut scheduler = Scheduler::default();
+        scheduler.checks.insert(CheckMethod::Dns, Box::new(Hung(Mutex::new(hung))));
+        let stuck = Host {
+            status: HostStatus::Checking,
+            health_check_method: Some(CheckMethod::Dns),
+            ..host("stuck", "")
+        };
+        let hosts = Arc::new(Mutex::new(vec![stuck]));
+
+        let check = run_check(
+            Arc::clone(&hosts),
+            Arc::new(scheduler),
+            "stuck".into(),
+            Duration::from_millis(50),
+        );
+        runtime().block_on(check);
+        release.send(()).unwrap();
+
+        let hosts = hosts.lock().unwrap();
+        assert!(matches!(hosts[0].status, HostStatus::Timeout), "{:?}", hosts[0].status);
+        let history: Vec<_> = hosts[0].health_history.iter().map(|r| &r.status).collect();
+        assert!(matches!(history[..], [HostStatus::Timeout]), "{:?}", history);
+    }
+
+    #[test]
+    fn the_scheduler_shares_one_check_per_method() {
+        let mut scheduler = Scheduler::default();
+        scheduler.add(CheckMethod::Dns);
+        scheduler.add(CheckMethod::TcpPort(22));
+        scheduler.add(CheckMethod::Dns);
+        assert_eq!(scheduler.checks.len(), 2);
+
+        let host = Host {
+            hostname: "127.0.0.1".into(),
+            health_check_method: Some(CheckMethod::Dns),
+            ..Host::default()
+        };
+        let (status, ip) = scheduler.check(&host);
+        assert!(status.is_up(), "{:?}", status);
+        assert_eq!(ip, Some(IpAddr::from([127, 0, 0, 1])));
+    }
+
+    #[test]
+    fn a_round_of_transitions_makes_one_notification() {
+        let transition = |alias: &str, up| Transition {
+            alias: alias.into(),
+            hostname: String::new(),
+            group: String::new(),
+            alert_command: None,
+            up,
+        };
+        assert_eq!(notification_body(&[]), None);
+        assert_eq!(notification_body(&[transition("web", false)]).unwrap(), "web went DOWN");
+        let round = [transition("web", false), transition("db", true)];
+        assert_eq!(
+            notification_body(&round).unwrap(),
+            "2 hosts changed state:\nweb went DOWN\ndb went UP"
+        );
     }
-    None
 }
@@ -0,0 +1,59 @@
+use crate::host::sshmap_config_dir;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+/// Oldest entries are dropped once the log grows past this.
+const MAX_ENTRIES: usize = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub alias: String,
+    pub hostname: String,
+    pub connected_at: DateTime<Utc>,
+    /// `None` while the session is running, or if sshmap exited before it ended.
+    pub duration_secs: Option<u64>,
+}
+
+impl HistoryEntry {
+    pub fn new(alias: &str, hostname: &str) -> Self {
+        Self {
+            alias: alias.to_string(),
+            hostname: hostname.to_string(),
+            connected_at: Utc::now(),
+            duration_secs: None,
+        }
+    }
+}
+
+fn history_path() -> PathBuf {
+    sshmap_config_dir().join("history.json")
+}
+
+/// Oldest first. A missing or unreadable file is an empty history.
+pub fn load() -> Vec<HistoryEntry> {
+    fs::read_to_string(history_path())
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+pub fn save(history: &[HistoryEntry]) -> anyhow::Result<()> {
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(history)?;
+    fs::write(&path, json)?;
+    Ok(())
+}
+
+/// Append `entry`, trimming the log to the last `MAX_ENTRIES`.
+pub fn push(history: &mut Vec<HistoryEntry>, entry: HistoryEntry) {
+    history.push(entry);
+    if history.len() > MAX_ENTRIES {
+        let excess = history.len() - MAX_ENTRIES;
+        history.drain(..excess);
+    }
+}
@@ -1,7 +1,25 @@
+use crate::config::Config;
+use crate::health::CheckMethod;
+use crate::watch;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
 use std::fs;
-use std::path::PathBuf;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{OnceLock, RwLock};
+use std::time::{Duration, Instant};
+
+// OpenSSH caps nested Includes at 16 levels
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+pub const RTT_HISTORY_LEN: usize = 20;
+const HEALTH_HISTORY_LEN: usize = 100;
+/// Group of hosts that were never given one.
+pub const DEFAULT_GROUP: &str = "default";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Host {
@@ -9,10 +27,204 @@ pub struct Host {
     pub hostname: String,
     pub user: String,
     pub port: u16,
-    pub identity_file: Option<String>,
+    /// Keys passed with `-i`, which ssh tries in order. Read from the old
+    /// single `identity_file` field too.
+    #[serde(default, alias = "identity_file", with = "identity_files")]
+    pub identity_files: Vec<String>,
+    /// Jump host(s) for `ssh -J`, e.g. `bastion` or `bastion1,bastion2`.
+    #[serde(default)]
+    pub proxy_jump: Option<String>,
+    /// `-L` forwards added when connecting with Shift-Enter.
+    #[serde(default)]
+    pub local_forwards: Vec<PortForward>,
+    /// Shell command run (and waited for) before connecting, e.g. to bring
+    /// up a VPN; the connection is abandoned if it fails.
+    #[serde(default)]
+    pub pre_connect_command: Option<String>,
+    /// Shell command run after the session ends.
+    #[serde(default)]
+    pub post_connect_command: Option<String>,
+    /// Variables set in ssh's environment, e.g. `AWS_PROFILE`.
+    #[serde(default)]
+    pub environment: HashMap<String, String>,
+    /// Extra ssh arguments, e.g. `-o ServerAliveInterval=30`, one per entry.
+    #[serde(default)]
+    pub extra_ssh_options: Vec<String>,
+    /// Connect with mosh instead of ssh on Enter.
+    #[serde(default)]
+    pub use_mosh: bool,
+    /// Where mosh-server lives on the host, if not on its PATH.
+    #[serde(default)]
+    pub mosh_server_path: Option<String>,
+    /// Overrides `Config::record_sessions` for this host.
+    #[serde(default)]
+    pub record_sessions: Option<bool>,
     pub group: String,
+    /// Free-form note shown in the detail pane.
+    pub description: Option<String>,
+    /// Cross-cutting labels (`eu-west`, `db`) matched by `#tag` filters.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub last_connected: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub connection_count: u32,
+    /// Overrides `Config::health_check_method` for this host.
+    #[serde(default)]
+    pub health_check_method: Option<CheckMethod>,
+    /// Port to check instead of the SSH port.
+    #[serde(default)]
+    pub health_check_port: Option<u16>,
+    /// Run instead of `Config::on_host_down`/`on_host_up` for this host.
+    #[serde(default)]
+    pub alert_command_override: Option<String>,
     #[serde(skip)]
     pub status: HostStatus,
+    /// Address from the last DNS check.
+    #[serde(skip)]
+    pub resolved_ip: Option<IpAddr>,
+    /// Recent RTTs, oldest first; persisted in state.json rather than here.
+    #[serde(skip)]
+    pub rtt_history: VecDeque<f64>,
+    /// Recent check results, oldest first; kept in memory only.
+    #[serde(skip)]
+    pub health_history: VecDeque<HealthRecord>,
+    #[serde(skip)]
+    pub source: HostSource,
+    /// The ssh config file (~/.ssh/config or an included one) defining the host.
+    #[serde(skip)]
+    pub ssh_config_source: Option<PathBuf>,
+    /// For a `Merged` host, the two entries it was built from.
+    #[serde(skip)]
+    pub merged_from: Option<Box<MergedFrom>>,
+}
+
+/// The ~/.ssh/config host and sshmap's entry for the same alias, kept so
+/// that saving writes back only what changed rather than the merged values.
+#[derive(Debug, Clone)]
+pub struct MergedFrom {
+    pub ssh_config: Host,
+    pub sshmap: Host,
+}
+
+/// A local port forward, `local_port:remote_host:remote_port` as for `ssh -L`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortForward {
+    pub local_port: u16,
+    pub remote_host: String,
+    pub remote_port: u16,
+}
+
+impl fmt::Display for PortForward {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.local_port, self.remote_host, self.remote_port)
+    }
+}
+
+impl std::str::FromStr for PortForward {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{}' is not local_port:remote_host:remote_port", s);
+        let (local, rest) = s.split_once(':').ok_or_else(invalid)?;
+        let (host, remote) = rest.rsplit_once(':').ok_or_else(invalid)?;
+        let port = |p: &str| p.parse::<u16>().ok().filter(|p| *p > 0);
+        match (port(local), port(remote)) {
+            (Some(local_port), Some(remote_port)) if !host.is_empty() => Ok(PortForward {
+                local_port,
+                remote_host: host.to_string(),
+                remote_port,
+            }),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Serde for `Host::identity_files`: a single key is written as a plain
+/// string, as hosts had before they could have several, and a string, a
+/// list or null is accepted when reading.
+mod identity_files {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    pub fn serialize<S: Serializer>(keys: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+        match keys {
+            [key] => key.serialize(serializer),
+            keys => keys.serialize(serializer),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(match Option::<OneOrMany>::deserialize(deserializer)? {
+            None => Vec::new(),
+            Some(OneOrMany::One(key)) => vec![key],
+            Some(OneOrMany::Many(keys)) => keys,
+        })
+    }
+}
+
+/// Where a host was loaded from; only sshmap's own config is written back.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HostSource {
+    SshConfig,
+    #[default]
+    SshmapConfig,
+    /// In both; sshmap's entry was laid over the ~/.ssh/config one.
+    Merged,
+}
+
+impl HostSource {
+    /// Whether the host has an entry in sshmap's host file.
+    pub fn is_owned(self) -> bool {
+        matches!(self, HostSource::SshmapConfig | HostSource::Merged)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            HostSource::SshConfig => "~/.ssh/config",
+            HostSource::SshmapConfig => "sshmap",
+            HostSource::Merged => "~/.ssh/config + sshmap",
+        }
+    }
+}
+
+/// What to do with an alias defined in both ~/.ssh/config and sshmap's file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergeStrategy {
+    /// Combine them field by field with `merge_host`.
+    #[default]
+    Merge,
+    /// Keep the ~/.ssh/config entry and ignore sshmap's.
+    PreferSshConfig,
+    /// Keep sshmap's entry and ignore the ~/.ssh/config one.
+    PreferSshmap,
+}
+
+/// `Config::merge_strategy`, applied by `load_hosts`.
+static MERGE_STRATEGY: RwLock<MergeStrategy> = RwLock::new(MergeStrategy::Merge);
+
+/// Bumped by `Host::record_status`, on whichever thread the check finished.
+static STATUS_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Changes each time any host records a check result, so anything measured
+/// from the statuses, like the table's column widths, knows to measure again.
+pub fn status_generation() -> u64 {
+    STATUS_GENERATION.load(Ordering::Relaxed)
+}
+
+/// Apply the loading settings from config.toml.
+pub fn configure(config: &Config) {
+    *MERGE_STRATEGY.write().unwrap() = config.merge_strategy;
 }
 
 #[derive(Debug, Clone, Default)]
@@ -20,34 +232,244 @@ pub enum HostStatus {
     #[default]
     Unknown,
     Checking,
-    Up(f64),   // rtt ms
+    /// RTT mean and standard deviation in ms, packet loss in percent.
+    Up { mean: f64, stddev: f64, loss: f64 },
+    /// ICMP got no answer but the SSH port accepted a connection (rtt ms).
+    TcpOpen(f64),
     Down,
+    /// Reachable by name but nothing answered within the check window.
+    Timeout,
+    /// The hostname did not resolve.
+    DnsError,
+    /// TLS checks: the served certificate and how long it has left.
+    TlsOk { days_remaining: i64 },
+    TlsExpiringSoon { days_remaining: i64 },
+    TlsExpired,
 }
 
-impl Host {
-    pub fn status_label(&self) -> &str {
-        match &self.status {
-            HostStatus::Unknown => "?",
+#[derive(Debug, Clone)]
+pub struct HealthRecord {
+    pub timestamp: Instant,
+    pub status: HostStatus,
+    pub rtt_ms: Option<f64>,
+}
+
+impl HostStatus {
+    /// Up with a single RTT sample (TCP connect, SSH banner).
+    pub fn up(rtt: f64) -> Self {
+        HostStatus::Up { mean: rtt, stddev: 0.0, loss: 0.0 }
+    }
+
+    /// Reachable by either ICMP or the TCP fallback.
+    pub fn is_up(&self) -> bool {
+        matches!(
+            self,
+            HostStatus::Up { .. }
+                | HostStatus::TcpOpen(_)
+                | HostStatus::TlsOk { .. }
+                | HostStatus::TlsExpiringSoon { .. }
+        )
+    }
+
+    pub fn rtt(&self) -> Option<f64> {
+        match self {
+            HostStatus::Up { mean, .. } => Some(*mean),
+            HostStatus::TcpOpen(rtt) => Some(*rtt),
+            _ => None,
+        }
+    }
+
+    /// Short form for the status column.
+    pub fn label(&self) -> &'static str {
+        match self {
+            HostStatus::Unknown => "—",
             HostStatus::Checking => "...",
-            HostStatus::Up(_) => "UP",
+            HostStatus::Up { .. } => "UP",
+            HostStatus::TcpOpen(_) => "TCP↑",
             HostStatus::Down => "DOWN",
+            HostStatus::Timeout => "TIME",
+            HostStatus::DnsError => "DNS?",
+            HostStatus::TlsOk { .. } => "TLS",
+            HostStatus::TlsExpiringSoon { .. } => "TLS!",
+            HostStatus::TlsExpired => "EXPD",
+        }
+    }
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Self {
+            alias: String::new(),
+            hostname: String::new(),
+            user: String::new(),
+            port: 22,
+            identity_files: Vec::new(),
+            proxy_jump: None,
+            local_forwards: Vec::new(),
+            pre_connect_command: None,
+            post_connect_command: None,
+            environment: HashMap::new(),
+            extra_ssh_options: Vec::new(),
+            use_mosh: false,
+            mosh_server_path: None,
+            record_sessions: None,
+            group: DEFAULT_GROUP.to_string(),
+            description: None,
+            tags: Vec::new(),
+            last_connected: None,
+            connection_count: 0,
+            health_check_method: None,
+            health_check_port: None,
+            alert_command_override: None,
+            status: HostStatus::Unknown,
+            resolved_ip: None,
+            rtt_history: VecDeque::new(),
+            health_history: VecDeque::new(),
+            source: HostSource::default(),
+            ssh_config_source: None,
+            merged_from: None,
+        }
+    }
+}
+
+impl Host {
+    pub fn status_label(&self) -> &'static str {
+        self.status.label()
+    }
+
+    /// Set the latest check result, keeping the RTT trend up to date.
+    pub fn record_status(&mut self, status: HostStatus) {
+        if let Some(rtt) = status.rtt() {
+            if self.rtt_history.len() == RTT_HISTORY_LEN {
+                self.rtt_history.pop_front();
+            }
+            self.rtt_history.push_back(rtt);
+        }
+        if self.health_history.len() == HEALTH_HISTORY_LEN {
+            self.health_history.pop_front();
+        }
+        self.health_history.push_back(HealthRecord {
+            timestamp: Instant::now(),
+            status: status.clone(),
+            rtt_ms: status.rtt(),
+        });
+        self.status = status;
+        STATUS_GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Share of recorded checks in the last 24 hours that came back up.
+    pub fn uptime_24h(&self) -> Option<f64> {
+        let day = Duration::from_secs(24 * 60 * 60);
+        let recent: Vec<&HealthRecord> = self
+            .health_history
+            .iter()
+            .filter(|r| r.timestamp.elapsed() < day)
+            .collect();
+        if recent.is_empty() {
+            return None;
+        }
+        let up = recent
+            .iter()
+            .filter(|r| r.status.is_up())
+            .count();
+        Some(up as f64 / recent.len() as f64 * 100.0)
+    }
+
+    /// Whether any check within the last `window` found the host up.
+    pub fn up_within(&self, window: Duration) -> bool {
+        self.health_history
+            .iter()
+            .any(|r| r.status.is_up() && r.timestamp.elapsed() < window)
+    }
+
+    /// The plain filter match: `#tag` matches a tag exactly, anything else is
+    /// a substring of the alias, hostname, group or user. `query` is lowercase.
+    pub fn matches_filter(&self, query: &str) -> bool {
+        match query.strip_prefix('#') {
+            Some(tag) => self.tags.iter().any(|t| t.to_lowercase() == tag),
+            None => {
+                self.alias.to_lowercase().contains(query)
+                    || self.hostname.to_lowercase().contains(query)
+                    || self.group.to_lowercase().contains(query)
+                    || self.user.to_lowercase().contains(query)
+            }
         }
     }
 
     pub fn rtt_label(&self) -> String {
         match &self.status {
-            HostStatus::Up(rtt) => format!("{:.0}ms", rtt),
-            _ => "—".to_string(),
+            HostStatus::Up { mean, stddev, .. } if *stddev > 0.0 => {
+                format!("{:.0}ms±{:.0}", mean, stddev)
+            }
+            HostStatus::TlsOk { days_remaining }
+            | HostStatus::TlsExpiringSoon { days_remaining } => {
+                format!("cert:{}d", days_remaining)
+            }
+            HostStatus::TlsExpired => "cert:exp".to_string(),
+            status => match status.rtt() {
+                Some(rtt) => format!("{:.0}ms", rtt),
+                None => "—".to_string(),
+            },
         }
     }
 
-    pub fn ssh_command(&self) -> Vec<String> {
+    /// The ssh invocation for this host; `include_forwards` adds a `-L` for
+    /// each of `local_forwards`.
+    pub fn ssh_command(&self, include_forwards: bool) -> Vec<String> {
         let mut args = vec!["ssh".to_string()];
+        if let Some(ref proxy) = self.proxy_jump {
+            args.push("-J".to_string());
+            args.push(proxy.clone());
+        }
+        if include_forwards {
+            for forward in &self.local_forwards {
+                args.push("-L".to_string());
+                args.push(forward.to_string());
+            }
+        }
         if self.port != 22 {
             args.push("-p".to_string());
             args.push(self.port.to_string());
         }
-        if let Some(ref key) = self.identity_file {
+        for key in &self.identity_files {
+            args.push("-i".to_string());
+            args.push(key.clone());
+        }
+        args.extend(self.extra_ssh_options.iter().cloned());
+        if !self.user.is_empty() {
+            args.push(format!("{}@{}", self.user, self.hostname));
+        } else {
+            args.push(self.hostname.clone());
+        }
+        args
+    }
+
+    /// The mosh invocation for this host, given the ssh invocation (as from
+    /// `Config::ssh_command`) mosh starts its server with. Its options (port,
+    /// key, jump host) go in `--ssh`, quoted since mosh splits it into words.
+    pub fn mosh_command(&self, mut ssh: Vec<String>) -> Vec<String> {
+        let destination = ssh.pop().unwrap_or_default();
+        let mut args = vec!["mosh".to_string()];
+        if let Some(ref server) = self.mosh_server_path {
+            args.push(format!("--server={}", server));
+        }
+        args.push("--ssh".to_string());
+        args.push(ssh.iter().map(|a| shell_quote(a)).collect::<Vec<_>>().join(" "));
+        args.push(destination);
+        args
+    }
+
+    pub fn sftp_command(&self) -> Vec<String> {
+        let mut args = vec!["sftp".to_string()];
+        if let Some(ref proxy) = self.proxy_jump {
+            args.push("-J".to_string());
+            args.push(proxy.clone());
+        }
+        if self.port != 22 {
+            args.push("-P".to_string());
+            args.push(self.port.to_string());
+        }
+        for key in &self.identity_files {
             args.push("-i".to_string());
             args.push(key.clone());
         }
@@ -68,39 +490,461 @@ pub fn load_hosts() -> Vec<Host> {
 
     // 2. Load sshmap's own config (overrides/supplements)
     if let Some(extra) = load_sshmap_config() {
+        let strategy = *MERGE_STRATEGY.read().unwrap();
         for h in extra {
-            // Don't duplicate aliases already from ssh config
-            if !hosts.iter().any(|existing| existing.alias == h.alias) {
-                hosts.push(h);
+            let existing = match hosts.iter().position(|e| e.alias == h.alias) {
+                Some(i) => i,
+                None => {
+                    hosts.push(h);
+                    continue;
+                }
+            };
+            match strategy {
+                MergeStrategy::Merge => {
+                    let base = hosts[existing].clone();
+                    hosts[existing] = merge_host(base, h);
+                }
+                MergeStrategy::PreferSshConfig => {}
+                MergeStrategy::PreferSshmap => hosts[existing] = h,
+            }
+        }
+    }
+
+    sort_hosts(&mut hosts);
+    hosts
+}
+
+/// Lay sshmap's entry for a host over its ~/.ssh/config entry. Anything
+/// `override_` sets (a non-empty string, a non-22 port, a `Some`, a tag)
+/// wins, so sshmap's identity file, description, tags and group take
+/// precedence while the ssh config's hostname and port stand unless
+/// sshmap's file gives its own.
+pub fn merge_host(base: Host, override_: Host) -> Host {
+    let merged_from = MergedFrom { ssh_config: base.clone(), sshmap: override_.clone() };
+    let or_base = |value: String, base: String| if value.is_empty() { base } else { value };
+    let group = if override_.group.is_empty() || override_.group == DEFAULT_GROUP {
+        base.group
+    } else {
+        override_.group
+    };
+    Host {
+        alias: base.alias,
+        hostname: or_base(override_.hostname, base.hostname),
+        user: or_base(override_.user, base.user),
+        port: if override_.port != 22 { override_.port } else { base.port },
+        identity_files: if override_.identity_files.is_empty() {
+            base.identity_files
+        } else {
+            override_.identity_files
+        },
+        proxy_jump: override_.proxy_jump.or(base.proxy_jump),
+        local_forwards: if override_.local_forwards.is_empty() {
+            base.local_forwards
+        } else {
+            override_.local_forwards
+        },
+        pre_connect_command: override_.pre_connect_command.or(base.pre_connect_command),
+        post_connect_command: override_.post_connect_command.or(base.post_connect_command),
+        environment: if override_.environment.is_empty() {
+            base.environment
+        } else {
+            override_.environment
+        },
+        extra_ssh_options: if override_.extra_ssh_options.is_empty() {
+            base.extra_ssh_options
+        } else {
+            override_.extra_ssh_options
+        },
+        use_mosh: override_.use_mosh || base.use_mosh,
+        mosh_server_path: override_.mosh_server_path.or(base.mosh_server_path),
+        record_sessions: override_.record_sessions.or(base.record_sessions),
+        group,
+        description: override_.description.or(base.description),
+        tags: if override_.tags.is_empty() { base.tags } else { override_.tags },
+        last_connected: override_.last_connected.max(base.last_connected),
+        connection_count: override_.connection_count.max(base.connection_count),
+        health_check_method: override_.health_check_method.or(base.health_check_method),
+        health_check_port: override_.health_check_port.or(base.health_check_port),
+        alert_command_override: override_.alert_command_override.or(base.alert_command_override),
+        source: HostSource::Merged,
+        merged_from: Some(Box::new(merged_from)),
+        ..base
+    }
+}
+
+/// What sshmap's file should hold for `host`. A merged host's own entry is
+/// written back with just the fields changed since it was loaded, so values
+/// from ~/.ssh/config never end up in sshmap's file as overrides.
+pub fn sshmap_entry(host: &Host) -> Host {
+    use serde_json::Value;
+    let Some(from) = host.merged_from.as_deref() else {
+        return host.clone();
+    };
+    let loaded = merge_host(from.ssh_config.clone(), from.sshmap.clone());
+    let fields = |host: &Host| match serde_json::to_value(host) {
+        Ok(Value::Object(fields)) => fields,
+        _ => serde_json::Map::new(),
+    };
+    let (now, loaded, mut entry) = (fields(host), fields(&loaded), fields(&from.sshmap));
+    for (key, value) in &now {
+        if loaded.get(key) != Some(value) {
+            entry.insert(key.clone(), value.clone());
+        }
+    }
+    // Cleared since loading, like a removed description
+    for key in loaded.keys().filter(|key| !now.contains_key(*key)) {
+        entry.remove(key);
+    }
+    serde_json::from_value(Value::Object(entry)).unwrap_or_else(|_| from.sshmap.clone())
+}
+
+/// Replace `current` with a freshly loaded list, carrying over the runtime
+/// state (status, RTT and check history) of hosts that are still there.
+pub fn merge_hosts(current: &mut Vec<Host>, mut fresh: Vec<Host>) {
+    for host in &mut fresh {
+        if let Some(old) = current.iter_mut().find(|h| h.alias == host.alias) {
+            host.status = std::mem::take(&mut old.status);
+            host.resolved_ip = old.resolved_ip;
+            host.rtt_history = std::mem::take(&mut old.rtt_history);
+            host.health_history = std::mem::take(&mut old.health_history);
+            // Hosts from ~/.ssh/config only track these in memory
+            if host.source == HostSource::SshConfig {
+                host.last_connected = old.last_connected;
+                host.connection_count = old.connection_count;
             }
         }
     }
+    *current = fresh;
+}
 
-    // Sort by group then alias
+/// Sort by group then alias
+pub fn sort_hosts(hosts: &mut [Host]) {
     hosts.sort_by(|a, b| {
         a.group
             .cmp(&b.group)
             .then(a.alias.cmp(&b.alias))
     });
+}
 
-    hosts
+pub fn ssh_config_path() -> PathBuf {
+    dirs_home().join(".ssh").join("config")
+}
+
+/// Aliases with more than one `Host` block across ~/.ssh/config and its
+/// includes; only the first block is used.
+pub fn duplicate_ssh_config_aliases() -> Vec<String> {
+    let hosts = parse_ssh_config_file(&ssh_config_path(), &mut Vec::new(), &mut Vec::new());
+    let mut seen = std::collections::HashSet::new();
+    let mut duplicates: Vec<String> = hosts
+        .into_iter()
+        .filter(|h| !seen.insert(h.alias.clone()))
+        .map(|h| h.alias)
+        .collect();
+    duplicates.sort();
+    duplicates.dedup();
+    duplicates
 }
 
 fn parse_ssh_config() -> Vec<Host> {
-    let home = dirs_home();
-    let config_path = home.join(".ssh").join("config");
-    let content = match fs::read_to_string(&config_path) {
+    let mut match_blocks = Vec::new();
+    let mut hosts = parse_ssh_config_file(&ssh_config_path(), &mut Vec::new(), &mut match_blocks);
+
+    // First definition wins, as in OpenSSH (also drops repeats from include cycles)
+    let mut seen = std::collections::HashSet::new();
+    hosts.retain(|h| seen.insert(h.alias.clone()));
+
+    // Match blocks can sit anywhere, so they're applied once every host is known
+    for host in &mut hosts {
+        for block in &match_blocks {
+            if block.matches(host) {
+                block.apply(host);
+            }
+        }
+    }
+
+    hosts
+}
+
+/// What a `Match` line tests; other OpenSSH criteria (`exec`, `canonical`,
+/// `localuser`, negation) aren't supported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MatchCriterion {
+    All,
+    /// The hostname after any `HostName` substitution.
+    Host,
+    /// The alias as typed on the command line.
+    OriginalHost,
+    User,
+}
+
+/// A `Match` block from ~/.ssh/config: its criteria and the directives
+/// inside it, kept as lowercase keyword and value.
+#[derive(Debug, Clone)]
+struct MatchBlock {
+    criteria: Vec<(MatchCriterion, String)>,
+    directives: Vec<(String, String)>,
+}
+
+impl MatchBlock {
+    /// Parse the rest of a `Match` line, e.g. `User deploy Host *.prod`.
+    /// `None`, with a warning logged, if it uses anything unsupported.
+    fn parse(line: &str) -> Option<MatchBlock> {
+        let mut criteria = Vec::new();
+        let mut words = line.split_whitespace();
+        while let Some(word) = words.next() {
+            let criterion = match word.to_lowercase().as_str() {
+                "all" => {
+                    criteria.push((MatchCriterion::All, String::new()));
+                    continue;
+                }
+                "host" => MatchCriterion::Host,
+                "originalhost" => MatchCriterion::OriginalHost,
+                "user" => MatchCriterion::User,
+                _ => {
+                    tracing::warn!("ignoring Match block with unsupported criterion '{}'", word);
+                    return None;
+                }
+            };
+            match words.next() {
+                Some(patterns) => criteria.push((criterion, patterns.to_string())),
+                None => {
+                    tracing::warn!("ignoring Match block: '{}' needs a pattern", word);
+                    return None;
+                }
+            }
+        }
+        Some(MatchBlock { criteria, directives: Vec::new() })
+    }
+
+    fn matches(&self, host: &Host) -> bool {
+        self.criteria.iter().all(|(criterion, patterns)| match criterion {
+            MatchCriterion::All => true,
+            // Hostnames compare case-insensitively, user names don't
+            MatchCriterion::Host => {
+                match_pattern_list(&patterns.to_lowercase(), &host.hostname.to_lowercase())
+            }
+            MatchCriterion::OriginalHost => {
+                match_pattern_list(&patterns.to_lowercase(), &host.alias.to_lowercase())
+            }
+            // ssh logs in as the local user when none is configured
+            MatchCriterion::User if host.user.is_empty() => {
+                match_pattern_list(patterns, &std::env::var("USER").unwrap_or_default())
+            }
+            MatchCriterion::User => match_pattern_list(patterns, &host.user),
+        })
+    }
+
+    /// Fill in what `host` doesn't set itself; as in OpenSSH, the first
+    /// value obtained for a setting is the one used.
+    fn apply(&self, host: &mut Host) {
+        for (key, val) in &self.directives {
+            match key.as_str() {
+                "hostname" if host.hostname == host.alias => host.hostname = val.clone(),
+                "user" if host.user.is_empty() => host.user = val.clone(),
+                "port" if host.port == 22 => host.port = val.parse().unwrap_or(22),
+                "proxyjump" if host.proxy_jump.is_none() => {
+                    host.proxy_jump = Some(val.clone()).filter(|v| !v.eq_ignore_ascii_case("none"))
+                }
+                // Unlike most settings, identity files add up
+                "identityfile" => {
+                    let key = val.replace('~', &dirs_home().to_string_lossy());
+                    let key =
+                        expand_ssh_tokens(&key, &host.alias, &host.hostname, &host.user, host.port);
+                    if !host.identity_files.contains(&key) {
+                        host.identity_files.push(key);
+                    }
+                }
+                "localforward" => {
+                    let spec = val.split_whitespace().collect::<Vec<_>>().join(":");
+                    if let Ok(forward) = spec.parse() {
+                        host.local_forwards.push(forward);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Whether `name` matches an ssh_config pattern list such as
+/// `*.prod,!db.prod`: some pattern matches and no negated one does.
+fn match_pattern_list(list: &str, name: &str) -> bool {
+    let mut matched = false;
+    for pattern in list.split(',') {
+        match pattern.strip_prefix('!') {
+            Some(negated) if wildcard_match(negated, name) => return false,
+            Some(_) => {}
+            None => matched |= wildcard_match(pattern, name),
+        }
+    }
+    matched
+}
+
+/// Glob match with `*` (any run of characters) and `?` (one character).
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    // Backtrack to just after the last `*`, letting it swallow one more character
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((sp, sn)) => {
+                    p = sp + 1;
+                    n = sn + 1;
+                    star = Some((sp, sn + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Expand the `%` tokens ssh_config allows in `IdentityFile` and similar
+/// paths: `%h` the hostname, `%n` the alias as typed, `%p` the port, `%r`
+/// the remote user, `%u` the local user, `%d` the local home directory and
+/// `%%` a literal `%`. Unknown tokens are left as they are.
+pub fn expand_ssh_tokens(val: &str, alias: &str, hostname: &str, user: &str, port: u16) -> String {
+    let local_user = std::env::var("USER").unwrap_or_default();
+    let mut out = String::with_capacity(val.len());
+    let mut chars = val.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => out.push('%'),
+            Some('h') => out.push_str(hostname),
+            Some('n') => out.push_str(alias),
+            Some('p') => out.push_str(&port.to_string()),
+            // ssh logs in as the local user when none is configured
+            Some('r') if user.is_empty() => out.push_str(&local_user),
+            Some('r') => out.push_str(user),
+            Some('u') => out.push_str(&local_user),
+            Some('d') => out.push_str(&dirs_home().to_string_lossy()),
+            Some(other) => {
+                out.push('%');
+                out.push(other);
+            }
+            None => out.push('%'),
+        }
+    }
+    out
+}
+
+/// The `HostName` value for `alias`, which defaults to the alias itself;
+/// `%h` in it stands for the alias.
+fn expand_hostname(hostname: &str, alias: &str, user: &str, port: u16) -> String {
+    if hostname.is_empty() {
+        alias.to_string()
+    } else {
+        expand_ssh_tokens(hostname, alias, alias, user, port)
+    }
+}
+
+/// Directives whose value is a path, where `#` is an ordinary character
+/// rather than the start of a comment.
+const PATH_DIRECTIVES: &[&str] = &[
+    "certificatefile",
+    "controlpath",
+    "identityagent",
+    "identityfile",
+    "include",
+    "userknownhostsfile",
+];
+
+/// Split a config line into its lowercased keyword and value, accepting
+/// `Keyword value` and `Keyword=value`. A `#` after whitespace starts a
+/// trailing comment except in `PATH_DIRECTIVES`; a quoted value is unquoted.
+fn split_directive(line: &str) -> Option<(String, String)> {
+    let end = line.find(|c: char| c.is_whitespace() || c == '=')?;
+    let key = line[..end].to_lowercase();
+    let rest = line[end..].trim_start();
+    let mut val = rest.strip_prefix('=').unwrap_or(rest).trim_start();
+    if !PATH_DIRECTIVES.contains(&key.as_str()) {
+        let comment = val
+            .match_indices('#')
+            .find(|(i, _)| *i == 0 || val[..*i].ends_with(char::is_whitespace));
+        if let Some((i, _)) = comment {
+            val = &val[..i];
+        }
+    }
+    let val = val.trim();
+    let val = val.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(val);
+    if key.is_empty() || val.is_empty() {
+        return None;
+    }
+    Some((key, val.to_string()))
+}
+
+/// Hosts defined in `path` and the files it includes. `Match` blocks are
+/// added to `match_blocks` for `parse_ssh_config` to apply afterwards.
+/// `including` holds the canonical paths of the files whose `Include`
+/// led here, so a file that includes itself, by any path, is skipped.
+fn parse_ssh_config_file(
+    path: &Path,
+    including: &mut Vec<PathBuf>,
+    match_blocks: &mut Vec<MatchBlock>,
+) -> Vec<Host> {
+    let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    if including.len() > MAX_INCLUDE_DEPTH || including.contains(&canonical) {
+        return Vec::new();
+    }
+    let content = match fs::read_to_string(path) {
         Ok(c) => c,
         Err(_) => return Vec::new(),
     };
 
+    including.push(canonical);
+    let hosts = parse_ssh_config_lines(path, &content, including, match_blocks);
+    including.pop();
+    hosts
+}
+
+/// The hosts in `content`, read from `path`.
+fn parse_ssh_config_lines(
+    path: &Path,
+    content: &str,
+    including: &mut Vec<PathBuf>,
+    match_blocks: &mut Vec<MatchBlock>,
+) -> Vec<Host> {
     let mut hosts = Vec::new();
+    // Hosts from an `Include` inside a Host block, held back until that
+    // block's host has been added so the file order is kept
+    let mut included: Vec<Host> = Vec::new();
     let mut current_alias: Option<String> = None;
     let mut hostname = String::new();
     let mut user = String::new();
     let mut port: u16 = 22;
-    let mut identity: Option<String> = None;
-    let mut group = String::from("default");
+    let mut identities: Vec<String> = Vec::new();
+    let mut proxy: Option<String> = None;
+    let mut forwards: Vec<PortForward> = Vec::new();
+    let mut group = DEFAULT_GROUP.to_string();
+    // The group in effect at the current Host line; a `# group:` comment
+    // inside a block belongs to the hosts after it
+    let mut host_group = group.clone();
+    let mut description: Option<String> = None;
+    // A `# desc:` comment waiting for the Host line right after it
+    let mut pending_desc: Option<String> = None;
+    let mut tags: Vec<String> = Vec::new();
+    let mut pending_tags: Vec<String> = Vec::new();
+    let mut health: Option<(CheckMethod, Option<u16>)> = None;
+    let mut pending_health: Option<(CheckMethod, Option<u16>)> = None;
+    let mut extra_opts: Vec<String> = Vec::new();
+    let mut pending_extra_opts: Vec<String> = Vec::new();
+    // Set inside a `Match` block, whose directives are collected rather than applied
+    let mut current_match: Option<MatchBlock> = None;
 
     for line in content.lines() {
         let trimmed = line.trim();
@@ -110,6 +954,14 @@ fn parse_ssh_config() -> Vec<Host> {
             let tag = tag.trim();
             if let Some(g) = tag.strip_prefix("group:") {
                 group = g.trim().to_string();
+            } else if let Some(d) = tag.strip_prefix("desc:") {
+                pending_desc = Some(unescape_comment(d.trim())).filter(|d| !d.is_empty());
+            } else if let Some(t) = tag.strip_prefix("tags:") {
+                pending_tags = parse_tags(t);
+            } else if let Some(spec) = tag.strip_prefix("health:") {
+                pending_health = CheckMethod::parse_spec(spec);
+            } else if let Some(opts) = tag.strip_prefix("extra-opts:") {
+                pending_extra_opts = parse_ssh_options(opts);
             }
             continue;
         }
@@ -118,129 +970,1293 @@ fn parse_ssh_config() -> Vec<Host> {
             continue;
         }
 
-        let parts: Vec<&str> = trimmed.splitn(2, char::is_whitespace).collect();
-        if parts.len() < 2 {
+        let Some((key, val)) = split_directive(trimmed) else {
             continue;
-        }
+        };
 
-        let key = parts[0].to_lowercase();
-        let val = parts[1].trim().to_string();
+        if let Some(block) = current_match.as_mut() {
+            if !matches!(key.as_str(), "host" | "match" | "include") {
+                block.directives.push((key, val));
+                continue;
+            }
+        }
 
         match key.as_str() {
-            "host" => {
+            "host" | "match" => {
                 // Save previous host
                 if let Some(alias) = current_alias.take() {
                     if !alias.contains('*') && !alias.contains('?') {
-                        let h = hostname.clone();
+                        let h = expand_hostname(&hostname, &alias, &user, port);
+                        let identities: Vec<String> = identities
+                            .iter()
+                            .map(|key| expand_ssh_tokens(key, &alias, &h, &user, port))
+                            .collect();
                         hosts.push(Host {
-                            alias: alias.clone(),
-                            hostname: if h.is_empty() { alias } else { h },
+                            alias,
+                            hostname: h,
                             user: user.clone(),
                             port,
-                            identity_file: identity.clone(),
-                            group: group.clone(),
-                            status: HostStatus::Unknown,
+                            identity_files: identities,
+                            proxy_jump: proxy.take(),
+                            local_forwards: std::mem::take(&mut forwards),
+                            group: host_group.clone(),
+                            description: description.take(),
+                            tags: std::mem::take(&mut tags),
+                            health_check_method: health.as_ref().map(|(m, _)| m.clone()),
+                            health_check_port: health.take().and_then(|(_, p)| p),
+                            extra_ssh_options: std::mem::take(&mut extra_opts),
+                            source: HostSource::SshConfig,
+                            ssh_config_source: Some(path.to_path_buf()),
+                            ..Default::default()
                         });
                     }
                 }
-                current_alias = Some(val);
+                hosts.append(&mut included);
+                match_blocks.extend(current_match.take());
+                if key == "host" {
+                    current_alias = Some(val);
+                } else {
+                    current_match = MatchBlock::parse(&val);
+                }
+                host_group = group.clone();
                 hostname.clear();
                 user.clear();
                 port = 22;
-                identity = None;
+                identities.clear();
+                proxy = None;
+                forwards.clear();
+                description = pending_desc.take();
+                tags = std::mem::take(&mut pending_tags);
+                health = pending_health.take();
+                extra_opts = std::mem::take(&mut pending_extra_opts);
+                continue;
             }
             "hostname" => hostname = val,
             "user" => user = val,
             "port" => port = val.parse().unwrap_or(22),
+            // `ProxyJump none` switches off a jump set by an earlier pattern
+            "proxyjump" => proxy = Some(val).filter(|v| !v.eq_ignore_ascii_case("none")),
+            // `LocalForward 8080 db:5432`; bind addresses and sockets are skipped
+            "localforward" => {
+                let spec = val.split_whitespace().collect::<Vec<_>>().join(":");
+                if let Ok(forward) = spec.parse() {
+                    forwards.push(forward);
+                }
+            }
             "identityfile" => {
                 let expanded = val.replace('~', &dirs_home().to_string_lossy());
-                identity = Some(expanded);
+                identities.push(expanded);
+            }
+            "include" => {
+                // Include accepts several space-separated patterns
+                for pattern in val.split_whitespace() {
+                    included.extend(parse_included_files(pattern, including, match_blocks));
+                }
             }
             _ => {}
         }
+        pending_desc = None;
+        pending_tags.clear();
+        pending_health = None;
+        pending_extra_opts.clear();
     }
 
+    match_blocks.extend(current_match);
+
     // Don't forget the last host
     if let Some(alias) = current_alias {
         if !alias.contains('*') && !alias.contains('?') {
-            let h = hostname;
+            let h = expand_hostname(&hostname, &alias, &user, port);
+            let identities: Vec<String> = identities
+                .iter()
+                .map(|key| expand_ssh_tokens(key, &alias, &h, &user, port))
+                .collect();
             hosts.push(Host {
-                alias: alias.clone(),
-                hostname: if h.is_empty() { alias } else { h },
+                alias,
+                hostname: h,
                 user,
                 port,
-                identity_file: identity,
-                group,
-                status: HostStatus::Unknown,
+                identity_files: identities,
+                proxy_jump: proxy,
+                local_forwards: forwards,
+                group: host_group,
+                description,
+                tags,
+                health_check_method: health.as_ref().map(|(m, _)| m.clone()),
+                health_check_port: health.and_then(|(_, p)| p),
+                extra_ssh_options: extra_opts,
+                source: HostSource::SshConfig,
+                ssh_config_source: Some(path.to_path_buf()),
+                ..Default::default()
             });
         }
     }
+    hosts.extend(included);
 
     hosts
 }
 
-fn sshmap_config_path() -> PathBuf {
-    dirs_home().join(".config").join("sshmap").join("hosts.json")
+/// Render `hosts` as `~/.ssh/config` blocks under a `# Generated by
+/// sshmap` header, grouped under `# group:` comments. Descriptions, tags,
+/// health checks and extra options go in the same comments the parser
+/// reads, so parsing the output gives the same hosts back.
+pub fn export_ssh_config(hosts: &[Host]) -> String {
+    let mut sorted = hosts.to_vec();
+    sort_hosts(&mut sorted);
+
+    let mut out = String::from("# Generated by sshmap\n\n");
+    let mut last_group: Option<&str> = None;
+    for host in &sorted {
+        if last_group != Some(host.group.as_str()) {
+            out.push_str(&format!("# group: {}\n\n", host.group));
+            last_group = Some(&host.group);
+        }
+        // Escaped so a line break in either can't start a directive of its own
+        if let Some(ref desc) = host.description {
+            out.push_str(&format!("# desc: {}\n", escape_comment(desc, "")));
+        }
+        if !host.tags.is_empty() {
+            out.push_str(&format!("# tags: {}\n", join_tags(&host.tags)));
+        }
+        if let Some(ref method) = host.health_check_method {
+            out.push_str(&format!("# health: {}\n", method.spec(host.health_check_port)));
+        }
+        if !host.extra_ssh_options.is_empty() {
+            out.push_str(&format!("# extra-opts: {}\n", host.extra_ssh_options.join(" ")));
+        }
+        out.push_str(&format!("Host {}\n", host.alias));
+        out.push_str(&format!("    HostName {}\n", host.hostname));
+        if !host.user.is_empty() {
+            out.push_str(&format!("    User {}\n", host.user));
+        }
+        out.push_str(&format!("    Port {}\n", host.port));
+        for key in &host.identity_files {
+            out.push_str(&format!("    IdentityFile {}\n", key));
+        }
+        if let Some(ref proxy) = host.proxy_jump {
+            out.push_str(&format!("    ProxyJump {}\n", proxy));
+        }
+        for forward in &host.local_forwards {
+            out.push_str(&format!(
+                "    LocalForward {} {}:{}\n",
+                forward.local_port, forward.remote_host, forward.remote_port
+            ));
+        }
+        out.push('\n');
+    }
+    out
 }
 
-fn load_sshmap_config() -> Option<Vec<Host>> {
-    let path = sshmap_config_path();
-    let content = fs::read_to_string(&path).ok()?;
-    serde_json::from_str(&content).ok()
+/// The host list as RFC 4180 CSV with a header row.
+pub fn export_csv(hosts: &[Host]) -> String {
+    let mut out = String::from(
+        "alias,hostname,user,port,group,identity_file,description,tags,\
+         last_connected,connection_count\r\n",
+    );
+    for host in hosts {
+        let fields = [
+            host.alias.clone(),
+            host.hostname.clone(),
+            host.user.clone(),
+            host.port.to_string(),
+            host.group.clone(),
+            host.identity_files.join(","),
+            host.description.clone().unwrap_or_default(),
+            host.tags.join(","),
+            host.last_connected.map(|t| t.to_rfc3339()).unwrap_or_default(),
+            host.connection_count.to_string(),
+        ];
+        let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+        out.push_str(&row.join(","));
+        out.push_str("\r\n");
+    }
+    out
 }
 
-pub fn save_sshmap_config(hosts: &[Host]) -> anyhow::Result<()> {
-    let path = sshmap_config_path();
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
+/// Quote `field` if it holds a comma, quote or line break, doubling inner quotes.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
     }
-    let json = serde_json::to_string_pretty(hosts)?;
-    fs::write(&path, json)?;
-    Ok(())
 }
 
-pub fn create_sample_config() -> anyhow::Result<()> {
-    let path = sshmap_config_path();
-    if path.exists() {
-        return Ok(());
+/// The hosts in sshmap's own host file, for changing and saving back.
+/// Unlike `load_hosts`, a file that fails to parse is an error, so it is
+/// never overwritten with an empty list.
+pub fn load_owned_hosts() -> anyhow::Result<Vec<Host>> {
+    check_sshmap_config()?;
+    Ok(load_sshmap_config().unwrap_or_default())
+}
+
+/// Add `imported` to sshmap's own host file, skipping aliases that are
+/// already known. Returns how many hosts were added and skipped.
+pub fn import_hosts(imported: Vec<Host>) -> anyhow::Result<(usize, usize)> {
+    let known: Vec<String> = load_hosts().into_iter().map(|h| h.alias).collect();
+    let mut owned = load_owned_hosts()?;
+
+    let (mut added, mut skipped) = (0, 0);
+    for host in imported {
+        if known.contains(&host.alias) || owned.iter().any(|h| h.alias == host.alias) {
+            skipped += 1;
+        } else {
+            owned.push(host);
+            added += 1;
+        }
     }
+    sort_hosts(&mut owned);
+    save_sshmap_config(&owned)?;
+    Ok((added, skipped))
+}
 
-    let sample = vec![
-        Host {
-            alias: "web-prod-1".into(),
-            hostname: "192.168.1.10".into(),
-            user: "deploy".into(),
-            port: 22,
-            identity_file: None,
-            group: "production".into(),
-            status: HostStatus::Unknown,
-        },
-        Host {
+/// Connection settings Ansible keeps in host and group variables.
+#[derive(Debug, Clone, Default)]
+struct AnsibleVars {
+    host: Option<String>,
+    user: Option<String>,
+    port: Option<u16>,
+    key: Option<String>,
+}
+
+impl AnsibleVars {
+    fn set(&mut self, key: &str, value: &str) {
+        match key {
+            "ansible_host" | "ansible_ssh_host" => self.host = Some(value.to_string()),
+            "ansible_user" | "ansible_ssh_user" => self.user = Some(value.to_string()),
+            "ansible_port" | "ansible_ssh_port" => self.port = value.parse().ok(),
+            "ansible_ssh_private_key_file" | "ansible_private_key_file" => {
+                self.key = Some(value.to_string())
+            }
+            _ => {}
+        }
+    }
+
+    /// `self` with anything unset taken from `fallback`.
+    fn or(&self, fallback: &AnsibleVars) -> AnsibleVars {
+        AnsibleVars {
+            host: self.host.clone().or_else(|| fallback.host.clone()),
+            user: self.user.clone().or_else(|| fallback.user.clone()),
+            port: self.port.or(fallback.port),
+            key: self.key.clone().or_else(|| fallback.key.clone()),
+        }
+    }
+
+    fn into_host(self, alias: &str, group: &str) -> Host {
+        Host {
+            alias: alias.to_string(),
+            hostname: self.host.unwrap_or_else(|| alias.to_string()),
+            user: self.user.unwrap_or_default(),
+            port: self.port.unwrap_or(22),
+            identity_files: self
+                .key
+                .map(|k| expand_tilde(&k).to_string_lossy().into_owned())
+                .into_iter()
+                .collect(),
+            group: match group {
+                "all" | "ungrouped" => DEFAULT_GROUP.to_string(),
+                g => g.to_string(),
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// Hosts from an INI Ansible inventory. `[group]` sections name the group;
+/// `[group:vars]` supplies defaults for its hosts and, through
+/// `[group:children]`, for the hosts of its child groups. A host listed
+/// under several groups keeps the most deeply nested one, or the first of
+/// those equally deep.
+pub fn import_from_ansible_ini(path: &Path) -> anyhow::Result<Vec<Host>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+
+    // (alias, group, host vars) in file order; group vars are applied at the end
+    let mut entries: Vec<(String, String, AnsibleVars)> = Vec::new();
+    let mut group_vars: HashMap<String, AnsibleVars> = HashMap::new();
+    // Child group -> the groups listing it under `[parent:children]`
+    let mut parents: HashMap<String, Vec<String>> = HashMap::new();
+    let mut section = String::from("ungrouped");
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+        if let Some(group) = section.strip_suffix(":vars") {
+            if let Some((key, value)) = line.split_once('=') {
+                group_vars.entry(group.to_string()).or_default().set(key.trim(), value.trim());
+            }
+            continue;
+        }
+        if let Some(group) = section.strip_suffix(":children") {
+            if let Some(child) = line.split_whitespace().next() {
+                parents.entry(child.to_string()).or_default().push(group.to_string());
+            }
+            continue;
+        }
+
+        let mut words = line.split_whitespace();
+        let alias = match words.next() {
+            // Ranges like web[01:10] would need expanding; skip them
+            Some(alias) if !alias.contains('[') => alias,
+            _ => continue,
+        };
+        let mut vars = AnsibleVars::default();
+        for word in words {
+            if let Some((key, value)) = word.split_once('=') {
+                vars.set(key, value.trim_matches(|c| c == '"' || c == '\''));
+            }
+        }
+        entries.push((alias.to_string(), section.clone(), vars));
+    }
+
+    let depth = |group: &str| ini_group_depth(group, &parents, &mut Vec::new());
+    let mut chosen: Vec<(String, String, AnsibleVars)> = Vec::new();
+    for entry in entries {
+        match chosen.iter_mut().find(|(alias, _, _)| *alias == entry.0) {
+            Some(existing) if depth(&entry.1) > depth(&existing.1) => *existing = entry,
+            Some(_) => {}
+            None => chosen.push(entry),
+        }
+    }
+
+    let all = group_vars.get("all").cloned().unwrap_or_default();
+    Ok(chosen
+        .into_iter()
+        .map(|(alias, group, vars)| {
+            let inherited = ini_group_vars(&group, &parents, &group_vars, &mut Vec::new());
+            vars.or(&inherited.or(&all)).into_host(&alias, &group)
+        })
+        .collect())
+}
+
+/// How many `:children` levels `group` sits below a top-level group.
+/// `visiting` guards against groups that list each other.
+fn ini_group_depth(
+    group: &str,
+    parents: &HashMap<String, Vec<String>>,
+    visiting: &mut Vec<String>,
+) -> usize {
+    if visiting.iter().any(|g| g == group) {
+        return 0;
+    }
+    visiting.push(group.to_string());
+    let depth = parents
+        .get(group)
+        .into_iter()
+        .flatten()
+        .map(|parent| 1 + ini_group_depth(parent, parents, visiting))
+        .max()
+        .unwrap_or(0);
+    visiting.pop();
+    depth
+}
+
+/// `group`'s own vars, with anything unset taken from its parent groups.
+fn ini_group_vars(
+    group: &str,
+    parents: &HashMap<String, Vec<String>>,
+    group_vars: &HashMap<String, AnsibleVars>,
+    visiting: &mut Vec<String>,
+) -> AnsibleVars {
+    let mut vars = group_vars.get(group).cloned().unwrap_or_default();
+    if visiting.iter().any(|g| g == group) {
+        return vars;
+    }
+    visiting.push(group.to_string());
+    for parent in parents.get(group).into_iter().flatten() {
+        vars = vars.or(&ini_group_vars(parent, parents, group_vars, visiting));
+    }
+    visiting.pop();
+    vars
+}
+
+/// Hosts from a YAML Ansible inventory. Each host takes the innermost
+/// group it is listed under, and variables are inherited down the tree.
+pub fn import_from_ansible_yaml(path: &Path) -> anyhow::Result<Vec<Host>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let doc: serde_yaml::Value = serde_yaml::from_str(&content)
+        .with_context(|| format!("parsing {}", path.display()))?;
+    let groups = doc
+        .as_mapping()
+        .with_context(|| format!("{}: expected a mapping of groups", path.display()))?;
+
+    // Each host with the depth of the group it was taken from
+    let mut hosts = Vec::new();
+    for (name, group) in groups {
+        if let Some(name) = name.as_str() {
+            collect_yaml_group(name, group, &AnsibleVars::default(), 0, &mut hosts);
+        }
+    }
+    Ok(hosts.into_iter().map(|(host, _)| host).collect())
+}
+
+fn collect_yaml_group(
+    name: &str,
+    group: &serde_yaml::Value,
+    inherited: &AnsibleVars,
+    depth: usize,
+    hosts: &mut Vec<(Host, usize)>,
+) {
+    let vars = yaml_vars(group.get("vars")).or(inherited);
+    if let Some(members) = group.get("hosts").and_then(|h| h.as_mapping()) {
+        for (alias, host_vars) in members {
+            let Some(alias) = alias.as_str() else { continue };
+            let host = yaml_vars(Some(host_vars)).or(&vars).into_host(alias, name);
+            match hosts.iter_mut().find(|(h, _)| h.alias == alias) {
+                // A deeper listing is the more specific group
+                Some(existing) if depth > existing.1 => *existing = (host, depth),
+                Some(_) => {}
+                None => hosts.push((host, depth)),
+            }
+        }
+    }
+    if let Some(children) = group.get("children").and_then(|c| c.as_mapping()) {
+        for (child, child_group) in children {
+            if let Some(child) = child.as_str() {
+                collect_yaml_group(child, child_group, &vars, depth + 1, hosts);
+            }
+        }
+    }
+}
+
+fn yaml_vars(value: Option<&serde_yaml::Value>) -> AnsibleVars {
+    let mut vars = AnsibleVars::default();
+    if let Some(map) = value.and_then(|v| v.as_mapping()) {
+        for (key, value) in map {
+            let value = match value {
+                serde_yaml::Value::String(s) => s.clone(),
+                serde_yaml::Value::Number(n) => n.to_string(),
+                _ => continue,
+            };
+            if let Some(key) = key.as_str() {
+                vars.set(key, &value);
+            }
+        }
+    }
+    vars
+}
+
+/// Instances from a Terraform state file (format 4, Terraform 0.12 and 1.x).
+/// Handles `aws_instance`, `google_compute_instance` and `digitalocean_droplet`;
+/// the `Name` tag or the resource name becomes the alias and `Env` the group.
+pub fn import_from_terraform_state(path: &Path) -> anyhow::Result<Vec<Host>> {
+    use serde_json::Value;
+
+    let content =
+        fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let state: Value =
+        serde_json::from_str(&content).with_context(|| format!("parsing {}", path.display()))?;
+    let resources = state["resources"]
+        .as_array()
+        .with_context(|| format!("{}: no resources list; is this a state file?", path.display()))?;
+
+    let str_at = |v: &Value, ptr: &str| v.pointer(ptr).and_then(Value::as_str).map(str::to_string);
+    let mut hosts = Vec::new();
+    for resource in resources {
+        if resource["mode"].as_str() == Some("data") {
+            continue;
+        }
+        let kind = resource["type"].as_str().unwrap_or_default();
+        let name = resource["name"].as_str().unwrap_or_default();
+        let instances = resource["instances"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        for instance in instances {
+            let attrs = &instance["attributes"];
+            // `count`/`for_each` resources are told apart by their index key
+            let indexed_name = match &instance["index_key"] {
+                Value::Number(n) => format!("{}-{}", name, n),
+                Value::String(s) => format!("{}-{}", name, s),
+                _ => name.to_string(),
+            };
+            let (alias, address, user, group, tags) = match kind {
+                "aws_instance" => (
+                    str_at(attrs, "/tags/Name"),
+                    str_at(attrs, "/public_ip")
+                        .filter(|ip| !ip.is_empty())
+                        .or_else(|| str_at(attrs, "/private_ip")),
+                    None,
+                    str_at(attrs, "/tags/Env").or_else(|| str_at(attrs, "/tags/Environment")),
+                    Vec::new(),
+                ),
+                "google_compute_instance" => (
+                    str_at(attrs, "/name"),
+                    str_at(attrs, "/network_interface/0/access_config/0/nat_ip")
+                        .or_else(|| str_at(attrs, "/network_interface/0/network_ip")),
+                    // metadata.ssh-keys holds `user:ssh-ed25519 ...` lines
+                    str_at(attrs, "/metadata/ssh-keys")
+                        .and_then(|k| k.split(':').next().map(str::to_string)),
+                    str_at(attrs, "/labels/env"),
+                    Vec::new(),
+                ),
+                "digitalocean_droplet" => (
+                    str_at(attrs, "/name"),
+                    str_at(attrs, "/ipv4_address"),
+                    Some("root".to_string()),
+                    None,
+                    attrs["tags"]
+                        .as_array()
+                        .map(|t| t.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                        .unwrap_or_default(),
+                ),
+                _ => continue,
+            };
+            let hostname = match address.filter(|a| !a.is_empty()) {
+                Some(address) => address,
+                None => continue,
+            };
+            hosts.push(Host {
+                alias: alias.filter(|a| !a.is_empty()).unwrap_or(indexed_name),
+                hostname,
+                user: user.unwrap_or_default(),
+                group: group.unwrap_or_else(|| DEFAULT_GROUP.to_string()),
+                tags,
+                ..Default::default()
+            });
+        }
+    }
+    Ok(hosts)
+}
+
+/// Running EC2 instances in `region`, as listed by `aws ec2 describe-instances`.
+/// The `Name` tag is the alias, `Environment` or `Group` the group, and the
+/// key pair maps to `~/.ssh/<key>.pem` when that file exists.
+pub fn import_from_aws(region: &str, profile: Option<&str>) -> anyhow::Result<Vec<Host>> {
+    use serde_json::Value;
+
+    let mut cmd = std::process::Command::new("aws");
+    cmd.args(["ec2", "describe-instances", "--output", "json", "--region", region])
+        .args(["--filters", "Name=instance-state-name,Values=running"]);
+    if let Some(profile) = profile {
+        cmd.args(["--profile", profile]);
+    }
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!("the aws CLI is not on PATH; install it and run `aws configure`")
+        }
+        Err(e) => return Err(e).context("running aws"),
+    };
+    if !output.status.success() {
+        anyhow::bail!("aws failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let response: Value =
+        serde_json::from_slice(&output.stdout).context("parsing aws output")?;
+
+    let mut hosts = Vec::new();
+    let reservations = response["Reservations"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+    for instance in reservations.iter().flat_map(|r| r["Instances"].as_array()).flatten() {
+        if instance["State"]["Name"].as_str() != Some("running") {
+            continue;
+        }
+        let tag = |key: &str| {
+            instance["Tags"]
+                .as_array()?
+                .iter()
+                .find(|t| t["Key"].as_str() == Some(key))?["Value"]
+                .as_str()
+                .map(str::to_string)
+        };
+        let address = ["PublicDnsName", "PublicIpAddress", "PrivateIpAddress"]
+            .iter()
+            .filter_map(|field| instance[*field].as_str())
+            .find(|a| !a.is_empty());
+        let hostname = match address {
+            Some(address) => address.to_string(),
+            None => continue,
+        };
+        let identity_file = instance["KeyName"]
+            .as_str()
+            .map(|key| dirs_home().join(".ssh").join(format!("{}.pem", key)))
+            .filter(|path| path.exists())
+            .map(|path| path.to_string_lossy().into_owned());
+        let id = instance["InstanceId"].as_str().unwrap_or_default();
+        hosts.push(Host {
+            alias: tag("Name").filter(|n| !n.is_empty()).unwrap_or_else(|| id.to_string()),
+            hostname,
+            identity_files: identity_file.into_iter().collect(),
+            group: tag("Environment")
+                .or_else(|| tag("Group"))
+                .unwrap_or_else(|| DEFAULT_GROUP.to_string()),
+            ..Default::default()
+        });
+    }
+    Ok(hosts)
+}
+
+/// Cluster nodes from `kubectl get nodes`. The external IP is preferred
+/// over the internal one, a `node-role.kubernetes.io/<role>` label becomes
+/// the group, and nodes that aren't Ready are tagged `not-ready`.
+pub fn import_from_kubectl(context: Option<&str>, user: &str) -> anyhow::Result<Vec<Host>> {
+    use serde_json::Value;
+
+    let mut cmd = std::process::Command::new("kubectl");
+    cmd.args(["get", "nodes", "-o", "json"]);
+    if let Some(context) = context {
+        cmd.args(["--context", context]);
+    }
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!("kubectl is not on PATH")
+        }
+        Err(e) => return Err(e).context("running kubectl"),
+    };
+    if !output.status.success() {
+        anyhow::bail!("kubectl failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let list: Value = serde_json::from_slice(&output.stdout).context("parsing kubectl output")?;
+
+    let mut hosts = Vec::new();
+    for node in list["items"].as_array().map(Vec::as_slice).unwrap_or(&[]) {
+        let name = match node["metadata"]["name"].as_str() {
+            Some(name) => name,
+            None => continue,
+        };
+        let addresses = node["status"]["addresses"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        let address_of = |kind: &str| {
+            addresses
+                .iter()
+                .find(|a| a["type"].as_str() == Some(kind))
+                .and_then(|a| a["address"].as_str())
+        };
+        let hostname = address_of("ExternalIP")
+            .or_else(|| address_of("InternalIP"))
+            .unwrap_or(name)
+            .to_string();
+        let role = node["metadata"]["labels"].as_object().and_then(|labels| {
+            labels.keys().find_map(|k| k.strip_prefix("node-role.kubernetes.io/"))
+        });
+        let ready = node["status"]["conditions"].as_array().is_some_and(|conditions| {
+            conditions.iter().any(|c| {
+                c["type"].as_str() == Some("Ready") && c["status"].as_str() == Some("True")
+            })
+        });
+        hosts.push(Host {
+            alias: name.to_string(),
+            hostname,
+            user: user.to_string(),
+            group: role.unwrap_or(DEFAULT_GROUP).to_string(),
+            tags: if ready { Vec::new() } else { vec!["not-ready".to_string()] },
+            ..Default::default()
+        });
+    }
+    Ok(hosts)
+}
+
+/// `doctl compute droplet list` with the credentials `profile` names, as
+/// described at `Config::doctl_profile`.
+fn doctl_command(profile: Option<&str>) -> std::process::Command {
+    let mut cmd = std::process::Command::new("doctl");
+    cmd.args(["compute", "droplet", "list", "--output", "json"]);
+    match profile.map(|p| (p, expand_tilde(p))) {
+        Some((_, path)) if path.is_file() => cmd.arg("--config").arg(path),
+        // In the environment rather than argv, where `ps` would show it
+        Some((token, _)) => cmd.env("DIGITALOCEAN_ACCESS_TOKEN", token),
+        None => &mut cmd,
+    };
+    cmd
+}
+
+/// Active droplets from `doctl compute droplet list`, logged in as `user`.
+/// The region slug becomes the group and droplet tags carry over; doctl's
+/// listing has no SSH key IDs, so no identity file is set.
+pub fn import_from_do(user: &str, profile: Option<&str>) -> anyhow::Result<Vec<Host>> {
+    use serde_json::Value;
+
+    let output = match doctl_command(profile).output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!("doctl is not on PATH; install it and run `doctl auth init`")
+        }
+        Err(e) => return Err(e).context("running doctl"),
+    };
+    if !output.status.success() {
+        anyhow::bail!("doctl failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let droplets: Value = serde_json::from_slice(&output.stdout).context("parsing doctl output")?;
+
+    let mut hosts = Vec::new();
+    for droplet in droplets.as_array().map(Vec::as_slice).unwrap_or(&[]) {
+        if droplet["status"].as_str() != Some("active") {
+            continue;
+        }
+        let name = match droplet["name"].as_str() {
+            Some(name) if !name.is_empty() => name,
+            _ => continue,
+        };
+        let networks = droplet["networks"]["v4"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        let public_ip = networks
+            .iter()
+            .find(|n| n["type"].as_str() == Some("public"))
+            .and_then(|n| n["ip_address"].as_str());
+        let hostname = match public_ip {
+            Some(ip) => ip.to_string(),
+            None => continue,
+        };
+        hosts.push(Host {
+            alias: name.to_string(),
+            hostname,
+            user: user.to_string(),
+            group: droplet["region"]["slug"].as_str().unwrap_or(DEFAULT_GROUP).to_string(),
+            tags: droplet["tags"]
+                .as_array()
+                .map(|t| t.iter().filter_map(Value::as_str).map(str::to_string).collect())
+                .unwrap_or_default(),
+            ..Default::default()
+        });
+    }
+    Ok(hosts)
+}
+
+/// Compute Engine VMs from `gcloud compute instances list`. The external
+/// IP is the hostname, falling back to a hostname set on the instance (which
+/// the metadata server resolves); instances with neither are skipped. The
+/// `env` label becomes the group and the other labels `key=value` tags.
+pub fn import_from_gcloud(project: Option<&str>, zone: Option<&str>) -> anyhow::Result<Vec<Host>> {
+    use serde_json::Value;
+
+    let mut cmd = std::process::Command::new("gcloud");
+    cmd.args(["compute", "instances", "list", "--format", "json"]);
+    if let Some(project) = project {
+        cmd.args(["--project", project]);
+    }
+    if let Some(zone) = zone {
+        cmd.args(["--zones", zone]);
+    }
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            anyhow::bail!("gcloud is not on PATH; install the Cloud SDK and run `gcloud init`")
+        }
+        Err(e) => return Err(e).context("running gcloud"),
+    };
+    if !output.status.success() {
+        anyhow::bail!("gcloud failed: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    let instances: Value =
+        serde_json::from_slice(&output.stdout).context("parsing gcloud output")?;
+
+    let mut hosts = Vec::new();
+    for instance in instances.as_array().map(Vec::as_slice).unwrap_or(&[]) {
+        let name = match instance["name"].as_str() {
+            Some(name) => name,
+            None => continue,
+        };
+        let interfaces = instance["networkInterfaces"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+        let external_ip = interfaces
+            .iter()
+            .flat_map(|i| i["accessConfigs"].as_array().map(Vec::as_slice).unwrap_or(&[]))
+            .find_map(|c| c["natIP"].as_str());
+        let hostname = match external_ip.or_else(|| instance["hostname"].as_str()) {
+            Some(hostname) => hostname.to_string(),
+            None => {
+                tracing::warn!("skipping {}: no external IP", name);
+                continue;
+            }
+        };
+        let labels = instance["labels"].as_object();
+        let group = labels.and_then(|l| l.get("env")).and_then(Value::as_str);
+        let tags = labels
+            .into_iter()
+            .flatten()
+            .filter(|(key, _)| *key != "env")
+            .map(|(key, value)| format!("{}={}", key, value.as_str().unwrap_or_default()))
+            .collect();
+        hosts.push(Host {
+            alias: name.to_string(),
+            hostname,
+            group: group.unwrap_or(DEFAULT_GROUP).to_string(),
+            tags,
+            ..Default::default()
+        });
+    }
+    Ok(hosts)
+}
+
+/// Something wrong with a host entry, found by `validate_host`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    EmptyAlias,
+    /// ssh would read the alias as a pattern or several names.
+    InvalidAliasChar(char),
+    EmptyHostname,
+    InvalidPort,
+    /// The identity file (as written) does not exist.
+    MissingIdentityFile(String),
+    EmptyGroup,
+    /// An extra ssh option that is neither a flag nor `Keyword=value`.
+    InvalidSshOption(String),
+    /// Longer than `MAX_DESCRIPTION_LEN` characters.
+    DescriptionTooLong,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationError::EmptyAlias => write!(f, "Alias must not be empty"),
+            ValidationError::InvalidAliasChar(c) => {
+                write!(f, "Alias must not contain {:?}", c)
+            }
+            ValidationError::EmptyHostname => write!(f, "Hostname must not be empty"),
+            ValidationError::InvalidPort => write!(f, "Port must be between 1 and 65535"),
+            ValidationError::MissingIdentityFile(path) => {
+                write!(f, "Identity file {} does not exist", path)
+            }
+            ValidationError::EmptyGroup => write!(f, "Group must not be empty"),
+            ValidationError::InvalidSshOption(opt) => {
+                write!(f, "SSH option {} must be a -flag or Keyword=value", opt)
+            }
+            ValidationError::DescriptionTooLong => {
+                write!(f, "Description must be at most {} characters", MAX_DESCRIPTION_LEN)
+            }
+        }
+    }
+}
+
+/// Characters allowed in an alias besides letters and digits; anything else
+/// (spaces, `*`, `?`, `!`, `,`) means something else to ssh.
+const ALIAS_PUNCTUATION: &str = "-_.@:+";
+
+/// Longest description, in characters, that fits the detail pane.
+const MAX_DESCRIPTION_LEN: usize = 200;
+
+/// Everything wrong with `host`, in field order; empty when it is fine.
+pub fn validate_host(host: &Host) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    if host.alias.is_empty() {
+        errors.push(ValidationError::EmptyAlias);
+    } else if let Some(c) = host
+        .alias
+        .chars()
+        .find(|c| !c.is_alphanumeric() && !ALIAS_PUNCTUATION.contains(*c))
+    {
+        errors.push(ValidationError::InvalidAliasChar(c));
+    }
+    if host.hostname.trim().is_empty() {
+        errors.push(ValidationError::EmptyHostname);
+    }
+    if host.port == 0 {
+        errors.push(ValidationError::InvalidPort);
+    }
+    for key in &host.identity_files {
+        if !expand_tilde(key).exists() {
+            errors.push(ValidationError::MissingIdentityFile(key.clone()));
+        }
+    }
+    if host.group.trim().is_empty() {
+        errors.push(ValidationError::EmptyGroup);
+    }
+    if let Some(opt) = host.extra_ssh_options.iter().find(|o| !is_ssh_option(o)) {
+        errors.push(ValidationError::InvalidSshOption(opt.clone()));
+    }
+    if host.description.as_ref().is_some_and(|d| d.chars().count() > MAX_DESCRIPTION_LEN) {
+        errors.push(ValidationError::DescriptionTooLong);
+    }
+    errors
+}
+
+/// A flag (`-v`, `-o`) or an ssh_config `Keyword=value` as passed to `-o`.
+fn is_ssh_option(opt: &str) -> bool {
+    if opt.starts_with('-') {
+        return opt.len() > 1;
+    }
+    match opt.split_once('=') {
+        Some((keyword, value)) => {
+            !keyword.is_empty()
+                && keyword.chars().all(|c| c.is_ascii_alphanumeric())
+                && !value.is_empty()
+        }
+        None => false,
+    }
+}
+
+/// Split extra ssh options separated by commas or spaces into arguments,
+/// so `-o BatchMode=yes, -v` gives `-o`, `BatchMode=yes`, `-v`.
+pub fn parse_ssh_options(list: &str) -> Vec<String> {
+    list.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|o| !o.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// `options` as the edit form shows them: each flag with its arguments,
+/// comma-separated.
+pub fn format_ssh_options(options: &[String]) -> String {
+    let mut groups: Vec<String> = Vec::new();
+    for opt in options {
+        match groups.last_mut() {
+            Some(group) if !opt.starts_with('-') => {
+                group.push(' ');
+                group.push_str(opt);
+            }
+            _ => groups.push(opt.clone()),
+        }
+    }
+    groups.join(", ")
+}
+
+/// Split a comma-separated tag list, dropping empty entries. `\,` is a
+/// comma within a tag.
+pub fn parse_tags(list: &str) -> Vec<String> {
+    let mut tags = Vec::new();
+    let mut tag = String::new();
+    let mut chars = list.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            ',' => tags.push(std::mem::take(&mut tag)),
+            '\\' => {
+                tag.push(c);
+                tag.extend(chars.next());
+            }
+            c => tag.push(c),
+        }
+    }
+    tags.push(tag);
+    tags.iter().map(|t| unescape_comment(t.trim())).filter(|t| !t.is_empty()).collect()
+}
+
+/// The tags as `parse_tags` reads them, with commas inside a tag escaped.
+pub fn join_tags(tags: &[String]) -> String {
+    let escaped: Vec<String> = tags.iter().map(|t| escape_comment(t, ",")).collect();
+    escaped.join(", ")
+}
+
+/// `s` on one line for a `# key: value` comment: backslashes and the
+/// characters in `special` get a backslash, line breaks become `\n` and `\r`.
+fn escape_comment(s: &str, special: &str) -> String {
+    let mut out = String::new();
+    for c in s.chars() {
+        match c {
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\\' => out.push_str("\\\\"),
+            c if special.contains(c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Undo `escape_comment`. Any other backslash is kept, so comments written
+/// by hand before escaping existed read the same.
+fn unescape_comment(s: &str) -> String {
+    let mut out = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some(c @ ('\\' | ',')) => out.push(c),
+            Some(c) => {
+                out.push('\\');
+                out.push(c);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+/// Parse a comma-separated `KEY=value` list, as typed in the edit form.
+pub fn parse_environment(list: &str) -> Result<HashMap<String, String>, String> {
+    let mut vars = HashMap::new();
+    for entry in list.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        match entry.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+            Some((key, value)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+                vars.insert(key.to_string(), value.to_string());
+            }
+            _ => return Err(format!("'{}' is not KEY=value", entry)),
+        }
+    }
+    Ok(vars)
+}
+
+/// `environment` as `KEY=value` pairs sorted by key, for display.
+pub fn format_environment(environment: &HashMap<String, String>) -> String {
+    let mut pairs: Vec<String> = environment.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+    pairs.sort();
+    pairs.join(", ")
+}
+
+/// Expand an `Include` pattern and parse every matching file. Relative
+/// patterns are resolved against `~/.ssh/`, as OpenSSH does for user configs.
+fn parse_included_files(
+    pattern: &str,
+    including: &mut Vec<PathBuf>,
+    match_blocks: &mut Vec<MatchBlock>,
+) -> Vec<Host> {
+    let expanded = expand_tilde(pattern);
+    let full = if expanded.is_absolute() {
+        expanded
+    } else {
+        dirs_home().join(".ssh").join(expanded)
+    };
+
+    let paths = match glob::glob(&full.to_string_lossy()) {
+        Ok(paths) => paths,
+        Err(_) => return Vec::new(),
+    };
+
+    // glob yields paths in alphabetical order, matching OpenSSH
+    let mut hosts = Vec::new();
+    for path in paths.flatten() {
+        if !path.is_file() {
+            continue;
+        }
+        hosts.extend(parse_ssh_config_file(&path, including, match_blocks));
+    }
+    hosts
+}
+
+pub fn expand_tilde(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) => dirs_home().join(rest.trim_start_matches('/')),
+        None => PathBuf::from(path),
+    }
+}
+
+/// `path` for display, with the home directory shortened to `~`.
+pub fn tilde_path(path: &Path) -> String {
+    match path.strip_prefix(dirs_home()) {
+        Ok(rest) => format!("~/{}", rest.display()),
+        Err(_) => path.display().to_string(),
+    }
+}
+
+pub fn sshmap_config_dir() -> PathBuf {
+    dirs_home().join(".config").join("sshmap")
+}
+
+/// Where to record a session with `host` started now:
+/// `recordings/<alias>-<timestamp>.log` in the config directory. The
+/// timestamp is ISO 8601 with the colons swapped for hyphens.
+pub fn recording_path(host: &Host) -> PathBuf {
+    let timestamp = Utc::now()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+        .replace(':', "-");
+    sshmap_config_dir()
+        .join("recordings")
+        .join(format!("{}-{}.log", host.alias, timestamp))
+}
+
+/// `cmd` wrapped in `script` so the whole session is logged to `path`.
+pub fn script_command(cmd: &[String], path: &Path) -> Vec<String> {
+    let path = path.to_string_lossy().into_owned();
+    // BSD script takes the command as trailing arguments, util-linux as -c
+    if cfg!(target_os = "macos") {
+        let mut args = vec!["script".to_string(), "-q".to_string(), path];
+        args.extend(cmd.iter().cloned());
+        args
+    } else {
+        let line: Vec<String> = cmd.iter().map(|arg| shell_quote(arg)).collect();
+        vec!["script".into(), "-q".into(), "-c".into(), line.join(" "), path]
+    }
+}
+
+/// `arg` single-quoted for `sh` unless it is made only of safe characters.
+pub fn shell_quote(arg: &str) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:@,+%".contains(c);
+    if !arg.is_empty() && arg.chars().all(safe) {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
+
+/// Set by `--hosts`; replaces both default host files.
+static HOSTS_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+/// Read and write hosts at `path` instead of the config directory. A
+/// `.toml` extension selects the TOML format, anything else JSON.
+pub fn set_hosts_file(path: PathBuf) {
+    let _ = HOSTS_FILE.set(path);
+}
+
+fn sshmap_config_path() -> PathBuf {
+    match HOSTS_FILE.get() {
+        Some(path) if !is_toml(path) => path.clone(),
+        _ => sshmap_config_dir().join("hosts.json"),
+    }
+}
+
+fn sshmap_config_toml_path() -> PathBuf {
+    match HOSTS_FILE.get() {
+        Some(path) if is_toml(path) => path.clone(),
+        _ => sshmap_config_dir().join("hosts.toml"),
+    }
+}
+
+fn is_toml(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "toml")
+}
+
+/// Whether hosts are kept in TOML: an explicit `--hosts` file decides by
+/// its extension, otherwise an existing hosts.toml wins.
+fn uses_toml() -> bool {
+    match HOSTS_FILE.get() {
+        Some(path) => is_toml(path),
+        None => sshmap_config_toml_path().exists(),
+    }
+}
+
+/// Both files hosts may be loaded from, `--hosts` included.
+pub fn hosts_paths() -> [PathBuf; 2] {
+    [sshmap_config_path(), sshmap_config_toml_path()]
+}
+
+/// The host file sshmap reads and writes: hosts.toml if present, else hosts.json.
+pub fn active_hosts_path() -> PathBuf {
+    if uses_toml() {
+        sshmap_config_toml_path()
+    } else {
+        sshmap_config_path()
+    }
+}
+
+/// `hosts.toml` is an array of tables: one `[[hosts]]` per host.
+#[derive(Serialize, Deserialize)]
+struct HostsFile {
+    #[serde(default)]
+    hosts: Vec<Host>,
+}
+
+fn load_sshmap_config() -> Option<Vec<Host>> {
+    // hosts.toml is the hand-editable format and wins over hosts.json
+    if uses_toml() {
+        if HOSTS_FILE.get().is_none() && sshmap_config_path().exists() {
+            tracing::warn!("both hosts.toml and hosts.json exist; using hosts.toml");
+        }
+        return load_sshmap_config_toml();
+    }
+    let path = sshmap_config_path();
+    let content = fs::read_to_string(&path).ok()?;
+    let hosts: Vec<Host> = serde_json::from_str(&content).ok()?;
+    // Hosts saved before they could have several keys use `identity_file`
+    if content.contains("\"identity_file\"") {
+        match save_sshmap_config_json(&hosts) {
+            Ok(()) => tracing::info!("migrated {} to identity_files", path.display()),
+            Err(e) => tracing::warn!("could not migrate {}: {}", path.display(), e),
+        }
+    }
+    Some(hosts)
+}
+
+/// Parse sshmap's host file, if there is one, reporting what's wrong with it.
+/// `load_sshmap_config` treats a broken file as empty, which is fine at
+/// startup but would wipe the list on a live reload mid-edit.
+pub fn check_sshmap_config() -> anyhow::Result<()> {
+    let toml_path = sshmap_config_toml_path();
+    if uses_toml() && toml_path.exists() {
+        let content = fs::read_to_string(&toml_path)?;
+        toml::from_str::<HostsFile>(&content)
+            .with_context(|| format!("parsing {}", toml_path.display()))?;
+        return Ok(());
+    }
+    let path = sshmap_config_path();
+    if !uses_toml() && path.exists() {
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str::<Vec<Host>>(&content)
+            .with_context(|| format!("parsing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+fn load_sshmap_config_toml() -> Option<Vec<Host>> {
+    read_hosts_toml(&sshmap_config_toml_path())
+}
+
+fn read_hosts_toml(path: &Path) -> Option<Vec<Host>> {
+    let content = fs::read_to_string(path).ok()?;
+    let file: HostsFile = toml::from_str(&content).ok()?;
+    Some(file.hosts)
+}
+
+/// Save to whichever format the user keeps their hosts in.
+pub fn save_sshmap_config(hosts: &[Host]) -> anyhow::Result<()> {
+    if uses_toml() {
+        save_sshmap_config_toml(hosts)
+    } else {
+        save_sshmap_config_json(hosts)
+    }
+}
+
+fn save_sshmap_config_json(hosts: &[Host]) -> anyhow::Result<()> {
+    let path = sshmap_config_path();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let json = serde_json::to_string_pretty(hosts)?;
+    fs::write(&path, &json)?;
+    watch::note_own_write(&path, &json);
+    Ok(())
+}
+
+pub fn save_sshmap_config_toml(hosts: &[Host]) -> anyhow::Result<()> {
+    write_hosts_toml(&sshmap_config_toml_path(), hosts)
+}
+
+fn write_hosts_toml(path: &Path, hosts: &[Host]) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = HostsFile {
+        hosts: hosts.to_vec(),
+    };
+    let toml = toml::to_string_pretty(&file)?;
+    fs::write(path, &toml)?;
+    watch::note_own_write(path, &toml);
+    Ok(())
+}
+
+pub fn create_sample_config() -> anyhow::Result<()> {
+    if active_hosts_path().exists() {
+        return Ok(());
+    }
+
+    let sample = vec![
+        Host {
+            alias: "web-prod-1".into(),
+            hostname: "192.168.1.10".into(),
+            user: "deploy".into(),
+            port: 22,
+            group: "production".into(),
+            ..Default::default()
+        },
+        Host {
             alias: "web-staging".into(),
             hostname: "192.168.1.20".into(),
             user: "deploy".into(),
             port: 22,
-            identity_file: None,
             group: "staging".into(),
-            status: HostStatus::Unknown,
+            ..Default::default()
         },
         Host {
             alias: "db-prod".into(),
             hostname: "192.168.1.30".into(),
             user: "admin".into(),
             port: 2222,
-            identity_file: None,
             group: "production".into(),
-            status: HostStatus::Unknown,
+            ..Default::default()
         },
         Host {
             alias: "dev-box".into(),
             hostname: "10.0.0.5".into(),
             user: "matt".into(),
             port: 22,
-            identity_file: None,
             group: "dev".into(),
-            status: HostStatus::Unknown,
+            ..Default::default()
         },
     ];
 
@@ -253,3 +2269,439 @@ fn dirs_home() -> PathBuf {
         .map(PathBuf::from)
         .unwrap_or_else(|_| PathBuf::from("/tmp"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_file(path: &Path) -> Vec<Host> {
+        parse_ssh_config_file(path, &mut Vec::new(), &mut Vec::new())
+    }
+
+    fn parse(content: &str) -> Vec<Host> {
+        parse_ssh_config_lines(Path::new("config"), content, &mut Vec::new(), &mut Vec::new())
+    }
+
+    fn aliases(hosts: &[Host]) -> Vec<&str> {
+        hosts.iter().map(|h| h.alias.as_str()).collect()
+    }
+
+    #[test]
+    fn included_hosts_follow_the_enclosing_host() {
+        let dir = tempfile::tempdir().unwrap();
+        let included = dir.path().join("included");
+        fs::write(&included, "Host b\n  HostName b.example\n").unwrap();
+        let config = dir.path().join("config");
+        let content = format!(
+            "Host a\n  HostName a.example\n  Include {}\n  User bob\nHost c\n",
+            included.display()
+        );
+        fs::write(&config, content).unwrap();
+
+        let hosts = parse_file(&config);
+        assert_eq!(aliases(&hosts), ["a", "b", "c"]);
+        assert_eq!(hosts[0].user, "bob");
+    }
+
+    #[test]
+    fn include_cycles_are_detected_by_canonical_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config");
+        // The same file, spelled differently
+        let content = format!("Include {}/./config\nHost a\n", dir.path().display());
+        fs::write(&config, content).unwrap();
+
+        assert_eq!(aliases(&parse_file(&config)), ["a"]);
+    }
+
+    #[test]
+    fn health_comments_with_a_port_set_the_check_port() {
+        let hosts = parse("# health: tcp:8080\nHost web\n# health: ssh\nHost db\n");
+        assert_eq!(hosts[0].health_check_method, Some(CheckMethod::TcpPort(8080)));
+        assert_eq!(hosts[0].health_check_port, Some(8080));
+        assert_eq!(hosts[1].health_check_method, Some(CheckMethod::SshBanner));
+        assert_eq!(hosts[1].health_check_port, None);
+    }
+
+    #[test]
+    fn descriptions_over_the_limit_fail_validation() {
+        let described = |len| Host {
+            description: Some("é".repeat(len)),
+            ..crate::test_support::host("web", "prod")
+        };
+        assert!(validate_host(&described(MAX_DESCRIPTION_LEN)).is_empty());
+        assert_eq!(
+            validate_host(&described(MAX_DESCRIPTION_LEN + 1)),
+            [ValidationError::DescriptionTooLong]
+        );
+    }
+
+    #[test]
+    fn merged_hosts_save_only_their_own_changes() {
+        let ssh = Host {
+            alias: "web".into(),
+            hostname: "web.example".into(),
+            user: "bob".into(),
+            port: 2222,
+            ..Host::default()
+        };
+        let own = Host { alias: "web".into(), group: "prod".into(), ..Host::default() };
+        let mut merged = merge_host(ssh, own);
+        assert_eq!(merged.hostname, "web.example");
+
+        merged.description = Some("front end".into());
+        merged.connection_count = 3;
+        let entry = sshmap_entry(&merged);
+        assert_eq!(entry.hostname, "");
+        assert_eq!(entry.user, "");
+        assert_eq!(entry.port, Host::default().port);
+        assert_eq!(entry.group, "prod");
+        assert_eq!(entry.description.as_deref(), Some("front end"));
+        assert_eq!(entry.connection_count, 3);
+    }
+
+    #[test]
+    fn hosts_survive_a_toml_round_trip() {
+        let mut web = Host {
+            alias: "web".into(),
+            hostname: "web.example".into(),
+            user: "deploy".into(),
+            port: 2222,
+            identity_files: vec!["~/.ssh/web".into()],
+            proxy_jump: Some("bastion".into()),
+            local_forwards: vec![PortForward {
+                local_port: 8080,
+                remote_host: "localhost".into(),
+                remote_port: 80,
+            }],
+            group: "prod".into(),
+            description: Some("front \"end\"\nsecond line".into()),
+            tags: vec!["a b".into(), "c=d".into()],
+            last_connected: Some(Utc::now()),
+            connection_count: 7,
+            health_check_method: Some(CheckMethod::TcpPort(443)),
+            health_check_port: Some(443),
+            ..Host::default()
+        };
+        web.environment.insert("TERM".into(), "xterm".into());
+        let hosts = vec![web, Host { alias: "bare".into(), ..Host::default() }];
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hosts.toml");
+        write_hosts_toml(&path, &hosts).unwrap();
+        let loaded = read_hosts_toml(&path).unwrap();
+
+        let as_json = |hosts: &[Host]| serde_json::to_value(hosts).unwrap();
+        assert_eq!(as_json(&loaded), as_json(&hosts));
+    }
+
+    #[test]
+    fn csv_export_reads_back_with_a_csv_reader() {
+        let hosts = vec![
+            Host {
+                alias: "web".into(),
+                hostname: "web.example".into(),
+                identity_files: vec!["~/.ssh/a".into(), "~/.ssh/b".into()],
+                description: Some("front, \"main\"\r\nsecond line".into()),
+                tags: vec!["a,b".into(), "say \"hi\"".into(), "x\ny".into()],
+                ..Host::default()
+            },
+            Host { alias: "plain".into(), description: None, ..Host::default() },
+        ];
+
+        let exported = export_csv(&hosts);
+        let mut reader = csv::Reader::from_reader(exported.as_bytes());
+        let header = reader.headers().unwrap().clone();
+        assert_eq!(&header[0], "alias");
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 2);
+
+        let column = |name: &str| header.iter().position(|h| h == name).unwrap();
+        assert_eq!(&rows[0][column("alias")], "web");
+        assert_eq!(&rows[0][column("identity_file")], "~/.ssh/a,~/.ssh/b");
+        assert_eq!(&rows[0][column("description")], "front, \"main\"\r\nsecond line");
+        assert_eq!(&rows[0][column("tags")], "a,b,say \"hi\",x\ny");
+        assert_eq!(&rows[1][column("description")], "");
+        assert!(rows.iter().all(|row| row.len() == header.len()));
+    }
+
+    fn import(
+        name: &str,
+        content: &str,
+        import: fn(&Path) -> anyhow::Result<Vec<Host>>,
+    ) -> Vec<Host> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(name);
+        fs::write(&path, content).unwrap();
+        import(&path).unwrap()
+    }
+
+    #[test]
+    fn ansible_ini_hosts_take_the_deepest_group_and_inherit_vars() {
+        let inventory = "\
+[servers]
+web1
+db1 ansible_port=2200
+
+[web]
+web1 ansible_host=10.0.0.1
+
+[servers:children]
+web
+db
+
+[db]
+db1
+
+[servers:vars]
+ansible_user=deploy
+";
+        let hosts = import("hosts.ini", inventory, import_from_ansible_ini);
+        assert_eq!(aliases(&hosts), ["web1", "db1"]);
+        assert_eq!(hosts[0].group, "web");
+        assert_eq!(hosts[0].hostname, "10.0.0.1");
+        assert_eq!(hosts[0].user, "deploy");
+        assert_eq!(hosts[1].group, "db");
+        assert_eq!(hosts[1].user, "deploy");
+        // The db listing carries no port of its own
+        assert_eq!(hosts[1].port, 22);
+    }
+
+    #[test]
+    fn ansible_yaml_hosts_take_the_deepest_group() {
+        let inventory = "\
+all:
+  vars:
+    ansible_user: deploy
+  hosts:
+    web1:
+  children:
+    prod:
+      children:
+        web:
+          hosts:
+            web1:
+              ansible_host: 10.0.0.1
+";
+        let hosts = import("hosts.yml", inventory, import_from_ansible_yaml);
+        assert_eq!(aliases(&hosts), ["web1"]);
+        assert_eq!(hosts[0].group, "web");
+        assert_eq!(hosts[0].hostname, "10.0.0.1");
+        assert_eq!(hosts[0].user, "deploy");
+    }
+
+    #[test]
+    fn ssh_tokens_expand() {
+        let home = crate::test_support::isolated_home().to_string_lossy().into_owned();
+        let local_user = std::env::var("USER").unwrap_or_default();
+        let cases = [
+            ("~/.ssh/%h", "~/.ssh/web.example"),
+            ("%n.key", "web.key"),
+            ("port-%p", "port-2222"),
+            ("%r@%h", "deploy@web.example"),
+            ("%u", local_user.as_str()),
+            ("%d/.ssh/id", &format!("{}/.ssh/id", home)),
+            ("100%%", "100%"),
+            ("%%h", "%h"),
+            ("%x stays", "%x stays"),
+            ("trailing %", "trailing %"),
+            ("plain", "plain"),
+        ];
+        for (input, expected) in cases {
+            let expanded = expand_ssh_tokens(input, "web", "web.example", "deploy", 2222);
+            assert_eq!(expanded, expected, "expanding {:?}", input);
+        }
+        // With no user configured, ssh logs in as the local one
+        assert_eq!(expand_ssh_tokens("%r", "web", "web.example", "", 22), local_user);
+    }
+
+    #[test]
+    fn directives_split_into_keyword_and_value() {
+        let split = split_directive;
+        let pair = |k: &str, v: &str| Some((k.to_string(), v.to_string()));
+        assert_eq!(split("IdentityFile ~/k#1/id"), pair("identityfile", "~/k#1/id"));
+        assert_eq!(split("IdentityFile ~/k #1/id"), pair("identityfile", "~/k #1/id"));
+        assert_eq!(split("Port 2222 # note"), pair("port", "2222"));
+        assert_eq!(split("Port 2222#note"), pair("port", "2222#note"));
+        assert_eq!(split("Port=2222"), pair("port", "2222"));
+        assert_eq!(split("Port = 2222"), pair("port", "2222"));
+        assert_eq!(split("HostName\tweb.example"), pair("hostname", "web.example"));
+        assert_eq!(split("User\t=\tbob\t# tabbed"), pair("user", "bob"));
+        assert_eq!(split("IdentityFile \"/keys/my key\""), pair("identityfile", "/keys/my key"));
+        assert_eq!(split("ProxyJump \"bastion\" # quoted"), pair("proxyjump", "bastion"));
+        assert_eq!(split("Port # nothing but a comment"), None);
+        assert_eq!(split("Port"), None);
+    }
+
+    #[test]
+    fn directive_forms_all_parse() {
+        let config = "\
+Host web
+    HostName web.example # the front end
+\tUser=deploy
+  Port = 2222
+\t  IdentityFile \"/keys/web key#1\"
+Host\tdb
+ProxyJump\tweb
+";
+        let hosts = parse(config);
+        assert_eq!(aliases(&hosts), ["web", "db"]);
+        assert_eq!(hosts[0].hostname, "web.example");
+        assert_eq!(hosts[0].user, "deploy");
+        assert_eq!(hosts[0].port, 2222);
+        assert_eq!(hosts[0].identity_files, ["/keys/web key#1"]);
+        assert_eq!(hosts[1].proxy_jump.as_deref(), Some("web"));
+    }
+
+    #[test]
+    fn doctl_gets_a_config_path_as_an_argument_and_a_token_from_the_environment() {
+        let home = crate::test_support::isolated_home();
+        let config = home.join(".config/doctl/config.yaml");
+        fs::create_dir_all(config.parent().unwrap()).unwrap();
+        fs::write(&config, "").unwrap();
+        let args = |cmd: &std::process::Command| -> Vec<String> {
+            cmd.get_args().map(|a| a.to_string_lossy().into_owned()).collect()
+        };
+
+        let with_file = doctl_command(Some("~/.config/doctl/config.yaml"));
+        assert_eq!(args(&with_file)[5..], ["--config".to_string(), config.display().to_string()]);
+        assert_eq!(with_file.get_envs().count(), 0);
+
+        let with_token = doctl_command(Some("dop_v1_secret"));
+        assert!(!args(&with_token).iter().any(|a| a.contains("secret")));
+        let envs: Vec<_> = with_token.get_envs().collect();
+        assert_eq!(
+            envs,
+            [("DIGITALOCEAN_ACCESS_TOKEN".as_ref(), Some("dop_v1_secret".as_ref()))]
+        );
+    }
+
+    #[test]
+    fn mosh_quotes_the_ssh_options_it_passes_on() {
+        let web = Host {
+            port: 2222,
+            identity_files: vec!["/keys/web key#1".into()],
+            extra_ssh_options: vec!["-o".into(), "ProxyCommand=ssh -W %h:%p bastion".into()],
+            ..crate::test_support::host("web", "prod")
+        };
+        let cmd = web.mosh_command(web.ssh_command(false));
+        assert_eq!(cmd[..2], ["mosh", "--ssh"]);
+        assert_eq!(
+            cmd[2],
+            "ssh -p 2222 -i '/keys/web key#1' -o 'ProxyCommand=ssh -W %h:%p bastion'"
+        );
+        assert_eq!(cmd[3], "web.example");
+    }
+
+    #[test]
+    fn exported_descriptions_and_tags_stay_in_their_comments() {
+        let hosts = vec![Host {
+            description: Some("web\nHost evil\n    ProxyCommand sh -c id \\n".into()),
+            tags: vec!["a,b".into(), "c\\".into(), "d".into()],
+            ..crate::test_support::host("web", "prod")
+        }];
+        let exported = export_ssh_config(&hosts);
+
+        assert!(!exported.lines().any(|line| line.trim_start().starts_with("ProxyCommand")));
+        let parsed = parse(&exported);
+        assert_eq!(aliases(&parsed), ["web"]);
+        assert_eq!(parsed[0].description, hosts[0].description);
+        assert_eq!(parsed[0].tags, hosts[0].tags);
+    }
+
+    mod export_round_trip {
+        use super::*;
+        use proptest::collection::{btree_map, vec};
+        use proptest::option;
+        use proptest::prelude::*;
+
+        fn health_check() -> impl Strategy<Value = (CheckMethod, Option<u16>)> {
+            prop_oneof![
+                Just((CheckMethod::Icmp, None)),
+                Just((CheckMethod::Dns, None)),
+                (1..=u16::MAX).prop_map(|p| (CheckMethod::TcpPort(p), Some(p))),
+                option::of(1..=u16::MAX).prop_map(|p| (CheckMethod::SshBanner, p)),
+                ("https?://[a-z]{1,8}\\.example/[a-z]{0,6}", 100..600u16).prop_map(
+                    |(url, expected_status)| (CheckMethod::Http { url, expected_status }, None)
+                ),
+                "[a-z]{1,8}".prop_map(|name| {
+                    let url = format!("https://{}.example/", name);
+                    (CheckMethod::HttpsTls { url }, None)
+                }),
+            ]
+        }
+
+        fn forward() -> impl Strategy<Value = PortForward> {
+            (1..=u16::MAX, "[a-z][a-z0-9.-]{0,10}", 1..=u16::MAX).prop_map(
+                |(local_port, remote_host, remote_port)| PortForward {
+                    local_port,
+                    remote_host,
+                    remote_port,
+                },
+            )
+        }
+
+        /// Everything but the alias, which the caller keeps unique.
+        fn host_fields() -> impl Strategy<Value = Host> {
+            (
+                (
+                    "[a-z][a-z0-9-]{0,10}(\\.[a-z]{2,5}){0,2}",
+                    "([a-z_][a-z0-9_]{0,8})?",
+                    1..=u16::MAX,
+                    vec("/keys/[a-z0-9_#.-]{1,12}", 0..3),
+                    option::of("jump-[a-z0-9]{1,6}"),
+                    vec(forward(), 0..3),
+                ),
+                (
+                    "[a-z][a-z0-9_-]{0,8}",
+                    option::of("[A-Za-z0-9]([A-Za-z0-9 ,.#'\"\\\\\r\n-]{0,30}[A-Za-z0-9])?"),
+                    vec("[a-z0-9_,\\\\\n-]{1,8}", 0..4),
+                    option::of(health_check()),
+                    vec("-[A-Za-z]|[A-Za-z]{1,12}=[0-9]{1,4}", 0..4),
+                ),
+            )
+                .prop_map(|(connection, annotations)| {
+                    let (hostname, user, port, identity_files, proxy_jump, local_forwards) =
+                        connection;
+                    let (group, description, tags, health, extra_ssh_options) = annotations;
+                    let (health_check_method, health_check_port) = match health {
+                        Some((method, port)) => (Some(method), port),
+                        None => (None, None),
+                    };
+                    Host {
+                        hostname,
+                        user,
+                        port,
+                        identity_files,
+                        proxy_jump,
+                        local_forwards,
+                        group,
+                        description,
+                        tags,
+                        health_check_method,
+                        health_check_port,
+                        extra_ssh_options,
+                        ..Host::default()
+                    }
+                })
+        }
+
+        fn hosts() -> impl Strategy<Value = Vec<Host>> {
+            btree_map("[a-z][a-z0-9-]{0,10}", host_fields(), 0..6).prop_map(|hosts| {
+                hosts.into_iter().map(|(alias, host)| Host { alias, ..host }).collect()
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn exported_hosts_parse_back_the_same(mut hosts in hosts()) {
+                let parsed = parse(&export_ssh_config(&hosts));
+
+                sort_hosts(&mut hosts);
+                // Status and source aren't serialized, so this compares the rest
+                let as_json = |hosts: &[Host]| serde_json::to_value(hosts).unwrap();
+                prop_assert_eq!(as_json(&parsed), as_json(&hosts));
+            }
+        }
+    }
+}
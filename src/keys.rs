@@ -0,0 +1,426 @@
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    SelectUp,
+    SelectDown,
+    PageUp,
+    PageDown,
+    SelectFirst,
+    SelectLast,
+    Connect,
+    ConnectWithForwards,
+    ConnectRecorded,
+    FilterEnter,
+    JumpToAlias,
+    ClearFilter,
+    ToggleFuzzy,
+    ToggleRegex,
+    PingOne,
+    PingAll,
+    ToggleGroups,
+    ToggleCollapse,
+    CycleSort,
+    EditHost,
+    AddHost,
+    DeleteHost,
+    ToggleSelect,
+    BatchCommand,
+    CopyCommand,
+    Sftp,
+    Mosh,
+    OpenTunnel,
+    ListTunnels,
+    History,
+    Recent,
+    ToggleFavorite,
+    FavoritesOnly,
+    ToggleTags,
+    ToggleJump,
+    ToggleTrend,
+    ToggleDensity,
+    PickColumns,
+    HealthHistory,
+    Help,
+    HostDetail,
+    Dashboard,
+    ManageGroups,
+}
+
+impl Action {
+    /// Short name used in the footer hints.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Action::Quit => "Quit",
+            Action::SelectUp => "Up",
+            Action::SelectDown => "Down",
+            Action::PageUp => "PgUp",
+            Action::PageDown => "PgDn",
+            Action::SelectFirst => "Top",
+            Action::SelectLast => "Bottom",
+            Action::Connect => "Connect",
+            Action::ConnectWithForwards => "Forwards",
+            Action::ConnectRecorded => "Record",
+            Action::FilterEnter => "Filter",
+            Action::JumpToAlias => "Go to",
+            Action::ClearFilter => "Clear",
+            Action::ToggleFuzzy => "Fuzzy",
+            Action::ToggleRegex => "Regex",
+            Action::PingOne => "Ping",
+            Action::PingAll => "PingAll",
+            Action::ToggleGroups => "Groups",
+            Action::ToggleCollapse => "Fold",
+            Action::CycleSort => "Sort",
+            Action::EditHost => "Edit",
+            Action::AddHost => "Add",
+            Action::DeleteHost => "Delete",
+            Action::ToggleSelect => "Select",
+            Action::BatchCommand => "Run",
+            Action::CopyCommand => "Copy",
+            Action::Sftp => "SFTP",
+            Action::Mosh => "Mosh",
+            Action::OpenTunnel => "Tunnel",
+            Action::ListTunnels => "Tunnels",
+            Action::History => "History",
+            Action::Recent => "Recent",
+            Action::ToggleFavorite => "Favorite",
+            Action::FavoritesOnly => "Favorites",
+            Action::ToggleTags => "Tags",
+            Action::ToggleJump => "Jump",
+            Action::ToggleTrend => "Trend",
+            Action::ToggleDensity => "Layout",
+            Action::PickColumns => "Columns",
+            Action::HealthHistory => "Checks",
+            Action::Help => "Help",
+            Action::HostDetail => "Info",
+            Action::Dashboard => "Stats",
+            Action::ManageGroups => "Manage groups",
+        }
+    }
+
+    /// One-line explanation for the help overlay.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Quit => "Quit sshmap",
+            Action::SelectUp => "Move the cursor up",
+            Action::SelectDown => "Move the cursor down",
+            Action::PageUp => "Move up a page",
+            Action::PageDown => "Move down a page",
+            Action::SelectFirst => "Press twice to go to the first host",
+            Action::SelectLast => "Go to the last host",
+            Action::Connect => "SSH to the selected host",
+            Action::ConnectWithForwards => "SSH with the host's port forwards",
+            Action::ConnectRecorded => "SSH with the session recorded to a log",
+            Action::FilterEnter => "Type a filter (#tag matches tags)",
+            Action::JumpToAlias => "Jump to the first alias starting with what you type",
+            Action::ClearFilter => "Clear the filter and selection",
+            Action::ToggleFuzzy => "Toggle fuzzy filtering",
+            Action::ToggleRegex => "Toggle regex filtering",
+            Action::PingOne => "Check the selected host",
+            Action::PingAll => "Check all hosts, or only the marked ones",
+            Action::ToggleGroups => "Toggle group headers",
+            Action::ToggleCollapse => "Collapse or expand the group",
+            Action::CycleSort => "Cycle the sort column and order",
+            Action::EditHost => "Edit the selected host",
+            Action::AddHost => "Add a host",
+            Action::DeleteHost => "Delete the selected host",
+            Action::ToggleSelect => "Mark or unmark the selected host",
+            Action::BatchCommand => "Run a command on the marked hosts",
+            Action::CopyCommand => "Copy the SSH command",
+            Action::Sftp => "Open an SFTP session",
+            Action::Mosh => "Connect with mosh",
+            Action::OpenTunnel => "Forward a port through the host",
+            Action::ListTunnels => "List open tunnels",
+            Action::History => "Connection history",
+            Action::Recent => "Recently connected hosts",
+            Action::ToggleFavorite => "Star or unstar the host",
+            Action::FavoritesOnly => "Show only starred hosts",
+            Action::ToggleTags => "Toggle the tags column",
+            Action::ToggleJump => "Toggle the jump host column",
+            Action::ToggleTrend => "Toggle the RTT trend column",
+            Action::ToggleDensity => "Cycle the normal, compact and wide table",
+            Action::PickColumns => "Choose which columns the table shows",
+            Action::HealthHistory => "Health check log for the host",
+            Action::Help => "Show this help",
+            Action::HostDetail => "Show every field of the host",
+            Action::Dashboard => "Switch to the stats dashboard",
+            Action::ManageGroups => "Add, rename, reorder or delete groups",
+        }
+    }
+}
+
+type Key = (KeyCode, KeyModifiers);
+
+/// Key → action map, read from the `[keybindings]` table of `config.toml`:
+///
+/// ```toml
+/// [keybindings]
+/// quit = ["q", "ctrl-c"]
+/// ping_all = ["ctrl-p"]
+/// ```
+///
+/// Actions not listed keep their default keys.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    try_from = "BTreeMap<Action, Vec<String>>",
+    into = "BTreeMap<Action, Vec<String>>"
+)]
+pub struct Keybindings {
+    by_action: BTreeMap<Action, Vec<String>>,
+    lookup: HashMap<Key, Action>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        let defaults: &[(Action, &[&str])] = &[
+            (Action::Quit, &["q", "ctrl-c"]),
+            (Action::SelectUp, &["up", "k"]),
+            (Action::SelectDown, &["down", "j"]),
+            (Action::PageUp, &["pageup"]),
+            (Action::PageDown, &["pagedown"]),
+            (Action::SelectFirst, &["g"]),
+            (Action::SelectLast, &["G"]),
+            (Action::Connect, &["enter"]),
+            (Action::ConnectWithForwards, &["shift-enter"]),
+            (Action::ConnectRecorded, &["R"]),
+            (Action::FilterEnter, &["/"]),
+            (Action::JumpToAlias, &[":"]),
+            (Action::ClearFilter, &["esc"]),
+            (Action::ToggleFuzzy, &["ctrl-f"]),
+            (Action::ToggleRegex, &["ctrl-r"]),
+            (Action::PingOne, &["p"]),
+            (Action::PingAll, &["P"]),
+            (Action::ToggleGroups, &["ctrl-g"]),
+            (Action::ToggleCollapse, &["c"]),
+            (Action::CycleSort, &["s"]),
+            (Action::EditHost, &["e"]),
+            (Action::AddHost, &["a"]),
+            (Action::DeleteHost, &["d", "delete"]),
+            (Action::ToggleSelect, &["space"]),
+            (Action::BatchCommand, &["ctrl-b"]),
+            (Action::CopyCommand, &["y"]),
+            (Action::Sftp, &["S"]),
+            (Action::Mosh, &["m"]),
+            (Action::OpenTunnel, &["t"]),
+            (Action::ListTunnels, &["T"]),
+            (Action::History, &["H"]),
+            (Action::Recent, &["r"]),
+            (Action::ToggleFavorite, &["f"]),
+            (Action::FavoritesOnly, &["F"]),
+            (Action::ToggleTags, &["ctrl-t"]),
+            (Action::ToggleJump, &["J"]),
+            (Action::ToggleTrend, &["ctrl-s"]),
+            (Action::ToggleDensity, &["z"]),
+            (Action::PickColumns, &["ctrl-h"]),
+            (Action::HealthHistory, &["h"]),
+            (Action::Help, &["?"]),
+            (Action::HostDetail, &["i"]),
+            (Action::Dashboard, &["D"]),
+            (Action::ManageGroups, &["M"]),
+        ];
+        let by_action = defaults
+            .iter()
+            .map(|(action, keys)| (*action, keys.iter().map(|k| k.to_string()).collect()))
+            .collect();
+        Self::build(by_action).expect("default keybindings are valid")
+    }
+}
+
+impl TryFrom<BTreeMap<Action, Vec<String>>> for Keybindings {
+    type Error = String;
+
+    fn try_from(overrides: BTreeMap<Action, Vec<String>>) -> Result<Self, Self::Error> {
+        let mut by_action = Keybindings::default().by_action;
+        by_action.extend(overrides);
+        Self::build(by_action)
+    }
+}
+
+impl From<Keybindings> for BTreeMap<Action, Vec<String>> {
+    fn from(bindings: Keybindings) -> Self {
+        bindings.by_action
+    }
+}
+
+impl Keybindings {
+    /// Fails on a key bound to two actions, e.g. a user binding that takes
+    /// a default key without rebinding the action that had it.
+    fn build(by_action: BTreeMap<Action, Vec<String>>) -> Result<Self, String> {
+        let mut lookup = HashMap::new();
+        for (action, keys) in &by_action {
+            for key in keys {
+                match lookup.insert(parse_key(key)?, *action) {
+                    Some(other) if other != *action => {
+                        return Err(format!(
+                            "'{}' is bound to both {} and {}; rebind one of them",
+                            key,
+                            config_name(other),
+                            config_name(*action)
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(Self { by_action, lookup })
+    }
+
+    pub fn action_for(&self, key: &KeyEvent) -> Option<Action> {
+        self.lookup.get(&normalize(key)).copied()
+    }
+
+    /// Every action with the display labels of all its keys, in declaration order.
+    pub fn all(&self) -> Vec<(Action, Vec<String>)> {
+        self.by_action
+            .iter()
+            .map(|(action, keys)| {
+                let labels = keys.iter().filter_map(|k| parse_key(k).ok()).map(|k| key_label(&k));
+                (*action, labels.collect())
+            })
+            .collect()
+    }
+
+    /// Display label of the first key bound to `action`, if any.
+    pub fn key_for(&self, action: Action) -> Option<String> {
+        let key = self.by_action.get(&action)?.first()?;
+        parse_key(key).ok().map(|k| key_label(&k))
+    }
+}
+
+// Shift is already reflected in the character itself ('P' vs 'p')
+fn normalize(key: &KeyEvent) -> Key {
+    let mut modifiers = key.modifiers;
+    if matches!(key.code, KeyCode::Char(_) | KeyCode::BackTab) {
+        modifiers.remove(KeyModifiers::SHIFT);
+    }
+    (key.code, modifiers)
+}
+
+/// How `action` is written in the `[keybindings]` table.
+fn config_name(action: Action) -> String {
+    match serde_json::to_value(action) {
+        Ok(serde_json::Value::String(name)) => name,
+        _ => format!("{:?}", action),
+    }
+}
+
+/// Parse `"q"`, `"P"`, `"ctrl-c"`, `"alt-enter"`, `"pagedown"`, `"f5"`, ...
+fn parse_key(spec: &str) -> Result<Key, String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut name = spec;
+    while let Some((prefix, rest)) = name.split_once('-') {
+        if rest.is_empty() {
+            break;
+        }
+        match prefix.to_lowercase().as_str() {
+            "ctrl" => modifiers |= KeyModifiers::CONTROL,
+            "alt" => modifiers |= KeyModifiers::ALT,
+            "shift" => modifiers |= KeyModifiers::SHIFT,
+            _ => return Err(format!("unknown modifier in key '{}'", spec)),
+        }
+        name = rest;
+    }
+
+    let mut chars = name.chars();
+    let code = match (chars.next(), chars.next()) {
+        (Some(c), None) => KeyCode::Char(c),
+        _ => match name.to_lowercase().as_str() {
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "enter" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "backspace" => KeyCode::Backspace,
+            "delete" => KeyCode::Delete,
+            "insert" => KeyCode::Insert,
+            "space" => KeyCode::Char(' '),
+            f if f.starts_with('f') => f[1..]
+                .parse()
+                .map(KeyCode::F)
+                .map_err(|_| format!("unknown key '{}'", spec))?,
+            _ => return Err(format!("unknown key '{}'", spec)),
+        },
+    };
+
+    // Match what `normalize` does to incoming events
+    if matches!(code, KeyCode::Char(_) | KeyCode::BackTab) {
+        modifiers.remove(KeyModifiers::SHIFT);
+    }
+    Ok((code, modifiers))
+}
+
+fn key_label((code, modifiers): &Key) -> String {
+    let name = match code {
+        KeyCode::Up => "↑".to_string(),
+        KeyCode::Down => "↓".to_string(),
+        KeyCode::Left => "←".to_string(),
+        KeyCode::Right => "→".to_string(),
+        KeyCode::PageUp => "PgUp".to_string(),
+        KeyCode::PageDown => "PgDn".to_string(),
+        KeyCode::Home => "Home".to_string(),
+        KeyCode::End => "End".to_string(),
+        KeyCode::Enter => "Enter".to_string(),
+        KeyCode::Esc => "Esc".to_string(),
+        KeyCode::Tab => "Tab".to_string(),
+        KeyCode::BackTab => "S-Tab".to_string(),
+        KeyCode::Backspace => "Bksp".to_string(),
+        KeyCode::Delete => "Del".to_string(),
+        KeyCode::Insert => "Ins".to_string(),
+        KeyCode::Char(' ') => "Space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::F(n) => format!("F{}", n),
+        other => format!("{:?}", other),
+    };
+
+    let mut label = String::new();
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        label.push_str("Ctrl-");
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        label.push_str("Alt-");
+    }
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        label.push_str("Shift-");
+    }
+    label.push_str(&name);
+    label
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bindings(overrides: &[(Action, &[&str])]) -> Result<Keybindings, String> {
+        let overrides = overrides
+            .iter()
+            .map(|(action, keys)| (*action, keys.iter().map(|k| k.to_string()).collect()))
+            .collect::<BTreeMap<_, Vec<_>>>();
+        Keybindings::try_from(overrides)
+    }
+
+    #[test]
+    fn taking_a_default_key_is_reported() {
+        let err = bindings(&[(Action::Connect, &["p"])]).err().unwrap();
+        assert!(err.contains("'p'"), "{}", err);
+        assert!(err.contains("connect") && err.contains("ping_one"), "{}", err);
+    }
+
+    #[test]
+    fn a_default_key_can_move_once_its_action_is_rebound() {
+        let bindings = bindings(&[(Action::Connect, &["p"]), (Action::PingOne, &["o"])]).unwrap();
+        let key = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+        assert_eq!(bindings.action_for(&key('p')), Some(Action::Connect));
+        assert_eq!(bindings.action_for(&key('o')), Some(Action::PingOne));
+    }
+}
@@ -0,0 +1,43 @@
+use crate::host;
+use anyhow::Context;
+use std::fs::{self, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing_subscriber::EnvFilter;
+
+/// Environment variable holding the log level (or a full filter such as
+/// `sshmap=debug`). Nothing is logged unless it is set.
+pub const LOG_ENV: &str = "SSHMAP_LOG";
+
+pub fn default_log_path() -> PathBuf {
+    host::sshmap_config_dir().join("sshmap.log")
+}
+
+/// Send `tracing` events as JSON lines to `path` (default
+/// `~/.config/sshmap/sshmap.log`), if `SSHMAP_LOG` asks for them. Must run
+/// before the TUI takes over the terminal.
+pub fn init(path: Option<&Path>) -> anyhow::Result<()> {
+    let level = match std::env::var(LOG_ENV) {
+        Ok(level) if !level.is_empty() => level,
+        _ => return Ok(()),
+    };
+    let filter = EnvFilter::try_new(&level)
+        .with_context(|| format!("{}={} is not a valid log filter", LOG_ENV, level))?;
+
+    let path = path.map(Path::to_path_buf).unwrap_or_else(default_log_path);
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("opening {}", path.display()))?;
+
+    tracing_subscriber::fmt()
+        .json()
+        .with_env_filter(filter)
+        .with_writer(Mutex::new(file))
+        .init();
+    Ok(())
+}
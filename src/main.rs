@@ -1,22 +1,78 @@
 mod app;
+mod batch;
+mod cli;
+mod clipboard;
+mod config;
+mod desktop;
+mod favorites;
+mod form;
+mod groups;
 mod health;
+mod history;
 mod host;
+mod keys;
+mod logging;
+mod metrics;
+mod state;
+#[cfg(test)]
+mod test_support;
+mod theme;
+mod tls;
+mod tunnel;
 mod ui;
+mod watch;
 
 use anyhow::Result;
+use app::{TOAST, TOAST_LONG, ToastLevel};
+use clap::Parser;
 use crossterm::{
-    event::{self, Event, KeyCode, KeyModifiers},
+    cursor,
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent,
+        KeyboardEnhancementFlags, KeyModifiers, MouseButton, MouseEventKind,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
     execute,
-    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
+    terminal::{
+        EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+        supports_keyboard_enhancement,
+    },
 };
+use groups::GroupAction;
+use keys::Action;
 use ratatui::prelude::*;
+use std::collections::HashMap;
 use std::io;
-use std::sync::Arc;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 fn main() -> Result<()> {
+    cli::complete_from_env();
+    let args = cli::Cli::parse();
+    args.apply_paths();
+    logging::init(args.log_file.as_deref())?;
+    install_terminal_guards();
+    // Host loading needs merge_strategy; a broken config.toml is reported
+    // by whatever loads it next
+    if let Ok(config) = config::Config::load() {
+        host::configure(&config);
+    }
+    if let Some(command) = args.command {
+        return cli::run(command);
+    }
+    if let Some(ref alias) = args.connect {
+        return cli::connect(alias, false);
+    }
+    // Everything below puts the terminal in raw mode
+    if args.no_tui {
+        return cli::run_list(&cli::ListArgs::default());
+    }
+
     // Create sample config if none exists
     host::create_sample_config()?;
+    let config = config::Config::load()?;
+    health::configure(&config);
 
     let hosts = host::load_hosts();
     if hosts.is_empty() {
@@ -24,27 +80,164 @@ fn main() -> Result<()> {
         std::process::exit(1);
     }
 
-    eprintln!("Loaded {} hosts", hosts.len());
+    tracing::info!(count = hosts.len(), "loaded hosts");
 
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+    let mut app = app::App::new(hosts, config, args.filter, args.group);
+    match state::AppState::load() {
+        Ok(state) => state.restore(&mut app),
+        Err(e) => tracing::warn!("ignoring saved state: {:#}", e),
+    }
 
-    let mut app = app::App::new(hosts);
+    match watch::spawn() {
+        Ok(rx) => app.reloads = Some(rx),
+        Err(e) => tracing::warn!("not watching config for changes: {}", e),
+    }
+
+    if app.config.metrics_port != 0 {
+        if let Err(e) = metrics::serve(&app.config.metrics_bind, app.config.metrics_port) {
+            app.push_toast(format!("Metrics endpoint: {}", e), ToastLevel::Error, TOAST_LONG);
+        }
+    }
 
     // Initial health check
-    health::check_all(Arc::clone(&app.hosts));
+    app.check_all();
+    health::spawn_auto_refresh(
+        Arc::clone(&app.hosts),
+        Arc::clone(&app.refresh_interval),
+        Arc::clone(&app.checks_paused),
+        Arc::clone(&app.last_check),
+        Arc::clone(&app.health_concurrency),
+    );
+
+    enter_tui()?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    // Always hand the terminal back, even if the event loop bails out
+    IN_EVENT_LOOP.store(true, Ordering::SeqCst);
+    let result = run(&mut terminal, &mut app);
+    IN_EVENT_LOOP.store(false, Ordering::SeqCst);
+    leave_tui()?;
+    terminal.show_cursor()?;
+
+    if let Err(e) = state::AppState::capture(&app).save() {
+        tracing::error!("failed to save state: {:#}", e);
+    }
+
+    result
+}
+
+/// Whether the terminal is in raw mode on the alternate screen.
+static TUI_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Whether the terminal speaks the kitty keyboard protocol, which it needs
+/// to report Shift-Enter; asked once, on the first `enter_tui`.
+static KEYBOARD_ENHANCED: OnceLock<bool> = OnceLock::new();
+
+fn enter_tui() -> Result<()> {
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableMouseCapture)?;
+    if *KEYBOARD_ENHANCED.get_or_init(|| supports_keyboard_enhancement().unwrap_or(false)) {
+        let flags = KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES;
+        execute!(io::stdout(), PushKeyboardEnhancementFlags(flags))?;
+    }
+    TUI_ACTIVE.store(true, Ordering::SeqCst);
+    Ok(())
+}
+
+fn leave_tui() -> Result<()> {
+    TUI_ACTIVE.store(false, Ordering::SeqCst);
+    if KEYBOARD_ENHANCED.get() == Some(&true) {
+        execute!(io::stdout(), PopKeyboardEnhancementFlags)?;
+    }
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture)?;
+    Ok(())
+}
+
+/// Whether `run` is going, so a signal can leave through it.
+static IN_EVENT_LOOP: AtomicBool = AtomicBool::new(false);
+
+/// Set by the signal handler; `run` quits as if confirmed with y.
+static TERMINATE: AtomicBool = AtomicBool::new(false);
+
+/// Put the terminal back on a panic or SIGINT/SIGTERM/SIGHUP, so the shell
+/// is usable and the panic message lands on the normal screen. Once the
+/// event loop is running, signals make it quit instead.
+fn install_terminal_guards() {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+
+    let handler = ctrlc::set_handler(|| {
+        // The event loop closes tunnels and saves state on its way out
+        if IN_EVENT_LOOP.load(Ordering::SeqCst) {
+            TERMINATE.store(true, Ordering::SeqCst);
+            return;
+        }
+        restore_terminal();
+        std::process::exit(130);
+    });
+    if let Err(e) = handler {
+        tracing::warn!("no signal handler installed: {}", e);
+    }
+}
+
+/// Best-effort `leave_tui` for the panic hook and signal handler: stdout
+/// may be mid-write on another thread, so errors are ignored.
+fn restore_terminal() {
+    if TUI_ACTIVE.swap(false, Ordering::SeqCst) {
+        let mut stdout = io::stdout();
+        if KEYBOARD_ENHANCED.get() == Some(&true) {
+            let _ = execute!(stdout, PopKeyboardEnhancementFlags);
+        }
+        let _ = disable_raw_mode();
+        let _ = execute!(stdout, LeaveAlternateScreen, DisableMouseCapture, cursor::Show);
+    }
+}
 
+fn run(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>, app: &mut app::App) -> Result<()> {
     loop {
+        if TERMINATE.load(Ordering::SeqCst) {
+            app.kill_all_tunnels();
+            return Ok(());
+        }
+        app.expire_toasts();
+        app.reap_tunnels();
+        app.apply_reloads();
+        app.apply_preflight();
         terminal.draw(|f| {
-            ui::render(f, &mut app);
+            ui::render(f, app);
         })?;
 
         if event::poll(Duration::from_millis(100))? {
-            if let Event::Key(key) = event::read()? {
+            let ev = event::read()?;
+
+            // Scrolling anywhere on screen moves through the host table
+            if let Event::Mouse(mouse) = ev {
+                match mouse.kind {
+                    MouseEventKind::Down(MouseButton::Left) => app.click_row(mouse.row),
+                    MouseEventKind::ScrollUp => app.select_up(),
+                    MouseEventKind::ScrollDown => app.select_down(),
+                    _ => {}
+                }
+            }
+
+            if let Event::Key(key) = ev {
+                if !matches!(app.mode, app::Mode::Normal) {
+                    handle_mode_key(app, key);
+                    continue;
+                }
+
+                let action = app.config.keybindings.action_for(&key);
+
                 if app.filter_mode {
+                    // Only the filter-kind toggles apply while typing a query
+                    if let Some(action @ (Action::ToggleFuzzy | Action::ToggleRegex)) = action {
+                        dispatch(app, action);
+                        continue;
+                    }
                     match key.code {
                         KeyCode::Esc => {
                             app.filter_mode = false;
@@ -53,7 +246,9 @@ fn main() -> Result<()> {
                             app.filter_mode = false;
                         }
                         KeyCode::Backspace => {
-                            app.filter.pop();
+                            if app.filter.pop().is_none() {
+                                app.group_filter = None;
+                            }
                             app.selected = 0;
                             app.scroll_offset = 0;
                         }
@@ -67,89 +262,490 @@ fn main() -> Result<()> {
                     continue;
                 }
 
-                match key.code {
-                    KeyCode::Char('q') => app.should_quit = true,
-                    KeyCode::Char('c')
-                        if key.modifiers.contains(KeyModifiers::CONTROL) =>
-                    {
-                        app.should_quit = true;
-                    }
-                    KeyCode::Up | KeyCode::Char('k') => app.select_up(),
-                    KeyCode::Down | KeyCode::Char('j') => app.select_down(),
-                    KeyCode::PageUp => app.page_up(10),
-                    KeyCode::PageDown => app.page_down(10),
-                    KeyCode::Enter => {
-                        app.connect_selected();
-                    }
-                    KeyCode::Char('/') => {
-                        app.filter_mode = true;
-                        app.message = None;
-                    }
-                    KeyCode::Esc => {
-                        app.filter.clear();
-                        app.selected = 0;
-                        app.scroll_offset = 0;
-                    }
-                    KeyCode::Char('p') => {
-                        // Ping selected host
-                        if let Some(idx) = app.selected_host_index() {
-                            health::check_one(Arc::clone(&app.hosts), idx);
-                        }
-                    }
-                    KeyCode::Char('P') => {
-                        // Ping all
-                        health::check_all(Arc::clone(&app.hosts));
-                        app.message = Some("Pinging all hosts...".into());
-                    }
-                    KeyCode::Char('g') => {
-                        app.show_groups = !app.show_groups;
-                    }
-                    _ => {}
+                // Any other key in between cancels a pending `gg`
+                if action != Some(Action::SelectFirst) {
+                    app.pending_g = None;
+                }
+
+                // Unbound digits pick one of the rows on screen
+                if let (None, KeyCode::Char(c @ '1'..='9')) = (action, key.code) {
+                    app.select_visible_row(c as usize - '1' as usize);
+                    continue;
+                }
+
+                if let Some(action) = action {
+                    dispatch(app, action);
                 }
             }
         }
 
         // Handle connection
         if let Some(idx) = app.connect_index.take() {
-            let cmd = {
-                let hosts = app.hosts.lock().unwrap();
-                hosts[idx].ssh_command()
-            };
-
-            // Restore terminal
-            disable_raw_mode()?;
-            execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
-            terminal.show_cursor()?;
+            match app.session_command(idx) {
+                Ok((cmd, recording)) => {
+                    let paused = Arc::clone(&app.checks_paused);
+                    let result =
+                        with_terminal(terminal, &paused, || run_session(app, idx, &cmd))?;
+                    match (result, recording) {
+                        (Some(hook_error), _) => {
+                            app.push_toast(hook_error, ToastLevel::Warning, TOAST_LONG)
+                        }
+                        (None, Some(path)) => app.push_toast(
+                            format!("Session recorded to {}", path.display()),
+                            ToastLevel::Success,
+                            TOAST_LONG,
+                        ),
+                        (None, None) => {
+                            app.push_toast("Returned from SSH session", ToastLevel::Info, TOAST)
+                        }
+                    }
+                }
+                Err(e) => {
+                    let message = format!("Can't create the recordings directory: {}", e);
+                    app.push_toast(message, ToastLevel::Error, TOAST_LONG)
+                }
+            }
+        }
 
-            // Launch SSH
-            let status = std::process::Command::new(&cmd[0])
-                .args(&cmd[1..])
-                .status();
+        if let Some(idx) = app.mosh_index.take() {
+            if on_path("mosh") {
+                let cmd = app.config.mosh_command(&app.hosts.lock().unwrap()[idx]);
+                let paused = Arc::clone(&app.checks_paused);
+                let result = with_terminal(terminal, &paused, || run_session(app, idx, &cmd))?;
+                toast_session_end(app, result, "Returned from mosh session");
+            } else {
+                app.push_toast(
+                    "mosh is not on PATH; install it from https://mosh.org or connect with ssh",
+                    ToastLevel::Error, TOAST_LONG,
+                );
+            }
+        }
 
-            match status {
-                Ok(s) => {
-                    if !s.success() {
-                        eprintln!("SSH exited with: {}", s);
-                    }
+        if let Some(path) = app.edit_file.take() {
+            let paused = Arc::clone(&app.checks_paused);
+            match with_terminal(terminal, &paused, || run_editor(&path))? {
+                Ok(()) => {
+                    app.reload_hosts();
+                    let message = format!("Reloaded hosts from {}", host::tilde_path(&path));
+                    app.push_toast(message, ToastLevel::Success, TOAST);
                 }
-                Err(e) => eprintln!("Failed to launch ssh: {}", e),
+                Err(e) => app.push_toast(e, ToastLevel::Error, TOAST_LONG),
             }
+        }
 
-            // Re-enter TUI
-            enable_raw_mode()?;
-            execute!(io::stdout(), EnterAlternateScreen)?;
-            terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
-            app.message = Some("Returned from SSH session".into());
+        if let Some(idx) = app.sftp_index.take() {
+            let cmd = {
+                let hosts = app.hosts.lock().unwrap();
+                hosts[idx].sftp_command()
+            };
+            let paused = Arc::clone(&app.checks_paused);
+            let result = with_terminal(terminal, &paused, || run_session(app, idx, &cmd))?;
+            toast_session_end(app, result, "Returned from SFTP session");
         }
 
         if app.should_quit {
-            break;
+            return Ok(());
         }
     }
+}
 
-    disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+/// Report how a session ended: the failed hook if there was one, otherwise `done`.
+fn toast_session_end(app: &mut app::App, hook_error: Option<String>, done: &str) {
+    match hook_error {
+        Some(e) => app.push_toast(e, ToastLevel::Warning, TOAST_LONG),
+        None => app.push_toast(done, ToastLevel::Info, TOAST),
+    }
+}
+
+/// Leave the TUI so `f` can run interactive commands (hooks, ssh, sftp)
+/// on the normal screen, then take the terminal back.
+fn with_terminal<T>(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    checks_paused: &AtomicBool,
+    f: impl FnOnce() -> T,
+) -> Result<T> {
+    leave_tui()?;
     terminal.show_cursor()?;
 
-    Ok(())
+    checks_paused.store(true, Ordering::Relaxed);
+    let result = f();
+    checks_paused.store(false, Ordering::Relaxed);
+
+    enter_tui()?;
+    terminal.clear()?;
+    Ok(result)
+}
+
+/// Connect to the host at `idx` with `cmd`, between its pre- and
+/// post-connect commands, and record the session. Returns a message for
+/// the status line if a hook failed.
+fn run_session(app: &mut app::App, idx: usize, cmd: &[String]) -> Option<String> {
+    let (pre, post, environment) = {
+        let hosts = app.hosts.lock().unwrap();
+        let host = &hosts[idx];
+        (
+            host.pre_connect_command.clone(),
+            host.post_connect_command.clone(),
+            host.environment.clone(),
+        )
+    };
+    if let Some(ref hook) = pre {
+        if let Err(e) = run_hook(hook) {
+            return Some(format!("Not connecting: pre-connect command {}", e));
+        }
+    }
+
+    app.record_connection(idx);
+    let started = Instant::now();
+    if run_interactive(cmd, &environment) {
+        app.mark_connected(idx);
+    }
+    app.finish_connection(started.elapsed());
+
+    let hook = post?;
+    run_hook(&hook).err().map(|e| format!("Post-connect command {}", e))
+}
+
+/// Run `cmd` in the foreground with `environment` added to ours; returns
+/// whether it exited successfully.
+fn run_interactive(cmd: &[String], environment: &HashMap<String, String>) -> bool {
+    let status = std::process::Command::new(&cmd[0])
+        .args(&cmd[1..])
+        .envs(environment)
+        .status();
+    match status {
+        Ok(s) => {
+            if !s.success() {
+                tracing::warn!("{} exited with: {}", cmd[0], s);
+            }
+            s.success()
+        }
+        Err(e) => {
+            tracing::error!("failed to launch {}: {}", cmd[0], e);
+            false
+        }
+    }
+}
+
+/// Open `path` in `$EDITOR` (vi if unset), which may include arguments.
+fn run_editor(path: &std::path::Path) -> std::result::Result<(), String> {
+    let status = std::process::Command::new("sh")
+        .arg("-c")
+        .arg("${EDITOR:-vi} \"$1\"")
+        .arg("sh")
+        .arg(path)
+        .status();
+    match status {
+        Ok(s) if s.success() => Ok(()),
+        Ok(s) => Err(format!("Editor exited with {}", s)),
+        Err(e) => Err(format!("Could not run the editor: {}", e)),
+    }
+}
+
+/// Whether an executable called `program` is in one of the PATH directories.
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH").is_some_and(|paths| {
+        std::env::split_paths(&paths).any(|dir| dir.join(program).is_file())
+    })
+}
+
+/// Run a pre- or post-connect `hook` through `sh -c`, on the terminal so
+/// it can prompt.
+fn run_hook(hook: &str) -> std::result::Result<(), String> {
+    tracing::info!(hook, "running connect hook");
+    match std::process::Command::new("sh").arg("-c").arg(hook).status() {
+        Ok(s) if s.success() => Ok(()),
+        Ok(s) => Err(format!("'{}' failed ({})", hook, s)),
+        Err(e) => Err(format!("'{}' could not run: {}", hook, e)),
+    }
+}
+
+fn dispatch(app: &mut app::App, action: Action) {
+    match action {
+        Action::Quit if !app.active_tunnels.is_empty() => app.mode = app::Mode::QuitConfirm,
+        Action::Quit => app.should_quit = true,
+        Action::SelectUp => app.select_up(),
+        Action::SelectDown => app.select_down(),
+        Action::PageUp => app.page_up(10),
+        Action::PageDown => app.page_down(10),
+        Action::Connect => app.connect_selected(false, false),
+        Action::ConnectWithForwards => app.connect_selected(true, false),
+        Action::ConnectRecorded => app.connect_selected(false, true),
+        Action::FilterEnter => {
+            app.filter_mode = true;
+            app.toasts.clear();
+        }
+        Action::JumpToAlias => app.open_command_mode(),
+        Action::ClearFilter => {
+            app.selection.clear();
+            app.filter.clear();
+            app.selected = 0;
+            app.scroll_offset = 0;
+        }
+        Action::ToggleFuzzy => app.toggle_filter_kind(app::FilterKind::Fuzzy),
+        Action::ToggleRegex => app.toggle_filter_kind(app::FilterKind::Regex),
+        Action::PingOne => {
+            if let Some(idx) = app.selected_host_index() {
+                health::check_one(Arc::clone(&app.hosts), idx);
+            }
+        }
+        Action::PingAll if !app.selection.is_empty() => {
+            health::check_selected(
+                Arc::clone(&app.hosts),
+                &app.selection,
+                app.config.max_health_concurrency,
+            );
+            let message = format!("Pinging {} selected hosts...", app.selection.len());
+            app.push_toast(message, ToastLevel::Info, TOAST);
+        }
+        Action::PingAll => {
+            app.check_all();
+            app.push_toast("Pinging all hosts...", ToastLevel::Info, TOAST);
+        }
+        Action::SelectFirst => app.press_g(),
+        Action::SelectLast => app.select_last(),
+        Action::ToggleGroups => app.toggle_groups(),
+        Action::ToggleCollapse => app.toggle_collapse(),
+        Action::CycleSort => app.cycle_sort(),
+        Action::EditHost => app.open_edit_form(),
+        Action::AddHost => app.open_add_form(),
+        Action::DeleteHost => app.confirm_delete(),
+        Action::ToggleSelect => app.toggle_selection(),
+        Action::BatchCommand => app.open_batch_prompt(),
+        Action::CopyCommand => app.copy_command(),
+        Action::Sftp => app.sftp_index = app.selected_host_index(),
+        Action::Mosh => app.mosh_index = app.selected_host_index(),
+        Action::OpenTunnel => app.open_tunnel_prompt(),
+        Action::ListTunnels => app.mode = app::Mode::TunnelList { selected: 0 },
+        Action::History => app.mode = app::Mode::History { selected: 0 },
+        Action::Recent => app.mode = app::Mode::Recent,
+        Action::ToggleFavorite => app.toggle_favorite(),
+        Action::FavoritesOnly => app.toggle_favorites_only(),
+        Action::ToggleTags => app.toggle_column(app::ColumnId::Tags),
+        Action::ToggleJump => app.toggle_column(app::ColumnId::JumpHost),
+        Action::ToggleTrend => app.toggle_column(app::ColumnId::Trend),
+        Action::PickColumns => app.open_column_picker(),
+        Action::ToggleDensity => app.toggle_density(),
+        Action::Help => app.mode = app::Mode::Help { scroll: 0 },
+        Action::Dashboard => app.mode = app::Mode::Dashboard,
+        Action::ManageGroups => app.open_group_panel(),
+        Action::HostDetail => {
+            if let Some(idx) = app.selected_host_index() {
+                app.mode = app::Mode::HostDetail(idx);
+            }
+        }
+        Action::HealthHistory => {
+            if let Some(index) = app.selected_host_index() {
+                app.mode = app::Mode::HealthHistory { index, scroll: 0 };
+            }
+        }
+    }
+}
+
+/// Keys for modal views; these take raw input rather than keybindings.
+fn handle_mode_key(app: &mut app::App, key: KeyEvent) {
+    match &mut app.mode {
+        app::Mode::Normal => {}
+        app::Mode::Edit(form) | app::Mode::Add(form) => match key.code {
+            KeyCode::Esc => app.mode = app::Mode::Normal,
+            KeyCode::Enter => app.submit_form(),
+            KeyCode::Tab | KeyCode::Down => form.next_field(),
+            KeyCode::BackTab | KeyCode::Up => form.prev_field(),
+            KeyCode::Left => form.cycle(false),
+            KeyCode::Right => form.cycle(true),
+            KeyCode::Backspace => form.pop(),
+            KeyCode::Char(c) => form.push(c),
+            _ => {}
+        },
+        app::Mode::QuitConfirm => {
+            app.mode = app::Mode::Normal;
+            if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                app.kill_all_tunnels();
+                app.should_quit = true;
+            }
+        }
+        app::Mode::Delete(idx) => {
+            let idx = *idx;
+            app.mode = app::Mode::Normal;
+            if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                app.delete_host(idx);
+            }
+        }
+        app::Mode::Command(command) => match key.code {
+            KeyCode::Esc => app.cancel_command(),
+            KeyCode::Enter => app.accept_command(),
+            KeyCode::Backspace => {
+                command.input.pop();
+                app.jump_to_prefix();
+            }
+            KeyCode::Char(c) => {
+                command.input.push(c);
+                app.jump_to_prefix();
+            }
+            _ => {}
+        },
+        app::Mode::BatchPrompt(input) => match key.code {
+            KeyCode::Esc => app.mode = app::Mode::Normal,
+            KeyCode::Enter if !input.trim().is_empty() => {
+                let command = input.trim().to_string();
+                app.run_batch(&command);
+            }
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) => input.push(c),
+            _ => {}
+        },
+        app::Mode::BatchOutput { scroll, .. } => match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => app.mode = app::Mode::Normal,
+            KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => *scroll += 1,
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+            KeyCode::PageDown => *scroll += 10,
+            _ => {}
+        },
+        app::Mode::TunnelPrompt { input, error, .. } => match key.code {
+            KeyCode::Esc => app.mode = app::Mode::Normal,
+            KeyCode::Enter => app.start_tunnel(),
+            KeyCode::Backspace => {
+                input.pop();
+                *error = None;
+            }
+            KeyCode::Char(c) => {
+                input.push(c);
+                *error = None;
+            }
+            _ => {}
+        },
+        app::Mode::ConnectChoice(idx) => {
+            let idx = *idx;
+            app.mode = app::Mode::Normal;
+            match key.code {
+                KeyCode::Char('c') | KeyCode::Enter => app.connect_here(idx),
+                KeyCode::Char('w') => app.connect_in_tmux(idx),
+                KeyCode::Char('m') => app.mosh_index = Some(idx),
+                _ => {}
+            }
+        }
+        app::Mode::PreflightFailed { index, .. } => {
+            let idx = *index;
+            app.mode = app::Mode::Normal;
+            if key.code == KeyCode::Char('y') {
+                app.connect_unchecked(idx);
+            }
+        }
+        app::Mode::History { selected } => match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('H') => app.mode = app::Mode::Normal,
+            KeyCode::Up | KeyCode::Char('k') => *selected = selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                *selected = (*selected + 1).min(app.history.len().saturating_sub(1))
+            }
+            KeyCode::Enter => {
+                let selected = *selected;
+                app.mode = app::Mode::Normal;
+                app.reconnect_from_history(selected);
+            }
+            _ => {}
+        },
+        app::Mode::HealthHistory { scroll, .. } => match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('h') => app.mode = app::Mode::Normal,
+            KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => *scroll += 1,
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+            KeyCode::PageDown => *scroll += 10,
+            _ => {}
+        },
+        app::Mode::Help { scroll } => match key.code {
+            KeyCode::Up | KeyCode::Char('k') => *scroll = scroll.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => *scroll += 1,
+            KeyCode::PageUp => *scroll = scroll.saturating_sub(10),
+            KeyCode::PageDown | KeyCode::Char(' ') => *scroll += 10,
+            KeyCode::Home => *scroll = 0,
+            _ => app.mode = app::Mode::Normal,
+        },
+        app::Mode::GroupManage(panel) => match &mut panel.action {
+            GroupAction::Browse => match (key.code, key.modifiers.contains(KeyModifiers::SHIFT)) {
+                (KeyCode::Esc | KeyCode::Char('q'), _) => app.mode = app::Mode::Normal,
+                (KeyCode::Up, true) | (KeyCode::Char('K'), _) => app.move_group(true),
+                (KeyCode::Down, true) | (KeyCode::Char('J'), _) => app.move_group(false),
+                (KeyCode::Up | KeyCode::Char('k'), _) => panel.select_up(),
+                (KeyCode::Down | KeyCode::Char('j'), _) => panel.select_down(),
+                (KeyCode::Char('a'), _) => {
+                    panel.action = GroupAction::Add(String::new());
+                    panel.error = None;
+                }
+                (KeyCode::Char('r'), _) => {
+                    if let Some(name) = panel.current() {
+                        panel.action = GroupAction::Rename(name.to_string());
+                        panel.error = None;
+                    }
+                }
+                (KeyCode::Char('d') | KeyCode::Delete, _) => app.start_group_delete(),
+                _ => {}
+            },
+            GroupAction::Add(input) | GroupAction::Rename(input) => match key.code {
+                KeyCode::Esc => panel.action = GroupAction::Browse,
+                KeyCode::Enter => app.submit_group_input(),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            },
+            GroupAction::Delete { targets, selected } => match key.code {
+                KeyCode::Esc => panel.action = GroupAction::Browse,
+                KeyCode::Enter => app.confirm_group_delete(),
+                KeyCode::Up | KeyCode::Char('k') => *selected = selected.saturating_sub(1),
+                KeyCode::Down | KeyCode::Char('j') => {
+                    *selected = (*selected + 1).min(targets.len() - 1)
+                }
+                _ => {}
+            },
+        },
+        app::Mode::Dashboard => {
+            let toggle = app.config.keybindings.action_for(&key) == Some(Action::Dashboard);
+            if toggle || matches!(key.code, KeyCode::Esc | KeyCode::Char('q')) {
+                app.mode = app::Mode::Normal;
+            }
+        }
+        app::Mode::HostDetail(idx) => match key.code {
+            KeyCode::Esc | KeyCode::Char('i') | KeyCode::Char('q') => app.mode = app::Mode::Normal,
+            KeyCode::Char('e') => {
+                let idx = *idx;
+                app.edit_host_source(idx);
+            }
+            _ => {}
+        },
+        app::Mode::Recent => match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('r') => app.mode = app::Mode::Normal,
+            KeyCode::Char(c @ '1'..='5') => app.connect_recent(c as usize - '1' as usize),
+            _ => {}
+        },
+        app::Mode::ColumnPicker { selected, columns } => match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => app.mode = app::Mode::Normal,
+            KeyCode::Up | KeyCode::Char('k') => *selected = selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                *selected = (*selected + 1).min(app::ColumnId::ALL.len() - 1)
+            }
+            KeyCode::Char(' ') => app::toggle_column(columns, app::ColumnId::ALL[*selected]),
+            KeyCode::Enter => {
+                app.visible_columns = std::mem::take(columns);
+                app.mode = app::Mode::Normal;
+            }
+            _ => {}
+        },
+        app::Mode::TunnelList { selected } => match key.code {
+            KeyCode::Esc | KeyCode::Char('q') | KeyCode::Char('T') => app.mode = app::Mode::Normal,
+            KeyCode::Up | KeyCode::Char('k') => *selected = selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Char('j') => {
+                *selected = (*selected + 1).min(app.active_tunnels.len().saturating_sub(1))
+            }
+            KeyCode::Char('d') | KeyCode::Delete => {
+                let i = *selected;
+                *selected = i.min(app.active_tunnels.len().saturating_sub(2));
+                app.kill_tunnel(i);
+            }
+            _ => {}
+        },
+    }
 }
@@ -0,0 +1,131 @@
+//! Prometheus text exposition of the latest health results on `GET /metrics`,
+//! compiled in with the `metrics` feature.
+
+#[cfg(feature = "metrics")]
+mod server {
+    use crate::host::{Host, HostStatus};
+    use std::fmt::Write;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::RwLock;
+    use tiny_http::{Header, Method, Response, Server};
+
+    /// Only render snapshots once something can scrape them.
+    static SERVING: AtomicBool = AtomicBool::new(false);
+    static SNAPSHOT: RwLock<String> = RwLock::new(String::new());
+
+    /// Serve `/metrics` on `bind:port` from a background thread.
+    pub fn serve(bind: &str, port: u16) -> Result<(), String> {
+        let server = Server::http((bind, port))
+            .map_err(|e| format!("can't listen on {}:{}: {}", bind, port, e))?;
+        SERVING.store(true, Ordering::Relaxed);
+        std::thread::spawn(move || {
+            let content_type =
+                Header::from_bytes("Content-Type", "text/plain; version=0.0.4").unwrap();
+            for request in server.incoming_requests() {
+                let path = request.url().split('?').next().unwrap_or_default();
+                let response = if *request.method() == Method::Get && path == "/metrics" {
+                    let body = SNAPSHOT.read().unwrap().clone();
+                    Response::from_string(body).with_header(content_type.clone())
+                } else {
+                    Response::from_string("not found\n").with_status_code(404)
+                };
+                if let Err(e) = request.respond(response) {
+                    tracing::debug!("metrics response failed: {}", e);
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Refresh what `/metrics` reports; called after each round of checks.
+    pub fn update(hosts: &[Host]) {
+        if SERVING.load(Ordering::Relaxed) {
+            *SNAPSHOT.write().unwrap() = render(hosts);
+        }
+    }
+
+    fn render(hosts: &[Host]) -> String {
+        let mut out = String::new();
+        let labels = |host: &Host| {
+            format!("alias=\"{}\",group=\"{}\"", escape(&host.alias), escape(&host.group))
+        };
+
+        out.push_str("# HELP sshmap_host_up Whether the last health check reached the host.\n");
+        out.push_str("# TYPE sshmap_host_up gauge\n");
+        for host in hosts {
+            let up = u8::from(host.status.is_up());
+            let _ = writeln!(out, "sshmap_host_up{{{}}} {}", labels(host), up);
+        }
+
+        out.push_str("# HELP sshmap_host_rtt_ms Round-trip time of the last health check.\n");
+        out.push_str("# TYPE sshmap_host_rtt_ms gauge\n");
+        for host in hosts {
+            if let Some(rtt) = host.status.rtt() {
+                let _ = writeln!(out, "sshmap_host_rtt_ms{{{}}} {}", labels(host), rtt);
+            }
+        }
+
+        let (mut up, mut down, mut unknown) = (0, 0, 0);
+        for host in hosts {
+            match host.status {
+                HostStatus::Unknown | HostStatus::Checking => unknown += 1,
+                ref status if status.is_up() => up += 1,
+                _ => down += 1,
+            }
+        }
+        out.push_str("# HELP sshmap_hosts_total Hosts by the result of their last check.\n");
+        out.push_str("# TYPE sshmap_hosts_total gauge\n");
+        for (status, count) in [("up", up), ("down", down), ("unknown", unknown)] {
+            let _ = writeln!(out, "sshmap_hosts_total{{status=\"{}\"}} {}", status, count);
+        }
+        out
+    }
+
+    fn escape(value: &str) -> String {
+        value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::test_support::host;
+
+        #[test]
+        fn exposition_lists_each_host_with_escaped_labels() {
+            let web = Host {
+                status: HostStatus::up(12.5),
+                ..host("web", "prod \"eu\"\\a\nb")
+            };
+            let db = Host { status: HostStatus::Down, ..host("db", "prod") };
+            let idle = host("idle", "prod");
+
+            let expected = "\
+# HELP sshmap_host_up Whether the last health check reached the host.
+# TYPE sshmap_host_up gauge
+sshmap_host_up{alias=\"web\",group=\"prod \\\"eu\\\"\\\\a\\nb\"} 1
+sshmap_host_up{alias=\"db\",group=\"prod\"} 0
+sshmap_host_up{alias=\"idle\",group=\"prod\"} 0
+# HELP sshmap_host_rtt_ms Round-trip time of the last health check.
+# TYPE sshmap_host_rtt_ms gauge
+sshmap_host_rtt_ms{alias=\"web\",group=\"prod \\\"eu\\\"\\\\a\\nb\"} 12.5
+# HELP sshmap_hosts_total Hosts by the result of their last check.
+# TYPE sshmap_hosts_total gauge
+sshmap_hosts_total{status=\"up\"} 1
+sshmap_hosts_total{status=\"down\"} 1
+sshmap_hosts_total{status=\"unknown\"} 1
+";
+            assert_eq!(render(&[web, db, idle]), expected);
+        }
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub use server::{serve, update};
+
+#[cfg(not(feature = "metrics"))]
+pub fn serve(_bind: &str, _port: u16) -> Result<(), String> {
+    Err("built without metrics support".into())
+}
+
+#[cfg(not(feature = "metrics"))]
+pub fn update(_hosts: &[crate::host::Host]) {}
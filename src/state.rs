@@ -0,0 +1,177 @@
+use crate::app::{App, ColumnId};
+use crate::host::{sshmap_config_dir, RTT_HISTORY_LEN};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::PathBuf;
+
+/// UI state carried across restarts in `state.json`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppState {
+    pub last_selected_alias: Option<String>,
+    pub show_groups: bool,
+    pub filter: String,
+    pub visible_columns: Vec<ColumnId>,
+    /// RTT trend per host alias, so sparklines survive restarts.
+    pub rtt_history: HashMap<String, VecDeque<f64>>,
+    /// Connection stats per host alias. Hosts from ~/.ssh/config have
+    /// nowhere else to keep them.
+    pub connection_stats: HashMap<String, ConnectionStats>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionStats {
+    pub last_connected: Option<DateTime<Utc>>,
+    pub connection_count: u32,
+}
+
+impl Default for AppState {
+    fn default() -> Self {
+        Self {
+            last_selected_alias: None,
+            show_groups: true,
+            filter: String::new(),
+            visible_columns: ColumnId::DEFAULT.to_vec(),
+            rtt_history: HashMap::new(),
+            connection_stats: HashMap::new(),
+        }
+    }
+}
+
+impl AppState {
+    fn path() -> PathBuf {
+        sshmap_config_dir().join("state.json")
+    }
+
+    /// A missing file is a fresh start, not an error.
+    pub fn load() -> anyhow::Result<AppState> {
+        let path = Self::path();
+        if !path.exists() {
+            return Ok(AppState::default());
+        }
+        let content = fs::read_to_string(&path)?;
+        serde_json::from_str(&content).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn capture(app: &App) -> AppState {
+        let last_selected_alias = app
+            .selected_host_index()
+            .map(|idx| app.hosts.lock().unwrap()[idx].alias.clone());
+        let hosts = app.hosts.lock().unwrap();
+        let rtt_history = hosts
+            .iter()
+            .filter(|h| !h.rtt_history.is_empty())
+            .map(|h| (h.alias.clone(), h.rtt_history.clone()))
+            .collect();
+        let connection_stats = hosts
+            .iter()
+            .filter(|h| h.connection_count > 0 || h.last_connected.is_some())
+            .map(|h| {
+                let stats = ConnectionStats {
+                    last_connected: h.last_connected,
+                    connection_count: h.connection_count,
+                };
+                (h.alias.clone(), stats)
+            })
+            .collect();
+        AppState {
+            last_selected_alias,
+            show_groups: app.show_groups,
+            filter: app.filter.clone(),
+            visible_columns: app.visible_columns.clone(),
+            rtt_history,
+            connection_stats,
+        }
+    }
+
+    /// Put `app` back where it was; an alias that's gone or filtered out
+    /// leaves the cursor at the top.
+    pub fn restore(mut self, app: &mut App) {
+        app.show_groups = self.show_groups;
+        app.visible_columns = self.visible_columns;
+        // A hand-edited file may list them out of order or twice
+        app.visible_columns.sort();
+        app.visible_columns.dedup();
+        // --filter/--group on the command line win over the saved session
+        let startup_filter = app.filter_mode;
+        if !startup_filter {
+            app.filter = self.filter;
+        }
+        for host in app.hosts.lock().unwrap().iter_mut() {
+            if let Some(mut history) = self.rtt_history.remove(&host.alias) {
+                let excess = history.len().saturating_sub(RTT_HISTORY_LEN);
+                history.drain(..excess);
+                host.rtt_history = history;
+            }
+            // sshmap's own hosts have these in their file too; keep the newer
+            if let Some(stats) = self.connection_stats.remove(&host.alias) {
+                host.last_connected = host.last_connected.max(stats.last_connected);
+                host.connection_count = host.connection_count.max(stats.connection_count);
+            }
+        }
+
+        // `--filter web` most likely means the host called web
+        let filter = app.filter.clone();
+        if startup_filter && app.select_by_alias(&filter) {
+            return;
+        }
+        let Some(alias) = self.last_selected_alias else { return };
+        if !app.select_by_alias(&alias) {
+            app.selected = 0;
+        }
+    }
+
+    /// Open the TUI on `alias` next time, after connecting to it from the
+    /// command line.
+    pub fn select_next_time(alias: &str) -> anyhow::Result<()> {
+        let mut state = AppState::load()?;
+        state.last_selected_alias = Some(alias.to_string());
+        state.save()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host::{Host, HostSource};
+    use crate::test_support::{app_with, host};
+
+    #[test]
+    fn connection_stats_of_ssh_config_hosts_survive_a_restart() {
+        let from_ssh_config = Host { source: HostSource::SshConfig, ..host("web", "prod") };
+        let mut app = app_with(vec![from_ssh_config.clone()]);
+        app.mark_connected(0);
+        app.mark_connected(0);
+        let connected = app.hosts.lock().unwrap()[0].last_connected;
+
+        let mut restarted = app_with(vec![from_ssh_config]);
+        AppState::load().unwrap().restore(&mut restarted);
+        let hosts = restarted.hosts.lock().unwrap();
+        assert_eq!(hosts[0].connection_count, 2);
+        assert_eq!(hosts[0].last_connected, connected);
+    }
+
+    #[test]
+    fn a_startup_filter_naming_a_host_selects_it() {
+        crate::test_support::isolated_home();
+        let hosts = vec![host("web", "prod"), host("db", "prod"), host("db-replica", "prod")];
+        let mut app = App::new(hosts, Default::default(), Some("db".into()), None);
+        let saved = AppState { last_selected_alias: Some("web".into()), ..AppState::default() };
+
+        saved.restore(&mut app);
+        let idx = app.selected_host_index().unwrap();
+        assert_eq!(app.hosts.lock().unwrap()[idx].alias, "db");
+    }
+}
@@ -0,0 +1,33 @@
+//! Helpers shared by the unit tests.
+
+use crate::app::App;
+use crate::config::Config;
+use crate::host::Host;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Point `HOME` at a scratch directory for the whole test run, so nothing a
+/// test loads or saves touches the real `~/.ssh` or `~/.config/sshmap`.
+pub fn isolated_home() -> &'static PathBuf {
+    static HOME: OnceLock<PathBuf> = OnceLock::new();
+    HOME.get_or_init(|| {
+        let dir = tempfile::tempdir().unwrap().keep();
+        std::env::set_var("HOME", &dir);
+        dir
+    })
+}
+
+/// An `App` over `hosts` with the default settings.
+pub fn app_with(hosts: Vec<Host>) -> App {
+    isolated_home();
+    App::new(hosts, Config::default(), None, None)
+}
+
+pub fn host(alias: &str, group: &str) -> Host {
+    Host {
+        alias: alias.into(),
+        hostname: format!("{}.example", alias),
+        group: group.into(),
+        ..Host::default()
+    }
+}
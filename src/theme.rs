@@ -0,0 +1,76 @@
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+
+/// Colors used by the TUI, read from the `[theme]` table of `config.toml`.
+/// Values are ratatui color names (`"cyan"`, `"light-red"`) or hex (`"#ff8800"`).
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub title_fg: Color,
+    pub header_fg: Color,
+    pub text_fg: Color,
+    pub muted_fg: Color,
+    pub border_fg: Color,
+    pub selected_bg: Color,
+    pub accent_fg: Color,
+    pub key_fg: Color,
+    pub user_fg: Color,
+    pub command_fg: Color,
+    pub description_fg: Color,
+    pub error_fg: Color,
+    pub up_fg: Color,
+    pub tcp_open_fg: Color,
+    pub down_fg: Color,
+    pub timeout_fg: Color,
+    pub dns_error_fg: Color,
+    pub checking_fg: Color,
+    pub unknown_fg: Color,
+    pub production_fg: Color,
+    pub staging_fg: Color,
+    pub dev_fg: Color,
+    pub test_fg: Color,
+    pub group_fg: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            title_fg: Color::Cyan,
+            header_fg: Color::Cyan,
+            text_fg: Color::White,
+            muted_fg: Color::DarkGray,
+            border_fg: Color::DarkGray,
+            selected_bg: Color::DarkGray,
+            accent_fg: Color::Yellow,
+            key_fg: Color::Yellow,
+            user_fg: Color::Cyan,
+            command_fg: Color::Green,
+            description_fg: Color::Gray,
+            error_fg: Color::Red,
+            up_fg: Color::Green,
+            tcp_open_fg: Color::Rgb(255, 191, 0),
+            down_fg: Color::Red,
+            timeout_fg: Color::Yellow,
+            dns_error_fg: Color::Rgb(255, 165, 0),
+            checking_fg: Color::Yellow,
+            unknown_fg: Color::DarkGray,
+            production_fg: Color::Red,
+            staging_fg: Color::Yellow,
+            dev_fg: Color::Green,
+            test_fg: Color::Cyan,
+            group_fg: Color::Magenta,
+        }
+    }
+}
+
+impl Theme {
+    pub fn group_color(&self, group: &str) -> Color {
+        match group.to_lowercase().as_str() {
+            "production" | "prod" => self.production_fg,
+            "staging" | "stage" => self.staging_fg,
+            "dev" | "development" => self.dev_fg,
+            "test" | "testing" => self.test_fg,
+            _ => self.group_fg,
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use std::process::{Child, Command, Stdio};
+use std::time::Instant;
+
+/// A background `ssh -N -L` process started from the TUI.
+pub struct TunnelInfo {
+    pub alias: String,
+    pub mapping: String,
+    pub child: Child,
+    pub started: Instant,
+}
+
+impl TunnelInfo {
+    pub fn kill(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Start forwarding `mapping` (`local_port:remote_host:remote_port`) through
+/// the host reached by `ssh_cmd`.
+pub fn open(alias: &str, ssh_cmd: &[String], mapping: &str) -> Result<TunnelInfo, String> {
+    validate_mapping(mapping)?;
+
+    // No terminal to prompt on, and fail fast if the local port is taken
+    let child = Command::new(&ssh_cmd[0])
+        .args(["-N", "-L", mapping])
+        .args(["-o", "BatchMode=yes", "-o", "ExitOnForwardFailure=yes"])
+        .args(&ssh_cmd[1..])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("failed to launch ssh: {}", e))?;
+
+    Ok(TunnelInfo {
+        alias: alias.to_string(),
+        mapping: mapping.to_string(),
+        child,
+        started: Instant::now(),
+    })
+}
+
+fn validate_mapping(mapping: &str) -> Result<(), String> {
+    let parts: Vec<&str> = mapping.split(':').collect();
+    let valid = match parts.as_slice() {
+        [local, host, remote] => {
+            local.parse::<u16>().is_ok_and(|p| p > 0)
+                && !host.is_empty()
+                && remote.parse::<u16>().is_ok_and(|p| p > 0)
+        }
+        _ => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(format!(
+            "'{}' is not local_port:remote_host:remote_port",
+            mapping
+        ))
+    }
+}
@@ -1,83 +1,241 @@
-use crate::app::App;
-use crate::host::HostStatus;
+use crate::app::{
+    App, ColumnId, Density, FilterKind, ListEntry, Mode, SortField, SortOrder, ToastLevel,
+};
+use crate::batch::BatchResults;
+use crate::form::HostForm;
+use crate::groups::{GroupAction, GroupPanel};
+use crate::config::Config;
+use crate::host::{self, Host, HostStatus, RTT_HISTORY_LEN};
+use crate::keys::Action;
+use crate::theme::Theme;
+use chrono::{Local, Utc};
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
 use ratatui::{
     prelude::*,
-    widgets::{Block, Borders, Cell, Paragraph, Row, Table},
+    widgets::{
+        Block, Borders, Cell, Clear, Gauge, Paragraph, Row, Scrollbar, ScrollbarOrientation,
+        ScrollbarState, Table,
+    },
 };
 
 pub fn render(f: &mut Frame, app: &mut App) {
     let area = f.size();
 
+    // Room for extra lines for the selected host's jump host, forwards and description
+    let detail_height = match app.selected_host_index() {
+        Some(idx) => {
+            let hosts = app.hosts.lock().unwrap();
+            let host = &hosts[idx];
+            3 + host.proxy_jump.is_some() as u16
+                + !host.local_forwards.is_empty() as u16
+                + host.description.is_some() as u16
+        }
+        None => 3,
+    };
+
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
             Constraint::Length(3), // header
             Constraint::Min(5),   // host table
-            Constraint::Length(3), // detail
+            Constraint::Length(detail_height), // detail
             Constraint::Length(2), // footer/help
         ])
         .split(area);
 
-    render_header(f, app, chunks[0]);
-    render_host_table(f, app, chunks[1]);
-    render_detail(f, app, chunks[2]);
-    render_footer(f, app, chunks[3]);
+    let theme = app.config.theme;
+    render_header(f, app, &theme, chunks[0]);
+    if matches!(app.mode, Mode::Dashboard) {
+        // Nothing clickable underneath while the dashboard covers the table
+        app.table_rows.clear();
+        let body = chunks[1].union(chunks[2]);
+        render_stats_dashboard(f, app, &theme, body);
+    } else {
+        render_host_table(f, app, &theme, chunks[1]);
+        render_detail(f, app, &theme, chunks[2]);
+    }
+    match app.mode {
+        Mode::BatchPrompt(ref input) => render_batch_prompt(f, app, input, &theme, chunks[3]),
+        Mode::Command(ref command) => render_command_line(f, &command.input, &theme, chunks[3]),
+        _ => render_footer(f, app, &theme, chunks[3]),
+    }
+
+    match app.mode {
+        Mode::Normal | Mode::Dashboard => {}
+        Mode::Edit(ref form) | Mode::Add(ref form) => render_edit_form(f, form, &theme, area),
+        Mode::QuitConfirm => render_quit_confirmation(f, app, &theme, area),
+        Mode::Delete(idx) => {
+            let alias = app.hosts.lock().unwrap()[idx].alias.clone();
+            let prompt = format!("Delete '{}'? [y/N]", alias);
+            render_confirmation_dialog(f, &prompt, &theme, area);
+        }
+        Mode::BatchPrompt(_) | Mode::Command(_) => {}
+        Mode::TunnelPrompt { index, ref input, ref error } => {
+            let alias = app.hosts.lock().unwrap()[index].alias.clone();
+            render_tunnel_prompt(f, &alias, input, error.as_deref(), &theme, area);
+        }
+        Mode::TunnelList { selected } => render_tunnel_list(f, app, selected, &theme, area),
+        Mode::ColumnPicker { selected, ref columns } => {
+            render_column_picker(f, selected, columns, &theme, area)
+        }
+        Mode::History { selected } => render_history(f, app, selected, &theme, area),
+        Mode::Recent => render_recent(f, app, &theme, area),
+        Mode::Help { scroll } => render_help_overlay(f, app, scroll, &theme, area),
+        Mode::HostDetail(idx) => render_host_detail_popup(f, app, idx, &theme, area),
+        Mode::GroupManage(ref panel) => render_group_panel(f, app, panel, &theme, chunks[1]),
+        Mode::HealthHistory { index, scroll } => {
+            render_health_history(f, app, index, scroll, &theme, area)
+        }
+        Mode::ConnectChoice(idx) => {
+            let alias = app.hosts.lock().unwrap()[idx].alias.clone();
+            let prompt = format!(
+                "Open '{}' in [c]urrent terminal, new tmux [w]indow, or [m]osh?",
+                alias
+            );
+            render_confirmation_dialog(f, &prompt, &theme, area);
+        }
+        Mode::PreflightFailed { index, ref error } => {
+            let (alias, target) = {
+                let hosts = app.hosts.lock().unwrap();
+                let host = &hosts[index];
+                (host.alias.clone(), format!("{}:{}", host.hostname, host.port))
+            };
+            let prompt =
+                format!("Can't reach {} ({}): {}. Connect anyway? [y/N]", alias, target, error);
+            render_confirmation_dialog(f, &prompt, &theme, area);
+        }
+        Mode::BatchOutput { ref results, scroll } => {
+            render_batch_output(f, results, scroll, &theme, area)
+        }
+    }
 }
 
-fn render_header(f: &mut Frame, app: &App, area: Rect) {
+fn render_header(f: &mut Frame, app: &App, theme: &Theme, area: Rect) {
     let hosts = app.hosts.lock().unwrap();
     let total = hosts.len();
     let up = hosts
         .iter()
-        .filter(|h| matches!(h.status, HostStatus::Up(_)))
+        .filter(|h| h.status.is_up())
         .count();
     let down = hosts
         .iter()
-        .filter(|h| matches!(h.status, HostStatus::Down))
+        .filter(|h| {
+            matches!(h.status, HostStatus::Down | HostStatus::Timeout | HostStatus::TlsExpired)
+        })
+        .count();
+    let unresolved = hosts
+        .iter()
+        .filter(|h| matches!(h.status, HostStatus::DnsError))
         .count();
     drop(hosts);
 
     let mut spans = vec![
-        Span::styled(" sshmap ", Style::default().fg(Color::Cyan).bold()),
+        Span::styled(" sshmap ", Style::default().fg(theme.title_fg).bold()),
         Span::raw("│ "),
-        Span::styled(format!("{} hosts", total), Style::default().fg(Color::White)),
+        Span::styled(format!("{} hosts", total), Style::default().fg(theme.text_fg)),
         Span::raw("  "),
-        Span::styled(format!("▲{}", up), Style::default().fg(Color::Green)),
+        Span::styled(format!("▲{}", up), Style::default().fg(theme.up_fg)),
         Span::raw(" "),
-        Span::styled(format!("▼{}", down), Style::default().fg(Color::Red)),
+        Span::styled(format!("▼{}", down), Style::default().fg(theme.down_fg)),
     ];
 
+    if unresolved > 0 {
+        spans.push(Span::raw(" "));
+        spans.push(Span::styled(
+            format!("?{}", unresolved),
+            Style::default().fg(theme.dns_error_fg),
+        ));
+    }
+
+    if !app.active_tunnels.is_empty() {
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(
+            format!("⇄{}", app.active_tunnels.len()),
+            Style::default().fg(theme.accent_fg),
+        ));
+    }
+
+    if let Some(when) = *app.last_check.lock().unwrap() {
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled(
+            format!("checked {}", when.format("%H:%M:%S")),
+            Style::default().fg(theme.muted_fg),
+        ));
+    }
+
+    if app.show_favorites_only {
+        spans.push(Span::raw("  "));
+        spans.push(Span::styled("★ Favorites", Style::default().fg(theme.accent_fg)));
+    }
+
+    if let Some(ref group) = app.group_filter {
+        spans.push(Span::raw("  │ "));
+        spans.push(Span::styled("group: ", Style::default().fg(theme.accent_fg)));
+        spans.push(Span::styled(group, Style::default().fg(theme.text_fg).bold()));
+    }
+
     if app.filter_mode || !app.filter.is_empty() {
         spans.push(Span::raw("  │ "));
-        spans.push(Span::styled("filter: ", Style::default().fg(Color::Yellow)));
+        let label = match app.filter_kind {
+            FilterKind::Substring => "filter: ",
+            FilterKind::Fuzzy => "fuzzy: ",
+            FilterKind::Regex => "regex: ",
+        };
+        spans.push(Span::styled(label, Style::default().fg(theme.accent_fg)));
         spans.push(Span::styled(
             &app.filter,
-            Style::default().fg(Color::White).bold(),
+            Style::default().fg(theme.text_fg).bold(),
         ));
         if app.filter_mode {
-            spans.push(Span::styled("▌", Style::default().fg(Color::Yellow)));
+            spans.push(Span::styled("▌", Style::default().fg(theme.accent_fg)));
+        }
+        if let Some(err) = app.filter_error() {
+            spans.push(Span::raw("  "));
+            spans.push(Span::styled(err, Style::default().fg(theme.error_fg)));
         }
     }
 
-    if let Some(ref msg) = app.message {
+    if let Some(toast) = app.toasts.back() {
+        let color = match toast.level {
+            ToastLevel::Info => theme.accent_fg,
+            ToastLevel::Success => theme.up_fg,
+            ToastLevel::Warning => theme.timeout_fg,
+            ToastLevel::Error => theme.error_fg,
+        };
         spans.push(Span::raw("  │ "));
-        spans.push(Span::styled(msg.as_str(), Style::default().fg(Color::Yellow)));
+        spans.push(Span::styled(toast.message.as_str(), Style::default().fg(color)));
     }
 
     let header = Paragraph::new(Line::from(spans)).block(
         Block::default()
             .borders(Borders::BOTTOM)
-            .border_style(Style::default().fg(Color::DarkGray)),
+            .border_style(Style::default().fg(theme.border_fg)),
     );
     f.render_widget(header, area);
 }
 
-fn render_host_table(f: &mut Frame, app: &mut App, area: Rect) {
+fn render_host_table(f: &mut Frame, app: &mut App, theme: &Theme, area: Rect) {
+    // Last column holds the scrollbar
+    let [area, scrollbar_area] = Layout::horizontal([Constraint::Min(1), Constraint::Length(1)])
+        .areas(area);
     let inner_height = area.height.saturating_sub(2) as usize;
-    let filtered = app.filtered_indices();
-    let total = filtered.len();
+    let entries = app.list_entries();
+    // Hosts in collapsed groups still count towards the title
+    let total: usize = if app.grouped() {
+        entries
+            .iter()
+            .map(|e| match e {
+                ListEntry::Group { count, .. } => *count,
+                ListEntry::Host(_) => 0,
+            })
+            .sum()
+    } else {
+        entries.len()
+    };
 
     // Adjust scroll
+    app.table_height = inner_height;
     if app.selected < app.scroll_offset {
         app.scroll_offset = app.selected;
     }
@@ -86,169 +244,1482 @@ fn render_host_table(f: &mut Frame, app: &mut App, area: Rect) {
     }
 
     let hosts = app.hosts.lock().unwrap();
+    let compact = app.display_density == Density::Compact;
+    // Wide adds its columns to the chosen ones, each as wide as its widest
+    // value, as long as they all fit; otherwise draw the normal table
+    let mut columns = app.visible_columns.clone();
+    let mut wide_widths = None;
+    let mut too_narrow = false;
+    if app.display_density == Density::Wide {
+        let mut wide_columns = columns.clone();
+        wide_columns.extend(WIDE_COLUMNS);
+        wide_columns.sort();
+        wide_columns.dedup();
+        let listed = entries.iter().filter_map(|e| match *e {
+            ListEntry::Host(idx) => Some(&hosts[idx]),
+            ListEntry::Group { .. } => None,
+        });
+        let widths = content_widths(&wide_columns, listed, true);
+        if table_width(&widths) <= area.width {
+            columns = wide_columns;
+            wide_widths = Some(widths);
+        } else {
+            too_narrow = true;
+        }
+    }
+    let wide = wide_widths.is_some();
 
-    let header = Row::new(vec![
-        Cell::from(" ").style(Style::default().fg(Color::Cyan).bold()),
-        Cell::from("Alias").style(Style::default().fg(Color::Cyan).bold()),
-        Cell::from("Host").style(Style::default().fg(Color::Cyan).bold()),
-        Cell::from("User").style(Style::default().fg(Color::Cyan).bold()),
-        Cell::from("Port").style(Style::default().fg(Color::Cyan).bold()),
-        Cell::from("Group").style(Style::default().fg(Color::Cyan).bold()),
-        Cell::from("Status").style(Style::default().fg(Color::Cyan).bold()),
-        Cell::from("RTT").style(Style::default().fg(Color::Cyan).bold()),
-    ])
-    .height(1);
+    let mut header_cells = vec![
+        Cell::from(" "),
+        Cell::from(" ").style(Style::default().fg(theme.header_fg).bold()),
+    ];
+    if compact {
+        header_cells.extend([
+            sort_header(app, theme, "Alias", SortField::Alias),
+            sort_header(app, theme, "Host", SortField::Hostname),
+            sort_header(app, theme, "Status", SortField::Status),
+        ]);
+    } else {
+        header_cells.extend(columns.iter().map(|&c| column_header(app, theme, c)));
+    }
+    let header = Row::new(header_cells).height(1);
 
-    let mut last_group = String::new();
     let mut rows: Vec<Row> = Vec::new();
 
-    for (display_idx, &real_idx) in filtered
+    // Rows start below the top border and the header row
+    let body_top = area.y + 2;
+    app.table_rows.clear();
+
+    for (display_idx, entry) in entries
         .iter()
         .enumerate()
         .skip(app.scroll_offset)
         .take(inner_height)
     {
-        let host = &hosts[real_idx];
         let is_selected = display_idx == app.selected;
+        app.table_rows.push((body_top + rows.len() as u16, display_idx));
 
-        // Group separator
-        if app.show_groups && host.group != last_group {
-            if !last_group.is_empty() {
-                rows.push(Row::new(vec![Cell::from("")]));
+        let real_idx = match *entry {
+            ListEntry::Host(idx) => idx,
+            ListEntry::Group { ref name, count, collapsed } => {
+                let text = if collapsed {
+                    format!("▶ {} ({})", name, count)
+                } else {
+                    format!("▼ {}", name)
+                };
+                let mut style = Style::default().fg(theme.group_color(name)).bold();
+                if is_selected {
+                    style = style.bg(theme.selected_bg);
+                }
+                rows.push(
+                    Row::new(vec![Cell::from(""), Cell::from(""), Cell::from(text)]).style(style),
+                );
+                continue;
             }
-            last_group = host.group.clone();
-        }
-
-        let status_icon = match &host.status {
-            HostStatus::Unknown => Span::styled("?", Style::default().fg(Color::DarkGray)),
-            HostStatus::Checking => Span::styled("◌", Style::default().fg(Color::Yellow)),
-            HostStatus::Up(_) => Span::styled("●", Style::default().fg(Color::Green)),
-            HostStatus::Down => Span::styled("●", Style::default().fg(Color::Red)),
         };
+        let host = &hosts[real_idx];
 
-        let (status_text, status_style) = match &host.status {
-            HostStatus::Unknown => ("—", Style::default().fg(Color::DarkGray)),
-            HostStatus::Checking => ("...", Style::default().fg(Color::Yellow)),
-            HostStatus::Up(_) => ("UP", Style::default().fg(Color::Green)),
-            HostStatus::Down => ("DOWN", Style::default().fg(Color::Red)),
+        let (status_text, status_color) = status_label(&host.status, theme);
+        let icon = match &host.status {
+            HostStatus::Unknown => Span::styled("?", Style::default().fg(status_color)),
+            HostStatus::Checking => Span::styled("◌", Style::default().fg(status_color)),
+            _ => Span::styled("●", Style::default().fg(status_color)),
         };
-
-        let rtt = host.rtt_label();
-
-        let group_color = group_color(&host.group);
+        let mark = if app.selection.contains(&real_idx) {
+            Span::styled("✓", Style::default().fg(theme.accent_fg).bold())
+        } else {
+            Span::raw(" ")
+        };
+        let status_icon = Line::from(vec![mark, icon]);
 
         let row_style = if is_selected {
-            Style::default().bg(Color::DarkGray)
+            Style::default().bg(theme.selected_bg)
         } else {
             Style::default()
         };
 
-        let port_str = if host.port != 22 {
-            format!("{}", host.port)
+        let star = if app.favorites.contains(&host.alias) {
+            Cell::from("★").style(Style::default().fg(theme.accent_fg))
         } else {
-            "22".to_string()
+            Cell::from(" ")
         };
 
-        rows.push(
-            Row::new(vec![
-                Cell::from(status_icon),
-                Cell::from(host.alias.clone()).style(Style::default().fg(Color::White).bold()),
-                Cell::from(host.hostname.clone()).style(Style::default().fg(Color::DarkGray)),
-                Cell::from(host.user.clone()).style(Style::default().fg(Color::Cyan)),
-                Cell::from(port_str),
-                Cell::from(host.group.clone()).style(Style::default().fg(group_color)),
-                Cell::from(status_text).style(status_style),
-                Cell::from(rtt).style(Style::default().fg(Color::DarkGray)),
-            ])
-            .style(row_style),
-        );
+        let mut cells = vec![star, Cell::from(status_icon)];
+        if compact {
+            cells.extend([
+                Cell::from(host.alias.clone()).style(Style::default().fg(theme.text_fg).bold()),
+                Cell::from(format!("{}:{}", host.hostname, host.port))
+                    .style(Style::default().fg(theme.muted_fg)),
+                Cell::from(status_text).style(Style::default().fg(status_color)),
+            ]);
+        } else {
+            cells.extend(columns.iter().map(|&c| column_cell(c, host, theme, wide)));
+        }
+        rows.push(Row::new(cells).style(row_style));
     }
 
-    let table = Table::new(
-        rows,
-        [
-            Constraint::Length(2),  // status icon
+    let mut widths = vec![
+        Constraint::Length(1), // favorite star
+        Constraint::Length(3), // selection mark + status icon
+    ];
+    if let Some(ref content) = wide_widths {
+        widths.extend(content.iter().map(|&w| Constraint::Min(w)));
+    } else if compact {
+        widths.extend([
             Constraint::Length(18), // alias
-            Constraint::Length(20), // hostname
-            Constraint::Length(12), // user
-            Constraint::Length(6),  // port
-            Constraint::Length(14), // group
+            Constraint::Length(26), // hostname:port
             Constraint::Length(6),  // status
-            Constraint::Length(8),  // rtt
-        ],
-    )
-    .header(header)
-    .block(
+        ]);
+    } else {
+        // A check result can widen the RTT or status column
+        let generation = host::status_generation();
+        let content = match app.last_computed_widths {
+            Some((width, ref measured, measured_at, ref widths))
+                if width == area.width && *measured == columns && measured_at == generation =>
+            {
+                widths.clone()
+            }
+            _ => {
+                let widths = compute_column_widths(&columns, &hosts, area.width);
+                app.last_computed_widths =
+                    Some((area.width, columns.clone(), generation, widths.clone()));
+                widths
+            }
+        };
+        widths.extend(content.into_iter().map(Constraint::Length));
+    }
+
+    // Without its column, last-connected has nowhere to carry the sort arrow
+    let last_conn_shown = !compact && columns.contains(&ColumnId::LastConn);
+    let mut title = if app.sort_field == SortField::LastConnected && !last_conn_shown {
+        let arrow = match app.sort_order {
+            SortOrder::Asc => "▲",
+            SortOrder::Desc => "▼",
+        };
+        format!(" {} hosts · sorted by last connected {} ", total, arrow)
+    } else {
+        format!(" {} hosts ", total)
+    };
+    if too_narrow {
+        title.push_str("· too narrow for the wide layout ");
+    }
+
+    let table = Table::new(rows, widths).header(header).block(
         Block::default()
-            .title(format!(" {} hosts ", total))
+            .title(title)
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::DarkGray)),
+            .border_style(Style::default().fg(theme.border_fg)),
     );
 
     f.render_widget(table, area);
+
+    if entries.is_empty() && (!app.filter.is_empty() || app.group_filter.is_some()) {
+        let what = match (&app.group_filter, app.filter.is_empty()) {
+            (Some(group), true) => format!("No hosts in group '{}'", group),
+            (Some(group), false) => format!("No hosts in '{}' match '{}'", group, app.filter),
+            (None, _) => format!("No hosts match '{}'", app.filter),
+        };
+        let message = Paragraph::new(vec![
+            Line::styled(what, Style::default().fg(theme.text_fg)),
+            Line::styled(
+                "Backspace edits the filter",
+                Style::default().fg(theme.muted_fg),
+            ),
+        ])
+        .alignment(Alignment::Center);
+        f.render_widget(message, inset_rect(area, 1, 3));
+    }
+
+    // Track alongside the table body, inside its borders
+    let mut scrollbar_state = ScrollbarState::new(entries.len().saturating_sub(inner_height))
+        .position(app.scroll_offset)
+        .viewport_content_length(inner_height);
+    f.render_stateful_widget(
+        Scrollbar::new(ScrollbarOrientation::VerticalRight)
+            .begin_symbol(None)
+            .end_symbol(None)
+            .style(Style::default().fg(theme.border_fg)),
+        inset_rect(scrollbar_area, 0, 1),
+        &mut scrollbar_state,
+    );
+}
+
+const JUMP_COLUMN_WIDTH: usize = 16;
+
+/// Columns the wide layout adds to the chosen ones.
+const WIDE_COLUMNS: [ColumnId; 4] =
+    [ColumnId::JumpHost, ColumnId::KeyFile, ColumnId::LastConn, ColumnId::Tags];
+
+fn column_header(app: &App, theme: &Theme, column: ColumnId) -> Cell<'static> {
+    match column.sort_field() {
+        Some(field) => sort_header(app, theme, column.label(), field),
+        None => Cell::from(column.label()).style(Style::default().fg(theme.header_fg).bold()),
+    }
+}
+
+/// Text of `column` for `host`. The wide layout has room for whole jump
+/// chains but keeps tags to the first two.
+fn column_text(column: ColumnId, host: &Host, wide: bool) -> String {
+    match column {
+        ColumnId::Alias => host.alias.clone(),
+        ColumnId::Host => host.hostname.clone(),
+        ColumnId::User => host.user.clone(),
+        ColumnId::Port => host.port.to_string(),
+        ColumnId::Group => host.group.clone(),
+        ColumnId::Status => host.status_label().to_string(),
+        ColumnId::Rtt => host.rtt_label(),
+        ColumnId::Trend => sparkline(&host.rtt_history),
+        ColumnId::JumpHost => {
+            let width = if wide { usize::MAX } else { JUMP_COLUMN_WIDTH };
+            jump_label(host.proxy_jump.as_deref(), width)
+        }
+        ColumnId::KeyFile => {
+            let mut key = host
+                .identity_files
+                .first()
+                .map(|k| k.rsplit('/').next().unwrap_or(k).to_string())
+                .unwrap_or_default();
+            if host.identity_files.len() > 1 {
+                key.push_str(&format!(" +{}", host.identity_files.len() - 1));
+            }
+            key
+        }
+        ColumnId::LastConn => host
+            .last_connected
+            .map(|t| t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default(),
+        ColumnId::Tags if wide => {
+            let mut tags = host.tags.iter().take(2).cloned().collect::<Vec<_>>().join(",");
+            if host.tags.len() > 2 {
+                tags.push_str(",…");
+            }
+            tags
+        }
+        ColumnId::Tags => host.tags.join(","),
+    }
+}
+
+fn column_cell(column: ColumnId, host: &Host, theme: &Theme, wide: bool) -> Cell<'static> {
+    let style = match column {
+        ColumnId::Alias => Style::default().fg(theme.text_fg).bold(),
+        ColumnId::User => Style::default().fg(theme.user_fg),
+        ColumnId::Port => Style::default(),
+        ColumnId::Group => Style::default().fg(theme.group_color(&host.group)),
+        ColumnId::Status => Style::default().fg(status_label(&host.status, theme).1),
+        ColumnId::Trend => Style::default().fg(theme.up_fg),
+        _ => Style::default().fg(theme.muted_fg),
+    };
+    Cell::from(column_text(column, host, wide)).style(style)
+}
+
+/// Narrowest `column` gets in the normal layout: room for its header and
+/// sort arrow, or for the values that change with every check.
+fn min_column_width(column: ColumnId) -> u16 {
+    match column {
+        ColumnId::Status => 6,
+        ColumnId::Rtt => 8,
+        ColumnId::Trend => RTT_HISTORY_LEN as u16,
+        ColumnId::LastConn => 16,
+        _ => column.label().chars().count() as u16 + 2,
+    }
+}
+
+/// Widest a column other than Alias and Host grows to fit its values.
+const MAX_FIXED_COLUMN_WIDTH: u16 = 24;
+
+/// Normal-layout widths of `columns` for a table `term_width` cells wide:
+/// each fits the longest value among all hosts plus a space, and Alias and
+/// Host share out what's left over, or give up room down to their minimum
+/// when there isn't enough.
+fn compute_column_widths(columns: &[ColumnId], hosts: &[Host], term_width: u16) -> Vec<u16> {
+    let is_flexible = |c: ColumnId| matches!(c, ColumnId::Alias | ColumnId::Host);
+    let mut widths: Vec<u16> = content_widths(columns, hosts.iter(), false)
+        .into_iter()
+        .zip(columns)
+        .map(|(w, &c)| {
+            let w = w.saturating_add(1).max(min_column_width(c));
+            if is_flexible(c) { w } else { w.min(MAX_FIXED_COLUMN_WIDTH) }
+        })
+        .collect();
+    let flexible: Vec<usize> = (0..columns.len()).filter(|&i| is_flexible(columns[i])).collect();
+    let available = term_width.saturating_sub(table_width(&[]) + columns.len() as u16);
+    let used: u16 = widths.iter().fold(0, |sum: u16, &w| sum.saturating_add(w));
+    let flexible_total: u32 = flexible.iter().map(|&i| widths[i] as u32).sum();
+    if flexible_total == 0 || used == available {
+        return widths;
+    }
+
+    if used < available {
+        let spare = (available - used) as u32;
+        for &i in &flexible {
+            widths[i] += (spare * widths[i] as u32 / flexible_total) as u16;
+        }
+    } else {
+        let excess = (used - available) as u32;
+        for &i in &flexible {
+            let cut = (excess * widths[i] as u32).div_ceil(flexible_total) as u16;
+            widths[i] = widths[i].saturating_sub(cut).max(min_column_width(columns[i]));
+        }
+    }
+    widths
+}
+
+/// Widest header or value of each of `columns` over `hosts`.
+fn content_widths<'a>(
+    columns: &[ColumnId],
+    hosts: impl Iterator<Item = &'a Host>,
+    wide: bool,
+) -> Vec<u16> {
+    // Room for the sort arrow
+    let mut widths: Vec<usize> = columns.iter().map(|c| c.label().chars().count() + 2).collect();
+    for host in hosts {
+        for (width, &column) in widths.iter_mut().zip(columns) {
+            *width = (*width).max(column_text(column, host, wide).chars().count());
+        }
+    }
+    widths.into_iter().map(|w| w.min(u16::MAX as usize) as u16).collect()
+}
+
+/// Columns plus the star, status icon, one space between columns and the borders.
+fn table_width(widths: &[u16]) -> u16 {
+    let columns = widths.len() as u16 + 2;
+    widths.iter().fold(1 + 3 + (columns - 1) + 2, |sum: u16, &w| sum.saturating_add(w))
+}
+
+/// A ProxyJump chain as `a→b→c`, cut to `width` with a trailing `…`.
+fn jump_label(proxy: Option<&str>, width: usize) -> String {
+    let chain = match proxy {
+        Some(p) => p.split(',').map(str::trim).collect::<Vec<_>>().join("→"),
+        None => return String::new(),
+    };
+    if chain.chars().count() <= width {
+        return chain;
+    }
+    let mut cut: String = chain.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// Status column text and color.
+fn status_label(status: &HostStatus, theme: &Theme) -> (&'static str, Color) {
+    let color = match status {
+        HostStatus::Unknown => theme.unknown_fg,
+        HostStatus::Checking => theme.checking_fg,
+        HostStatus::Up { .. } | HostStatus::TlsOk { .. } => theme.up_fg,
+        HostStatus::TcpOpen(_) => theme.tcp_open_fg,
+        HostStatus::Down | HostStatus::TlsExpired => theme.down_fg,
+        HostStatus::Timeout | HostStatus::TlsExpiringSoon { .. } => theme.timeout_fg,
+        HostStatus::DnsError => theme.dns_error_fg,
+    };
+    (status.label(), color)
+}
+
+/// RTT history as block characters, scaled to the host's own min/max.
+fn sparkline(history: &VecDeque<f64>) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let min = history.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = history.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let range = max - min;
+    history
+        .iter()
+        .map(|&rtt| {
+            if range <= 0.0 {
+                return BARS[0];
+            }
+            let level = ((rtt - min) / range * (BARS.len() - 1) as f64).round() as usize;
+            BARS[level.min(BARS.len() - 1)]
+        })
+        .collect()
+}
+
+fn sort_header(app: &App, theme: &Theme, label: &str, field: SortField) -> Cell<'static> {
+    let text = if app.sort_field == field {
+        let arrow = match app.sort_order {
+            SortOrder::Asc => "▲",
+            SortOrder::Desc => "▼",
+        };
+        format!("{} {}", label, arrow)
+    } else {
+        label.to_string()
+    };
+    Cell::from(text).style(Style::default().fg(theme.header_fg).bold())
 }
 
-fn render_detail(f: &mut Frame, app: &App, area: Rect) {
-    let filtered = app.filtered_indices();
+fn render_detail(f: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let selected = app.selected_host_index();
     let hosts = app.hosts.lock().unwrap();
 
-    let content = if let Some(&real_idx) = filtered.get(app.selected) {
+    let content = if let Some(real_idx) = selected {
         let host = &hosts[real_idx];
-        let cmd = host.ssh_command().join(" ");
-        Line::from(vec![
+        let cmd = host.ssh_command(false).join(" ");
+        let mut lines = vec![Line::from(vec![
             Span::raw(" → "),
-            Span::styled(cmd, Style::default().fg(Color::Green).bold()),
-            if let Some(ref key) = host.identity_file {
+            Span::styled(cmd, Style::default().fg(theme.command_fg).bold()),
+            if !host.identity_files.is_empty() {
                 Span::styled(
-                    format!("  │  key: {}", key),
-                    Style::default().fg(Color::DarkGray),
+                    format!("  │  key: {}", host.identity_files.join(", ")),
+                    Style::default().fg(theme.muted_fg),
                 )
             } else {
                 Span::raw("")
             },
-        ])
+            match host.status {
+                HostStatus::Up { loss, .. } => Span::styled(
+                    format!("  │  loss: {:.0}%", loss),
+                    Style::default().fg(if loss > 0.0 { theme.error_fg } else { theme.muted_fg }),
+                ),
+                _ => Span::raw(""),
+            },
+            match host.resolved_ip {
+                // An IP literal resolves to itself; only flag real lookups
+                Some(ip) if host.hostname.parse::<std::net::IpAddr>().is_err() => Span::styled(
+                    format!("  │  {} → {}", host.hostname, ip),
+                    Style::default().fg(theme.muted_fg),
+                ),
+                Some(ip) => Span::styled(
+                    format!("  │  ip: {}", ip),
+                    Style::default().fg(theme.muted_fg),
+                ),
+                None => Span::raw(""),
+            },
+            match host.uptime_24h() {
+                Some(pct) => Span::styled(
+                    format!("  │  uptime 24h: {:.0}%", pct),
+                    Style::default().fg(theme.muted_fg),
+                ),
+                None => Span::raw(""),
+            },
+            if host.environment.is_empty() {
+                Span::raw("")
+            } else {
+                Span::styled(
+                    format!("  │  +{} env vars", host.environment.len()),
+                    Style::default().fg(theme.muted_fg),
+                )
+            },
+            last_connected_span(host, theme),
+        ])];
+        if let Some(ref proxy) = host.proxy_jump {
+            lines.push(Line::from(Span::styled(
+                format!(" → via {}", proxy),
+                Style::default().fg(theme.muted_fg),
+            )));
+        }
+        if !host.local_forwards.is_empty() {
+            let forwards: Vec<String> = host.local_forwards.iter().map(|f| f.to_string()).collect();
+            lines.push(Line::from(Span::styled(
+                format!(" ⇄ forwards (Shift-Enter): {}", forwards.join(", ")),
+                Style::default().fg(theme.muted_fg),
+            )));
+        }
+        if let Some(ref desc) = host.description {
+            lines.push(Line::from(Span::styled(
+                format!("   {}", desc),
+                Style::default().fg(theme.description_fg),
+            )));
+        }
+        lines
     } else {
-        Line::from(Span::styled(
+        vec![Line::from(Span::styled(
             " No host selected",
-            Style::default().fg(Color::DarkGray),
-        ))
+            Style::default().fg(theme.muted_fg),
+        ))]
     };
 
     let detail = Paragraph::new(content).block(
         Block::default()
             .title(" Command ")
             .borders(Borders::ALL)
-            .border_style(Style::default().fg(Color::DarkGray)),
+            .border_style(Style::default().fg(theme.border_fg)),
     );
     f.render_widget(detail, area);
 }
 
-fn render_footer(f: &mut Frame, _app: &App, area: Rect) {
-    let help = Paragraph::new(Line::from(vec![
-        Span::styled(" ↑↓", Style::default().fg(Color::Yellow).bold()),
-        Span::raw(":Nav  "),
-        Span::styled("Enter", Style::default().fg(Color::Yellow).bold()),
-        Span::raw(":Connect  "),
-        Span::styled("/", Style::default().fg(Color::Yellow).bold()),
-        Span::raw(":Filter  "),
-        Span::styled("p", Style::default().fg(Color::Yellow).bold()),
-        Span::raw(":Ping  "),
-        Span::styled("P", Style::default().fg(Color::Yellow).bold()),
-        Span::raw(":PingAll  "),
-        Span::styled("g", Style::default().fg(Color::Yellow).bold()),
-        Span::raw(":Groups  "),
-        Span::styled("q", Style::default().fg(Color::Yellow).bold()),
-        Span::raw(":Quit"),
+/// When the host was last connected to, colored by how recent that was.
+fn last_connected_span(host: &Host, theme: &Theme) -> Span<'static> {
+    let when = match host.last_connected {
+        Some(when) => when,
+        None => {
+            return Span::styled("  │  never connected", Style::default().fg(theme.muted_fg));
+        }
+    };
+    let age = Utc::now().signed_duration_since(when);
+    let color = if age < chrono::Duration::hours(1) {
+        theme.up_fg
+    } else if age < chrono::Duration::days(1) {
+        theme.accent_fg
+    } else {
+        theme.muted_fg
+    };
+    Span::styled(
+        format!(
+            "  │  last: {} ({}×)",
+            when.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+            host.connection_count
+        ),
+        Style::default().fg(color),
+    )
+}
+
+fn render_edit_form(f: &mut Frame, form: &HostForm, theme: &Theme, area: Rect) {
+    let key_style = Style::default().fg(theme.key_fg).bold();
+    let mut lines: Vec<Line> = Vec::new();
+
+    for (i, field) in form.fields.iter().enumerate() {
+        let focused = i == form.focus;
+        let mut spans = vec![
+            Span::styled(
+                format!(" {:<14}", field.label),
+                Style::default().fg(if focused { theme.accent_fg } else { theme.muted_fg }),
+            ),
+            Span::styled(field.value.as_str(), Style::default().fg(theme.text_fg)),
+        ];
+        if focused {
+            spans.push(Span::styled("▌", Style::default().fg(theme.accent_fg)));
+            if !field.choices.is_empty() {
+                spans.push(Span::styled("  ←/→", Style::default().fg(theme.muted_fg)));
+            }
+        }
+        lines.push(Line::from(spans));
+    }
+
+    lines.push(Line::from(""));
+    match form.error {
+        // One line per validation problem
+        Some(ref err) => lines.extend(err.split("; ").map(|e| {
+            Line::from(Span::styled(format!(" ✗ {}", e), Style::default().fg(theme.error_fg)))
+        })),
+        None => lines.push(Line::from(vec![
+            Span::styled(" Tab", key_style),
+            Span::raw(":Next  "),
+            Span::styled("Enter", key_style),
+            Span::raw(":Save  "),
+            Span::styled("Esc", key_style),
+            Span::raw(":Cancel"),
+        ])),
+    }
+
+    let popup = centered_rect(60, lines.len() as u16 + 2, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(form.title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent_fg)),
+        ),
+        popup,
+    );
+}
+
+fn render_batch_prompt(f: &mut Frame, app: &App, input: &str, theme: &Theme, area: Rect) {
+    let prompt = Paragraph::new(Line::from(vec![
+        Span::styled(
+            format!(" run on {} hosts: ", app.selection.len()),
+            Style::default().fg(theme.accent_fg),
+        ),
+        Span::styled(input, Style::default().fg(theme.text_fg).bold()),
+        Span::styled("▌", Style::default().fg(theme.accent_fg)),
     ]));
+    f.render_widget(prompt, area);
+}
+
+/// The `:` jump prompt, in place of the footer.
+fn render_command_line(f: &mut Frame, input: &str, theme: &Theme, area: Rect) {
+    let prompt = Paragraph::new(Line::from(vec![
+        Span::styled(" :", Style::default().fg(theme.accent_fg)),
+        Span::styled(input, Style::default().fg(theme.text_fg).bold()),
+        Span::styled("▌", Style::default().fg(theme.accent_fg)),
+    ]));
+    f.render_widget(prompt, area);
+}
+
+fn render_batch_output(
+    f: &mut Frame,
+    results: &BatchResults,
+    scroll: usize,
+    theme: &Theme,
+    area: Rect,
+) {
+    let results = results.lock().unwrap();
+    let mut lines: Vec<Line> = Vec::new();
+    for r in results.iter() {
+        let (status, color) = match (&r.output, r.success) {
+            (None, _) => ("running...", theme.checking_fg),
+            (Some(_), true) => ("ok", theme.up_fg),
+            (Some(_), false) => ("failed", theme.down_fg),
+        };
+        lines.push(Line::from(vec![
+            Span::styled(format!(" [{}] ", r.alias), Style::default().fg(theme.text_fg).bold()),
+            Span::styled(status, Style::default().fg(color)),
+        ]));
+        if let Some(ref out) = r.output {
+            for l in out.lines() {
+                lines.push(Line::from(format!("   {}", l)));
+            }
+        }
+        lines.push(Line::from(""));
+    }
+    let done = results.iter().filter(|r| r.output.is_some()).count();
+    let title = format!(" Batch: {}/{} done  (q/Esc to close) ", done, results.len());
+    drop(results);
+
+    let popup = inset_rect(area, 4, 2);
+    let max_scroll = lines.len().saturating_sub(popup.height.saturating_sub(2) as usize);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines)
+            .scroll((scroll.min(max_scroll) as u16, 0))
+            .block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.accent_fg)),
+            ),
+        popup,
+    );
+}
+
+fn render_tunnel_prompt(
+    f: &mut Frame,
+    alias: &str,
+    input: &str,
+    error: Option<&str>,
+    theme: &Theme,
+    area: Rect,
+) {
+    let lines = vec![
+        Line::from(Span::styled(
+            " local_port:remote_host:remote_port",
+            Style::default().fg(theme.muted_fg),
+        )),
+        Line::from(vec![
+            Span::raw(" "),
+            Span::styled(input, Style::default().fg(theme.text_fg).bold()),
+            Span::styled("▌", Style::default().fg(theme.accent_fg)),
+        ]),
+        match error {
+            Some(err) => Line::from(Span::styled(
+                format!(" {}", err),
+                Style::default().fg(theme.error_fg),
+            )),
+            None => Line::from(""),
+        },
+    ];
+
+    let popup = centered_rect(60, 5, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(format!(" Tunnel via {} ", alias))
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent_fg)),
+        ),
+        popup,
+    );
+}
+
+fn render_tunnel_list(f: &mut Frame, app: &App, selected: usize, theme: &Theme, area: Rect) {
+    let rows: Vec<Row> = app
+        .active_tunnels
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            let secs = t.started.elapsed().as_secs();
+            let style = if i == selected {
+                Style::default().bg(theme.selected_bg)
+            } else {
+                Style::default()
+            };
+            Row::new(vec![
+                Cell::from(t.alias.clone()).style(Style::default().fg(theme.text_fg).bold()),
+                Cell::from(t.mapping.clone()).style(Style::default().fg(theme.command_fg)),
+                Cell::from(format!("{}m{:02}s", secs / 60, secs % 60))
+                    .style(Style::default().fg(theme.muted_fg)),
+            ])
+            .style(style)
+        })
+        .collect();
+
+    let title = if rows.is_empty() {
+        " No active tunnels (Esc to close) ".to_string()
+    } else {
+        format!(" {} tunnels  (d:Kill  Esc:Close) ", rows.len())
+    };
+
+    let popup = centered_rect(64, rows.len() as u16 + 2, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Table::new(
+            rows,
+            [Constraint::Length(18), Constraint::Min(24), Constraint::Length(10)],
+        )
+        .block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent_fg)),
+        ),
+        popup,
+    );
+}
+
+fn render_quit_confirmation(f: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let count = app.active_tunnels.len();
+    let prompt = format!(
+        "Kill {} active tunnel{} and quit? [y/N]",
+        count,
+        if count == 1 { "" } else { "s" }
+    );
+    render_confirmation_dialog(f, &prompt, theme, area);
+}
+
+fn render_column_picker(
+    f: &mut Frame,
+    selected: usize,
+    columns: &[ColumnId],
+    theme: &Theme,
+    area: Rect,
+) {
+    let lines: Vec<Line> = ColumnId::ALL
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let check = if columns.contains(column) { "[x]" } else { "[ ]" };
+            let style = if i == selected {
+                Style::default().fg(theme.text_fg).bg(theme.selected_bg)
+            } else {
+                Style::default().fg(theme.text_fg)
+            };
+            Line::styled(format!(" {} {}", check, column.label()), style)
+        })
+        .collect();
+
+    let popup = centered_rect(44, lines.len() as u16 + 2, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(" Columns  (Space:Toggle  Enter:Apply) ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent_fg)),
+        ),
+        popup,
+    );
+}
+
+fn render_history(f: &mut Frame, app: &App, selected: usize, theme: &Theme, area: Rect) {
+    let popup = inset_rect(area, 8, 3);
+    // Keep the selected entry in view
+    let visible = popup.height.saturating_sub(2) as usize;
+    let skip = (selected + 1).saturating_sub(visible);
+
+    let rows: Vec<Row> = app
+        .history
+        .iter()
+        .rev()
+        .enumerate()
+        .skip(skip)
+        .take(visible)
+        .map(|(i, entry)| {
+            let style = if i == selected {
+                Style::default().bg(theme.selected_bg)
+            } else {
+                Style::default()
+            };
+            let when = entry.connected_at.with_timezone(&Local).format("%Y-%m-%d %H:%M");
+            let duration = match entry.duration_secs {
+                Some(secs) => format!("{}m{:02}s", secs / 60, secs % 60),
+                None => "—".to_string(),
+            };
+            Row::new(vec![
+                Cell::from(when.to_string()).style(Style::default().fg(theme.muted_fg)),
+                Cell::from(entry.alias.clone()).style(Style::default().fg(theme.text_fg).bold()),
+                Cell::from(entry.hostname.clone()).style(Style::default().fg(theme.muted_fg)),
+                Cell::from(duration).style(Style::default().fg(theme.accent_fg)),
+            ])
+            .style(style)
+        })
+        .collect();
+
+    let title = if app.history.is_empty() {
+        " No connection history (Esc to close) ".to_string()
+    } else {
+        format!(" History: {} connections  (Enter:Reconnect  Esc:Close) ", app.history.len())
+    };
+
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Table::new(
+            rows,
+            [
+                Constraint::Length(17),
+                Constraint::Length(20),
+                Constraint::Min(20),
+                Constraint::Length(10),
+            ],
+        )
+        .block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent_fg)),
+        ),
+        popup,
+    );
+}
+
+fn render_health_history(
+    f: &mut Frame,
+    app: &App,
+    index: usize,
+    scroll: usize,
+    theme: &Theme,
+    area: Rect,
+) {
+    let hosts = app.hosts.lock().unwrap();
+    let host = &hosts[index];
+    let lines: Vec<Line> = host
+        .health_history
+        .iter()
+        .rev()
+        .map(|r| {
+            let secs = r.timestamp.elapsed().as_secs();
+            let (label, color) = status_label(&r.status, theme);
+            let rtt = r.rtt_ms.map(|ms| format!("{:.0}ms", ms)).unwrap_or_default();
+            Line::from(vec![
+                Span::styled(
+                    format!(" {:>4}m{:02}s ago  ", secs / 60, secs % 60),
+                    Style::default().fg(theme.muted_fg),
+                ),
+                Span::styled(format!("{:<6}", label), Style::default().fg(color)),
+                Span::styled(rtt, Style::default().fg(theme.text_fg)),
+            ])
+        })
+        .collect();
+    let title = format!(
+        " {}: {} checks  (↑↓ scroll, Esc to close) ",
+        host.alias,
+        host.health_history.len()
+    );
+    drop(hosts);
+
+    let popup = inset_rect(area, 12, 3);
+    let max_scroll = lines.len().saturating_sub(popup.height.saturating_sub(2) as usize);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines)
+            .scroll((scroll.min(max_scroll) as u16, 0))
+            .block(
+                Block::default()
+                    .title(title)
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.accent_fg)),
+            ),
+        popup,
+    );
+}
+
+/// Fleet overview: status counts, up/down ratios, RTT extremes, hosts
+/// gone quiet, and a per-group summary. Recomputed every frame.
+fn render_stats_dashboard(f: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let hosts = app.hosts.lock().unwrap();
+    let total = hosts.len();
+    let up = hosts.iter().filter(|h| h.status.is_up()).count();
+    let checking = hosts.iter().filter(|h| matches!(h.status, HostStatus::Checking)).count();
+    let unknown = hosts.iter().filter(|h| matches!(h.status, HostStatus::Unknown)).count();
+    let down = total - up - checking - unknown;
+
+    let mut rtts: Vec<(&str, f64)> = hosts
+        .iter()
+        .filter_map(|h| h.status.rtt().map(|rtt| (h.alias.as_str(), rtt)))
+        .collect();
+    rtts.sort_by(|a, b| a.1.total_cmp(&b.1));
+    let avg_rtt = if rtts.is_empty() {
+        None
+    } else {
+        Some(rtts.iter().map(|(_, rtt)| rtt).sum::<f64>() / rtts.len() as f64)
+    };
+    let hour = Duration::from_secs(60 * 60);
+    let stale: Vec<&str> = hosts
+        .iter()
+        .filter(|h| !h.up_within(hour))
+        .map(|h| h.alias.as_str())
+        .collect();
+
+    let [counts_area, gauges_area, rtt_area, groups_area] = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Length(3),
+        Constraint::Length(5),
+        Constraint::Min(3),
+    ])
+    .areas(area);
+
+    let counts = [
+        ("Hosts", total, theme.text_fg),
+        ("Up", up, theme.up_fg),
+        ("Down", down, theme.down_fg),
+        ("Checking", checking, theme.checking_fg),
+        ("Unknown", unknown, theme.unknown_fg),
+    ];
+    let cells = Layout::horizontal([Constraint::Ratio(1, counts.len() as u32); 5])
+        .split(counts_area);
+    for ((label, n, color), cell) in counts.iter().zip(cells.iter()) {
+        let lines = vec![
+            Line::from(Span::styled(n.to_string(), Style::default().fg(*color).bold())),
+            Line::from(Span::styled(*label, Style::default().fg(theme.muted_fg))),
+        ];
+        f.render_widget(
+            Paragraph::new(lines).alignment(Alignment::Center).block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(theme.border_fg)),
+            ),
+            *cell,
+        );
+    }
+
+    let ratio = |n: usize| if total == 0 { 0.0 } else { n as f64 / total as f64 };
+    let [up_gauge, down_gauge] =
+        Layout::horizontal([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)]).areas(gauges_area);
+    for (title, n, color, cell) in [
+        (" Up ", up, theme.up_fg, up_gauge),
+        (" Down ", down, theme.down_fg, down_gauge),
+    ] {
+        f.render_widget(
+            Gauge::default()
+                .block(
+                    Block::default()
+                        .title(title)
+                        .borders(Borders::ALL)
+                        .border_style(Style::default().fg(theme.border_fg)),
+                )
+                .gauge_style(Style::default().fg(color))
+                .ratio(ratio(n)),
+            cell,
+        );
+    }
+
+    let muted = Style::default().fg(theme.muted_fg);
+    let fmt_rtt = |entry: Option<&(&str, f64)>| match entry {
+        Some((alias, rtt)) => format!("{} ({:.0}ms)", alias, rtt),
+        None => "—".to_string(),
+    };
+    let stale_text = if stale.is_empty() {
+        "none".to_string()
+    } else {
+        format!("{}: {}", stale.len(), stale.join(", "))
+    };
+    let rtt_lines = vec![
+        Line::from(vec![
+            Span::styled(" Average RTT   ", muted),
+            Span::raw(avg_rtt.map_or("—".to_string(), |ms| format!("{:.1}ms", ms))),
+        ]),
+        Line::from(vec![Span::styled(" Fastest       ", muted), Span::raw(fmt_rtt(rtts.first()))]),
+        Line::from(vec![Span::styled(" Slowest       ", muted), Span::raw(fmt_rtt(rtts.last()))]),
+        Line::from(vec![
+            Span::styled(" Not up in 1h  ", muted),
+            Span::styled(stale_text, Style::default().fg(theme.error_fg)),
+        ]),
+    ];
+    f.render_widget(
+        Paragraph::new(rtt_lines).block(
+            Block::default()
+                .title(" Latency ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.border_fg)),
+        ),
+        rtt_area,
+    );
+
+    // group → (hosts, up, RTT sum, hosts with an RTT)
+    let mut groups: BTreeMap<&str, (usize, usize, f64, usize)> = BTreeMap::new();
+    for host in hosts.iter() {
+        let entry = groups.entry(host.group.as_str()).or_default();
+        entry.0 += 1;
+        if host.status.is_up() {
+            entry.1 += 1;
+        }
+        if let Some(rtt) = host.status.rtt() {
+            entry.2 += rtt;
+            entry.3 += 1;
+        }
+    }
+    let header_style = Style::default().fg(theme.header_fg).bold();
+    let header = Row::new(["Group", "Hosts", "Up", "Down", "Avg RTT"])
+        .style(header_style)
+        .height(1);
+    let rows: Vec<Row> = groups
+        .iter()
+        .map(|(name, &(n, up, rtt_sum, with_rtt))| {
+            let avg = if with_rtt == 0 {
+                "—".to_string()
+            } else {
+                format!("{:.0}ms", rtt_sum / with_rtt as f64)
+            };
+            Row::new(vec![
+                Cell::from(name.to_string()).style(Style::default().fg(theme.group_color(name))),
+                Cell::from(n.to_string()),
+                Cell::from(up.to_string()).style(Style::default().fg(theme.up_fg)),
+                Cell::from((n - up).to_string()).style(Style::default().fg(theme.down_fg)),
+                Cell::from(avg).style(muted),
+            ])
+        })
+        .collect();
+    let widths = [
+        Constraint::Length(20),
+        Constraint::Length(7),
+        Constraint::Length(5),
+        Constraint::Length(6),
+        Constraint::Length(8),
+    ];
+    f.render_widget(
+        Table::new(rows, widths).header(header).block(
+            Block::default()
+                .title(" Groups ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.border_fg)),
+        ),
+        groups_area,
+    );
+}
+
+/// `M` panel docked on the right of the host table.
+fn render_group_panel(f: &mut Frame, app: &App, panel: &GroupPanel, theme: &Theme, area: Rect) {
+    let muted = Style::default().fg(theme.muted_fg);
+    let hosts = app.hosts.lock().unwrap();
+    let mut lines: Vec<Line> = panel
+        .groups
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let count = hosts.iter().filter(|h| h.group == *name).count();
+            let mut style = Style::default().fg(theme.group_color(name));
+            if i == panel.selected {
+                style = style.bg(theme.selected_bg).bold();
+            }
+            Line::from(vec![
+                Span::styled(format!(" {:<22}", name), style),
+                Span::styled(format!("{:>4}", count), muted),
+            ])
+        })
+        .collect();
+    drop(hosts);
+    lines.push(Line::from(""));
+
+    let input_line = |label: &str, input: &str| {
+        Line::from(vec![
+            Span::styled(format!(" {}: ", label), muted),
+            Span::styled(input.to_string(), Style::default().fg(theme.text_fg).bold()),
+            Span::styled("▌", Style::default().fg(theme.accent_fg)),
+        ])
+    };
+    match &panel.action {
+        GroupAction::Browse => {
+            lines.push(Line::from(Span::styled(" a add  r rename  d delete", muted)));
+            lines.push(Line::from(Span::styled(" J/K or Shift-↑↓ move", muted)));
+        }
+        GroupAction::Add(input) => lines.push(input_line("New group", input)),
+        GroupAction::Rename(input) => lines.push(input_line("Rename to", input)),
+        GroupAction::Delete { targets, selected } => {
+            lines.push(Line::from(Span::styled(" Move its hosts to:", muted)));
+            for (i, target) in targets.iter().enumerate() {
+                let label = if target == host::DEFAULT_GROUP {
+                    format!("   {} (ungrouped)", target)
+                } else {
+                    format!("   {}", target)
+                };
+                let style = if i == *selected {
+                    Style::default().bg(theme.selected_bg).bold()
+                } else {
+                    Style::default()
+                };
+                lines.push(Line::from(Span::styled(label, style)));
+            }
+        }
+    }
+    if let Some(ref err) = panel.error {
+        let style = Style::default().fg(theme.error_fg);
+        lines.push(Line::from(Span::styled(format!(" {}", err), style)));
+    }
+
+    let width = 30.min(area.width);
+    let side = Rect::new(area.right() - width, area.y, width, area.height);
+    f.render_widget(Clear, side);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(" Groups ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent_fg)),
+        ),
+        side,
+    );
+}
+
+fn render_host_detail_popup(f: &mut Frame, app: &App, idx: usize, theme: &Theme, area: Rect) {
+    let hosts = app.hosts.lock().unwrap();
+    let host = &hosts[idx];
+    let (status_text, status_color) = status_label(&host.status, theme);
+    let or_dash = |value: Option<String>| value.unwrap_or_else(|| "—".to_string());
+    let forwards: Vec<String> = host.local_forwards.iter().map(|f| f.to_string()).collect();
+    let forwards = forwards.join(", ");
+    let environment = host::format_environment(&host.environment);
+    let ssh_options = host::format_ssh_options(&host.extra_ssh_options);
+
+    let fields: Vec<(&str, Span)> = vec![
+        ("Alias", Span::styled(host.alias.clone(), Style::default().fg(theme.text_fg).bold())),
+        ("Hostname", Span::raw(host.hostname.clone())),
+        ("Resolved IP", Span::raw(or_dash(host.resolved_ip.map(|ip| ip.to_string())))),
+        ("User", Span::styled(host.user.clone(), Style::default().fg(theme.user_fg))),
+        ("Port", Span::raw(host.port.to_string())),
+        (
+            "Identity files",
+            Span::raw(if host.identity_files.is_empty() {
+                "—".into()
+            } else {
+                host.identity_files.join(", ")
+            }),
+        ),
+        ("Proxy jump", Span::raw(or_dash(host.proxy_jump.clone()))),
+        ("Forwards", Span::raw(if forwards.is_empty() { "—".into() } else { forwards })),
+        ("Pre-connect", Span::raw(or_dash(host.pre_connect_command.clone()))),
+        ("Post-connect", Span::raw(or_dash(host.post_connect_command.clone()))),
+        ("Environment", Span::raw(if environment.is_empty() { "—".into() } else { environment })),
+        ("SSH options", Span::raw(if ssh_options.is_empty() { "—".into() } else { ssh_options })),
+        (
+            "Record",
+            Span::raw(match host.record_sessions {
+                Some(true) => "yes",
+                Some(false) => "no",
+                None => "default",
+            }),
+        ),
+        (
+            "Mosh",
+            Span::raw(match (host.use_mosh, host.mosh_server_path.as_deref()) {
+                (use_mosh, Some(server)) => {
+                    format!("{} (server: {})", if use_mosh { "yes" } else { "no" }, server)
+                }
+                (true, None) => "yes".into(),
+                (false, None) => "no".into(),
+            }),
+        ),
+        (
+            "Group",
+            Span::styled(host.group.clone(), Style::default().fg(theme.group_color(&host.group))),
+        ),
+        ("Tags", Span::raw(if host.tags.is_empty() { "—".into() } else { host.tags.join(", ") })),
+        (
+            "Description",
+            Span::styled(
+                or_dash(host.description.clone()),
+                Style::default().fg(theme.description_fg),
+            ),
+        ),
+        (
+            "Last connected",
+            Span::raw(or_dash(host.last_connected.map(|t| {
+                t.with_timezone(&Local).format("%Y-%m-%d %H:%M").to_string()
+            }))),
+        ),
+        ("Connections", Span::raw(host.connection_count.to_string())),
+        ("Source", Span::raw(host.source.label())),
+        (
+            "Config",
+            Span::raw(or_dash(host.ssh_config_source.as_deref().map(host::tilde_path))),
+        ),
+        ("Status", Span::styled(status_text, Style::default().fg(status_color))),
+        ("RTT", Span::raw(host.rtt_label())),
+        (
+            "RTT trend",
+            Span::styled(sparkline(&host.rtt_history), Style::default().fg(theme.up_fg)),
+        ),
+    ];
+    let lines: Vec<Line> = fields
+        .into_iter()
+        .map(|(label, value)| {
+            Line::from(vec![
+                Span::styled(format!(" {:<16}", label), Style::default().fg(theme.muted_fg)),
+                value,
+            ])
+        })
+        .collect();
+    let title = format!(" {}  (e to edit, Esc to close) ", host.alias);
+    drop(hosts);
+
+    let width = area.width.saturating_sub(4).min(80);
+    let popup = centered_rect(width, lines.len() as u16 + 2, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent_fg)),
+        ),
+        popup,
+    );
+}
+
+fn render_help_overlay(f: &mut Frame, app: &App, scroll: usize, theme: &Theme, area: Rect) {
+    let key_style = Style::default().fg(theme.key_fg).bold();
+    let muted = Style::default().fg(theme.muted_fg);
+
+    let lines = vec![
+        Line::from(vec![
+            Span::styled(" sshmap ", Style::default().fg(theme.title_fg).bold()),
+            Span::styled(env!("CARGO_PKG_VERSION"), muted),
+            Span::styled(
+                format!("  │  {} hosts loaded", app.hosts.lock().unwrap().len()),
+                muted,
+            ),
+        ]),
+        Line::from(vec![
+            Span::styled(" Config: ", muted),
+            Span::raw(Config::path().display().to_string()),
+        ]),
+        Line::from(vec![
+            Span::styled(" Hosts:  ", muted),
+            Span::raw(host::active_hosts_path().display().to_string()),
+        ]),
+        Line::from(""),
+    ];
+    let bindings: Vec<Line> = app
+        .config
+        .keybindings
+        .all()
+        .into_iter()
+        .map(|(action, keys)| {
+            Line::from(vec![
+                Span::styled(format!(" {:<16}", keys.join(" ")), key_style),
+                Span::styled(action.description(), Style::default().fg(theme.text_fg)),
+            ])
+        })
+        .collect();
+
+    let popup = inset_rect(area, 4, 1);
+    // The key list gets whatever the popup's borders and info lines leave
+    let keys_height = popup.height.saturating_sub(2 + lines.len() as u16).max(1) as usize;
+    let two_columns = bindings.len() > keys_height;
+    let rows = if two_columns { bindings.len().div_ceil(2) } else { bindings.len() };
+    let max_scroll = rows.saturating_sub(keys_height);
+    let title = if max_scroll > 0 {
+        " Help  (↑↓ PgUp PgDn scroll, any other key to close) "
+    } else {
+        " Help  (any key to close) "
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(theme.accent_fg));
+    let inner = block.inner(popup);
+    f.render_widget(Clear, popup);
+    f.render_widget(block, popup);
+
+    let [info_area, keys_area] =
+        Layout::vertical([Constraint::Length(lines.len() as u16), Constraint::Min(1)])
+            .areas(inner);
+    f.render_widget(Paragraph::new(lines), info_area);
+
+    let offset = (scroll.min(max_scroll) as u16, 0);
+    // Spill into a second column when the list doesn't fit, then scroll both
+    if two_columns {
+        let [left, right] =
+            Layout::horizontal([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)]).areas(keys_area);
+        let mut bindings = bindings;
+        let second = bindings.split_off(rows);
+        f.render_widget(Paragraph::new(bindings).scroll(offset), left);
+        f.render_widget(Paragraph::new(second).scroll(offset), right);
+    } else {
+        f.render_widget(Paragraph::new(bindings).scroll(offset), keys_area);
+    }
+}
+
+fn render_recent(f: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let hosts = app.hosts.lock().unwrap();
+    let lines: Vec<Line> = if app.recent_connections.is_empty() {
+        vec![Line::from(Span::styled(
+            " No recent connections",
+            Style::default().fg(theme.muted_fg),
+        ))]
+    } else {
+        app.recent_connections
+            .iter()
+            .enumerate()
+            .map(|(i, alias)| {
+                // Hosts removed since the connection can't be reached from here
+                let style = if hosts.iter().any(|h| h.alias == *alias) {
+                    Style::default().fg(theme.text_fg).bold()
+                } else {
+                    Style::default().fg(theme.muted_fg)
+                };
+                Line::from(vec![
+                    Span::styled(format!(" {} ", i + 1), Style::default().fg(theme.key_fg)),
+                    Span::styled(alias.clone(), style),
+                ])
+            })
+            .collect()
+    };
+
+    let popup = centered_rect(40, lines.len() as u16 + 2, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines).block(
+            Block::default()
+                .title(" Recent (1-5 to connect) ")
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.accent_fg)),
+        ),
+        popup,
+    );
+}
+
+/// `area` shrunk by `dx` columns and `dy` rows on every side.
+fn inset_rect(area: Rect, dx: u16, dy: u16) -> Rect {
+    let dx = dx.min(area.width / 4);
+    let dy = dy.min(area.height / 4);
+    Rect {
+        x: area.x + dx,
+        y: area.y + dy,
+        width: area.width - 2 * dx,
+        height: area.height - 2 * dy,
+    }
+}
+
+fn render_confirmation_dialog(f: &mut Frame, prompt: &str, theme: &Theme, area: Rect) {
+    let popup = centered_rect(prompt.chars().count() as u16 + 6, 3, area);
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(Line::from(Span::styled(
+            prompt,
+            Style::default().fg(theme.text_fg).bold(),
+        )))
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(theme.down_fg)),
+        ),
+        popup,
+    );
+}
+
+/// A `width` x `height` rect centred in `area`, clamped to fit.
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    }
+}
+
+fn render_footer(f: &mut Frame, app: &App, theme: &Theme, area: Rect) {
+    let bindings = &app.config.keybindings;
+    let key_style = Style::default().fg(theme.key_fg).bold();
+
+    let mut spans = vec![Span::raw(" ")];
+    // Up/down share one hint, e.g. "↑↓:Nav"
+    if let (Some(up), Some(down)) = (
+        bindings.key_for(Action::SelectUp),
+        bindings.key_for(Action::SelectDown),
+    ) {
+        spans.push(Span::styled(format!("{}{}", up, down), key_style));
+        spans.push(Span::raw(":Nav  "));
+    }
+
+    // With hosts marked, PingAll only pings those
+    let ping_all_label = if app.selection.is_empty() {
+        Action::PingAll.label()
+    } else {
+        "PingSelected"
+    };
+
+    let hints = [
+        Action::Connect,
+        Action::FilterEnter,
+        Action::SelectFirst,
+        Action::SelectLast,
+        Action::ToggleSelect,
+        Action::PingOne,
+        Action::PingAll,
+        Action::ToggleGroups,
+        Action::CycleSort,
+        Action::EditHost,
+        Action::AddHost,
+        Action::Help,
+        Action::Quit,
+    ];
+    for action in hints {
+        if let Some(mut key) = bindings.key_for(action) {
+            // Going to the top takes the key twice
+            if action == Action::SelectFirst {
+                key = key.repeat(2);
+            }
+            spans.push(Span::styled(key, key_style));
+            let label = match action {
+                Action::PingAll => ping_all_label,
+                _ => action.label(),
+            };
+            spans.push(Span::raw(format!(":{}  ", label)));
+        }
+    }
+
+    let help = Paragraph::new(Line::from(spans));
     f.render_widget(help, area);
 }
 
-fn group_color(group: &str) -> Color {
-    match group.to_lowercase().as_str() {
-        "production" | "prod" => Color::Red,
-        "staging" | "stage" => Color::Yellow,
-        "dev" | "development" => Color::Green,
-        "test" | "testing" => Color::Cyan,
-        _ => Color::Magenta,
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::host;
+
+    #[test]
+    fn column_widths_grow_shrink_and_cap() {
+        let columns = [ColumnId::Alias, ColumnId::Host, ColumnId::Port];
+        let hosts = [Host { port: 22, ..host("web", "") }];
+        // Alias 8 and Host 12 fit their values; Port gets its minimum
+        let fitted = [8, 12, 7];
+        let room = |widths: &[u16]| table_width(widths);
+        assert_eq!(compute_column_widths(&columns, &hosts, room(&fitted)), fitted);
+
+        // Spare room goes to Alias and Host in proportion
+        let wide = compute_column_widths(&columns, &hosts, room(&fitted) + 20);
+        assert_eq!(wide, [16, 24, 7]);
+
+        // Short of room, Alias and Host give way down to their minimums
+        let narrow = compute_column_widths(&columns, &hosts, 20);
+        let minimums = [ColumnId::Alias, ColumnId::Host].map(min_column_width);
+        assert_eq!(narrow, [minimums[0], minimums[1], 7]);
+
+        let columns = [ColumnId::Alias, ColumnId::Group];
+        let hosts = [host("web", &"g".repeat(40))];
+        let widths = compute_column_widths(&columns, &hosts, 200);
+        assert_eq!(widths[1], MAX_FIXED_COLUMN_WIDTH);
+    }
+
+    #[test]
+    fn check_results_after_the_first_frame_widen_the_rtt_column() {
+        use crate::test_support::app_with;
+        use ratatui::backend::TestBackend;
+        use ratatui::Terminal;
+
+        let mut app = app_with(vec![host("web", "a")]);
+        let mut terminal = Terminal::new(TestBackend::new(160, 20)).unwrap();
+        terminal.draw(|f| render(f, &mut app)).unwrap();
+
+        let up = HostStatus::Up { mean: 1234.0, stddev: 123.0, loss: 0.0 };
+        let checked = Host { status: up.clone(), ..host("web", "") };
+        let label = column_text(ColumnId::Rtt, &checked, false);
+        app.hosts.lock().unwrap()[0].record_status(up);
+        terminal.draw(|f| render(f, &mut app)).unwrap();
+
+        let (_, columns, _, widths) = app.last_computed_widths.clone().unwrap();
+        let rtt = columns.iter().position(|&c| c == ColumnId::Rtt).unwrap();
+        assert!(widths[rtt] as usize > label.chars().count(), "{:?} for {}", widths, label);
     }
 }
@@ -0,0 +1,133 @@
+use crate::config::Config;
+use crate::host::{self, Host};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// Editors often write a file in several steps; wait for them to settle.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// What sshmap last wrote to each watched file, so saving from the app
+/// doesn't come back as a reload.
+static OWN_WRITES: Mutex<BTreeMap<PathBuf, String>> = Mutex::new(BTreeMap::new());
+
+/// What the watcher loaded, for the UI thread to apply.
+pub enum Reload {
+    /// A freshly loaded host list, to be merged with the current one.
+    Hosts(Vec<Host>),
+    Config(Box<Config>),
+    Error(String),
+}
+
+/// Record that sshmap itself just wrote `content` to `path`.
+pub fn note_own_write(path: &Path, content: &str) {
+    OWN_WRITES.lock().unwrap().insert(normalize(path), content.to_string());
+}
+
+/// Whether `path` still holds exactly what sshmap last wrote there.
+fn is_own_write(path: &Path) -> bool {
+    let writes = OWN_WRITES.lock().unwrap();
+    match writes.get(path) {
+        Some(written) => fs::read_to_string(path).is_ok_and(|content| content == *written),
+        None => false,
+    }
+}
+
+/// `path` with its directory resolved, to compare with the paths in events.
+fn normalize(path: &Path) -> PathBuf {
+    let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    match (dir.canonicalize(), path.file_name()) {
+        (Ok(dir), Some(name)) => dir.join(name),
+        _ => std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
+
+/// Watch the host and settings files, wherever `--hosts` and `--config` put
+/// them, and load them again when someone else changes them.
+pub fn spawn() -> notify::Result<Receiver<Reload>> {
+    let config_path = normalize(&Config::path());
+    let hosts_paths: Vec<PathBuf> = host::hosts_paths().iter().map(|p| normalize(p)).collect();
+
+    let (event_tx, event_rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(event_tx)?;
+    let dirs: BTreeSet<&Path> =
+        hosts_paths.iter().chain([&config_path]).filter_map(|p| p.parent()).collect();
+    for dir in dirs {
+        fs::create_dir_all(dir).map_err(notify::Error::io)?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+    }
+
+    let (reload_tx, reload_rx) = mpsc::channel();
+    thread::spawn(move || {
+        // The watcher stops when dropped, so it lives as long as this thread
+        let _watcher = watcher;
+        let mut changed = BTreeSet::new();
+
+        while let Ok(event) = event_rx.recv() {
+            note_change(event, &mut changed);
+            while let Ok(event) = event_rx.recv_timeout(DEBOUNCE) {
+                note_change(event, &mut changed);
+            }
+            changed.retain(|path| !is_own_write(path));
+            let config_changed = changed.contains(&config_path);
+            let hosts_changed = hosts_paths.iter().any(|path| changed.contains(path));
+            changed.clear();
+
+            let mut results = Vec::new();
+            if config_changed {
+                results.push(match Config::load() {
+                    Ok(config) => Reload::Config(Box::new(config)),
+                    Err(e) => Reload::Error(format!("Config not reloaded: {:#}", e)),
+                });
+            }
+            if hosts_changed {
+                results.push(match host::check_sshmap_config() {
+                    Ok(()) => Reload::Hosts(host::load_hosts()),
+                    Err(e) => Reload::Error(format!("Hosts not reloaded: {:#}", e)),
+                });
+            }
+            for result in results {
+                if reload_tx.send(result).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok(reload_rx)
+}
+
+/// Collect the files an event created or modified.
+fn note_change(event: notify::Result<notify::Event>, changed: &mut BTreeSet<PathBuf>) {
+    let event = match event {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+        changed.extend(event.paths);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_an_unchanged_own_write_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = normalize(&dir.path().join("hosts.json"));
+        assert!(!is_own_write(&path));
+
+        fs::write(&path, "[]").unwrap();
+        note_own_write(&path, "[]");
+        assert!(is_own_write(&path));
+
+        fs::write(&path, "[{}]").unwrap();
+        assert!(!is_own_write(&path));
+    }
+}